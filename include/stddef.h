@@ -0,0 +1,13 @@
+#ifndef __STDDEF_H
+#define __STDDEF_H
+
+// minicc doesn't have typedef, so the standard types are macros for now.
+#define size_t unsigned long
+#define ptrdiff_t long
+#define wchar_t int
+
+#define NULL ((void *)0)
+
+#define offsetof(type, member) __builtin_offsetof(type, member)
+
+#endif
@@ -0,0 +1,79 @@
+#ifndef __STDINT_H
+#define __STDINT_H
+
+#include <limits.h>
+
+// minicc doesn't have typedef, so the standard types are macros for now.
+#define int8_t signed char
+#define int16_t short
+#define int32_t int
+#define int64_t long
+#define uint8_t unsigned char
+#define uint16_t unsigned short
+#define uint32_t unsigned int
+#define uint64_t unsigned long
+
+#define int_least8_t int8_t
+#define int_least16_t int16_t
+#define int_least32_t int32_t
+#define int_least64_t int64_t
+#define uint_least8_t uint8_t
+#define uint_least16_t uint16_t
+#define uint_least32_t uint32_t
+#define uint_least64_t uint64_t
+
+#define int_fast8_t int8_t
+#define int_fast16_t int16_t
+#define int_fast32_t int32_t
+#define int_fast64_t int64_t
+#define uint_fast8_t uint8_t
+#define uint_fast16_t uint16_t
+#define uint_fast32_t uint32_t
+#define uint_fast64_t uint64_t
+
+#define intptr_t long
+#define uintptr_t unsigned long
+#define intmax_t long
+#define uintmax_t unsigned long
+
+_Static_assert(sizeof(int8_t) == 1, "int8_t must be 8 bits wide");
+_Static_assert(sizeof(int16_t) == 2, "int16_t must be 16 bits wide");
+_Static_assert(sizeof(int32_t) == 4, "int32_t must be 32 bits wide");
+_Static_assert(sizeof(int64_t) == 8, "int64_t must be 64 bits wide");
+_Static_assert(sizeof(intptr_t) == sizeof(void *), "intptr_t must hold a pointer");
+
+#define INT8_MIN SCHAR_MIN
+#define INT8_MAX SCHAR_MAX
+#define UINT8_MAX UCHAR_MAX
+#define INT16_MIN SHRT_MIN
+#define INT16_MAX SHRT_MAX
+#define UINT16_MAX USHRT_MAX
+#define INT32_MIN INT_MIN
+#define INT32_MAX INT_MAX
+#define UINT32_MAX UINT_MAX
+#define INT64_MIN LONG_MIN
+#define INT64_MAX LONG_MAX
+#define UINT64_MAX ULONG_MAX
+
+#define INTPTR_MIN LONG_MIN
+#define INTPTR_MAX LONG_MAX
+#define UINTPTR_MAX ULONG_MAX
+#define INTMAX_MIN LONG_MIN
+#define INTMAX_MAX LONG_MAX
+#define UINTMAX_MAX ULONG_MAX
+#define PTRDIFF_MIN LONG_MIN
+#define PTRDIFF_MAX LONG_MAX
+#define SIZE_MAX ULONG_MAX
+
+#define INT8_C(value) value
+#define INT16_C(value) value
+#define INT32_C(value) value
+#define INT64_C(value) value ## L
+#define UINT8_C(value) value
+#define UINT16_C(value) value
+#define UINT32_C(value) value ## U
+#define UINT64_C(value) value ## UL
+#define INTMAX_C(value) value ## L
+#define UINTMAX_C(value) value ## UL
+
+#endif
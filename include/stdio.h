@@ -0,0 +1,69 @@
+#ifndef __STDIO_H
+#define __STDIO_H
+
+#include <stdarg.h>
+#include <stddef.h>
+
+// FILE is only ever used through pointers, so its definition doesn't matter.
+#define FILE struct __sFILE
+
+#define EOF (-1)
+#define BUFSIZ 1024
+
+#define SEEK_SET 0
+#define SEEK_CUR 1
+#define SEEK_END 2
+
+#ifdef __APPLE__
+extern FILE *__stdinp;
+extern FILE *__stdoutp;
+extern FILE *__stderrp;
+#define stdin __stdinp
+#define stdout __stdoutp
+#define stderr __stderrp
+#else
+extern FILE *stdin;
+extern FILE *stdout;
+extern FILE *stderr;
+#endif
+
+FILE *fopen(const char *path, const char *mode);
+int fclose(FILE *stream);
+int fflush(FILE *stream);
+
+int printf(const char *format, ...);
+int fprintf(FILE *stream, const char *format, ...);
+int sprintf(char *buffer, const char *format, ...);
+int snprintf(char *buffer, size_t size, const char *format, ...);
+int vprintf(const char *format, va_list args);
+int vfprintf(FILE *stream, const char *format, va_list args);
+int vsprintf(char *buffer, const char *format, va_list args);
+int vsnprintf(char *buffer, size_t size, const char *format, va_list args);
+int scanf(const char *format, ...);
+int sscanf(const char *buffer, const char *format, ...);
+
+int getchar(void);
+int fgetc(FILE *stream);
+int getc(FILE *stream);
+char *fgets(char *buffer, int size, FILE *stream);
+int ungetc(int c, FILE *stream);
+
+int putchar(int c);
+int fputc(int c, FILE *stream);
+int putc(int c, FILE *stream);
+int puts(const char *s);
+int fputs(const char *s, FILE *stream);
+
+size_t fread(void *buffer, size_t size, size_t count, FILE *stream);
+size_t fwrite(const void *buffer, size_t size, size_t count, FILE *stream);
+int fseek(FILE *stream, long offset, int whence);
+long ftell(FILE *stream);
+void rewind(FILE *stream);
+int feof(FILE *stream);
+int ferror(FILE *stream);
+
+int remove(const char *path);
+int rename(const char *from, const char *to);
+void perror(const char *s);
+
+#endif
@@ -0,0 +1,380 @@
+use diagnostics::{Diagnostics, Warning};
+use semantic::{Function, Stmt, StmtKind};
+use std::collections::HashMap;
+use types::Type;
+
+// The control-flow graph of a function. Each basic block holds statements
+// that run one after the other, the last of which may transfer control, and
+// the blocks that control can go to from it.
+pub struct Cfg<'a> {
+    pub blocks: Vec<BasicBlock<'a>>,
+    // The block that falls off the end of the function body.
+    pub end: usize,
+}
+
+// The entry of a function is its first block, and the exit of the function,
+// which every return and the end of the body lead to, is its second.
+pub const ENTRY: usize = 0;
+pub const EXIT: usize = 1;
+
+#[derive(Default)]
+pub struct BasicBlock<'a> {
+    // Statements other than blocks and labels, which only give the graph
+    // its shape. A switch statement comes last in its block, and stands for
+    // the evaluation of its controlling expression.
+    pub statements: Vec<&'a Stmt>,
+    pub successors: Vec<usize>,
+}
+
+impl<'a> Cfg<'a> {
+    pub fn new(function: &'a Function) -> Self {
+        let mut builder = Builder {
+            blocks: vec![BasicBlock::default(), BasicBlock::default()],
+            current: ENTRY,
+            labels: HashMap::new(),
+            gotos: Vec::new(),
+            break_targets: Vec::new(),
+            switches: Vec::new(),
+        };
+        builder.add_statements(&function.body);
+
+        let end = builder.current;
+        builder.add_edge(end, EXIT);
+        for (block, label) in builder.gotos {
+            let target = builder.labels[label];
+            builder.blocks[block].successors.push(target);
+        }
+
+        Cfg {
+            blocks: builder.blocks,
+            end,
+        }
+    }
+
+    // Whether each block can be reached from the entry of the function.
+    pub fn reachable(&self) -> Vec<bool> {
+        self.reachable_from(ENTRY, vec![false; self.blocks.len()])
+    }
+
+    // Mark the blocks that can be reached from a block, adding them to those
+    // already marked.
+    fn reachable_from(&self, start: usize, mut reached: Vec<bool>) -> Vec<bool> {
+        let mut worklist = vec![start];
+        while let Some(block) = worklist.pop() {
+            if reached[block] {
+                continue;
+            }
+            reached[block] = true;
+            worklist.extend(&self.blocks[block].successors);
+        }
+        reached
+    }
+}
+
+struct Builder<'a> {
+    blocks: Vec<BasicBlock<'a>>,
+    // The block that statements are being added to.
+    current: usize,
+    labels: HashMap<&'a str, usize>,
+    // The blocks ending in a goto, with its label, which may not have been
+    // seen yet.
+    gotos: Vec<(usize, &'a str)>,
+    // The blocks that follow the enclosing switch statements, which break
+    // statements go to.
+    break_targets: Vec<usize>,
+    // The blocks that evaluate the controlling expressions of the enclosing
+    // switch statements, which go to their case labels.
+    switches: Vec<usize>,
+}
+
+impl<'a> Builder<'a> {
+    fn add_statements(&mut self, statements: &'a [Stmt]) {
+        for statement in statements {
+            self.add_statement(statement);
+        }
+    }
+
+    fn add_statement(&mut self, statement: &'a Stmt) {
+        match statement.kind {
+            StmtKind::Block(ref statements) => self.add_statements(statements),
+            StmtKind::Declaration(..) | StmtKind::Expression(_) => self.push(statement),
+            StmtKind::Return(_) => {
+                self.push(statement);
+                let block = self.current;
+                self.add_edge(block, EXIT);
+                self.current = self.new_block();
+            }
+            StmtKind::Switch(_, ref cases, ref body) => {
+                self.push(statement);
+                let switch = self.current;
+                let after = self.new_block();
+
+                // The body can only be entered through its case labels.
+                self.current = self.new_block();
+                self.break_targets.push(after);
+                self.switches.push(switch);
+                self.add_statement(body);
+                self.switches.pop();
+                self.break_targets.pop();
+
+                let end = self.current;
+                self.add_edge(end, after);
+                if !cases.contains(&None) {
+                    self.add_edge(switch, after);
+                }
+                self.current = after;
+            }
+            StmtKind::Case(_, ref statement) => {
+                let switch = *self
+                    .switches
+                    .last()
+                    .expect("case label outside of a switch");
+                let block = self.start_block();
+                self.add_edge(switch, block);
+                self.add_statement(statement);
+            }
+            StmtKind::Break => {
+                self.push(statement);
+                let target = *self
+                    .break_targets
+                    .last()
+                    .expect("break outside of a switch");
+                let block = self.current;
+                self.add_edge(block, target);
+                self.current = self.new_block();
+            }
+            StmtKind::Label(ref label, ref statement) => {
+                let block = self.start_block();
+                self.labels.insert(label, block);
+                self.add_statement(statement);
+            }
+            StmtKind::Goto(ref label) => {
+                self.push(statement);
+                self.gotos.push((self.current, label));
+                self.current = self.new_block();
+            }
+        }
+    }
+
+    fn push(&mut self, statement: &'a Stmt) {
+        self.blocks[self.current].statements.push(statement);
+    }
+
+    fn new_block(&mut self) -> usize {
+        self.blocks.push(BasicBlock::default());
+        self.blocks.len() - 1
+    }
+
+    // Start a new block that the current one falls through to, for a
+    // statement that control can also jump to.
+    fn start_block(&mut self) -> usize {
+        let block = self.new_block();
+        let previous = self.current;
+        self.add_edge(previous, block);
+        self.current = block;
+        block
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize) {
+        self.blocks[from].successors.push(to);
+    }
+}
+
+// Warn about code that can never run, and about control reaching the end of
+// a function that should return a value.
+pub fn check(function: &Function, cfg: &Cfg, diagnostics: &mut Diagnostics) {
+    let reachable = cfg.reachable();
+
+    // Each stretch of unreachable code is only reported once, at its start:
+    // the blocks it leads to are taken as covered by the same warning.
+    let mut covered = reachable.clone();
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        let code = match block.statements.iter().find(|statement| is_code(statement)) {
+            Some(statement) if !covered[i] => statement,
+            _ => continue,
+        };
+
+        diagnostics.warn(
+            Warning::UnreachableCode,
+            code.span,
+            format!(
+                "code in function '{}' will never be executed",
+                function.name
+            ),
+        );
+        covered = cfg.reachable_from(i, covered);
+    }
+
+    if !reachable[cfg.end] || *function.ty.return_type.unqualified() == Type::Void {
+        return;
+    }
+
+    let message = if &*function.name == "main" {
+        "control reaches the end of 'main', which returns 0".to_owned()
+    } else {
+        let returns = cfg
+            .blocks
+            .iter()
+            .zip(&reachable)
+            .any(|(block, &reachable)| {
+                reachable
+                    && block
+                        .statements
+                        .iter()
+                        .any(|statement| matches!(statement.kind, StmtKind::Return(_)))
+            });
+        format!(
+            "non-void function '{}' does not return a value{}",
+            function.name,
+            if returns { " in all control paths" } else { "" }
+        )
+    };
+    diagnostics.warn(Warning::ReturnType, function.end, message);
+}
+
+// Whether a statement does anything at run time. A break that can't be
+// reached is left alone, as it's often written after a return in a switch
+// statement, and so is a declaration without an initializer.
+fn is_code(statement: &Stmt) -> bool {
+    !matches!(
+        statement.kind,
+        StmtKind::Break | StmtKind::Declaration(_, _, None)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use diagnostics::{Diagnostics, Options, Warning};
+    use lexer::Lexer;
+    use parser::Parser;
+    use preprocessor::Preprocessor;
+    use semantic;
+    use std::path::Path;
+
+    // The warnings from this pass about a translation unit, with the lines
+    // they're on.
+    fn warnings(source: &str) -> Vec<(usize, String)> {
+        let output = Preprocessor::new(Vec::new())
+            .preprocess(Path::new("test.c"), source)
+            .expect("preprocessing failed");
+        let mut options = Options::default();
+        options.apply("-Wunreachable-code");
+        let mut diagnostics = Diagnostics::new(options, output.origins);
+        let ast = Parser::new(Lexer::new(&output.text), &mut diagnostics)
+            .parse()
+            .expect("parsing failed");
+        semantic::analyze(&ast, &mut diagnostics).expect("analysis failed");
+        diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                matches!(
+                    diagnostic.warning,
+                    Some(Warning::UnreachableCode | Warning::ReturnType)
+                )
+            })
+            .map(|diagnostic| {
+                let line = diagnostic
+                    .location
+                    .as_ref()
+                    .map_or(0, |location| location.line);
+                (line, diagnostic.message.clone())
+            })
+            .collect()
+    }
+
+    fn unreachable(line: usize) -> (usize, String) {
+        (
+            line,
+            "code in function 'f' will never be executed".to_owned(),
+        )
+    }
+
+    #[test]
+    fn code_after_return() {
+        let source = "int f(void) {\n    return 1;\n    return 2;\n}\n";
+        assert_eq!(warnings(source), [unreachable(3)]);
+    }
+
+    #[test]
+    fn unreachable_stretch_is_reported_once() {
+        let source = "int f(int a) {
+    return 1;
+    a = 2;
+    a = 3;
+label:
+    return a;
+}
+";
+        assert_eq!(warnings(source), [unreachable(3)]);
+    }
+
+    #[test]
+    fn break_after_return_is_allowed() {
+        let source = "int f(int a) {
+    switch (a) {
+    case 1:
+        return 1;
+        break;
+    }
+    return 0;
+}
+";
+        assert_eq!(warnings(source), []);
+    }
+
+    #[test]
+    fn code_reached_by_goto() {
+        let source = "int f(void) {
+    goto skip;
+    return 1;
+skip:
+    return 2;
+}
+";
+        assert_eq!(warnings(source), [unreachable(3)]);
+    }
+
+    #[test]
+    fn missing_return() {
+        let source = "int f(void) {\n}\n";
+        let message = "non-void function 'f' does not return a value";
+        assert_eq!(warnings(source), [(2, message.to_owned())]);
+    }
+
+    #[test]
+    fn missing_return_on_some_paths() {
+        let source = "int f(int a) {
+    switch (a) {
+    case 1:
+        return 1;
+    }
+}
+";
+        let message = "non-void function 'f' does not return a value in all control paths";
+        assert_eq!(warnings(source), [(6, message.to_owned())]);
+    }
+
+    #[test]
+    fn switch_with_default_returning_everywhere() {
+        let source = "int f(int a) {
+    switch (a) {
+    case 1:
+        return 1;
+    default:
+        return 2;
+    }
+}
+";
+        assert_eq!(warnings(source), []);
+    }
+
+    #[test]
+    fn infinite_loop_never_reaches_the_end() {
+        assert_eq!(warnings("int f(void) {\nloop:\n    goto loop;\n}\n"), []);
+    }
+
+    #[test]
+    fn void_function_can_fall_off_the_end() {
+        assert_eq!(warnings("void f(void) {\n}\n"), []);
+    }
+}
@@ -1,122 +1,1626 @@
-use parser::{BinaryOperator, UnaryOperator, AST};
+use parser::{BinaryOperator, UnaryOperator};
+use semantic::{
+    Data, Expr, ExprKind, Function, Initializer, Object, Program, StaticInitializer, Stmt,
+    StmtKind, Storage,
+};
+use std::collections::{BTreeMap, HashMap};
+use types::Type;
 
-pub fn generate(ast: AST) -> Vec<String> {
-    generate_program(&ast)
+// Switches with at least this many cases are candidates for a jump table.
+const JUMP_TABLE_MIN_CASES: usize = 4;
+
+// A jump table may have at most this many slots per case, so that sparse
+// switches fall back to a chain of comparisons instead.
+const JUMP_TABLE_MAX_SLOTS_PER_CASE: i64 = 3;
+
+// The registers in which the System V calling convention passes integer
+// arguments, by their 64-, 32-, 16- and 8-bit names.
+const ARGUMENT_REGISTERS: [[&str; 4]; 6] = [
+    ["%rdi", "%edi", "%di", "%dil"],
+    ["%rsi", "%esi", "%si", "%sil"],
+    ["%rdx", "%edx", "%dx", "%dl"],
+    ["%rcx", "%ecx", "%cx", "%cl"],
+    ["%r8", "%r8d", "%r8w", "%r8b"],
+    ["%r9", "%r9d", "%r9w", "%r9b"],
+];
+
+// Floating arguments are passed in %xmm0 to %xmm7.
+const SSE_ARGUMENT_REGISTERS: usize = 8;
+
+// A variadic function saves the registers that may hold its arguments in a
+// register save area: first each of the integer argument registers, then
+// each of the SSE argument registers, which take up 16 bytes apiece.
+const REGISTER_SAVE_AREA_SSE_OFFSET: i64 = 6 * 8;
+const REGISTER_SAVE_AREA_SIZE: i64 = REGISTER_SAVE_AREA_SSE_OFFSET + 8 * 16;
+
+pub fn generate(program: &Program) -> Vec<String> {
+    Generator::new().generate_program(program)
 }
 
-fn generate_program(ast: &AST) -> Vec<String> {
-    match ast {
-        &AST::Program(ref func) => generate_function(&func),
-        _ => Vec::new(),
-    }
+struct Generator {
+    label_count: usize,
+    // The string literals used in the program, and the labels of their data.
+    strings: Vec<(String, Box<[u8]>)>,
+    // The name of the function being generated, which scopes its labels.
+    function_name: String,
+    // The offset from the frame pointer of each of the function's automatic
+    // variables, by their index.
+    locals: HashMap<usize, i64>,
+    // The number of bytes allocated to local variables in the current stack
+    // frame.
+    frame_size: i64,
+    // The number of bytes pushed onto the stack below the local variables,
+    // which is needed to keep the stack aligned when calling a function.
+    stack_depth: i64,
+    // Where a variadic function's variable arguments can be found.
+    variadic_frame: Option<VariadicFrame>,
+    // The label that a `break` jumps to, innermost switch last.
+    break_labels: Vec<String>,
+    // The labels of each enclosing switch's `case` and `default` statements,
+    // in the order in which they appear in the switch body.
+    case_labels: Vec<Vec<String>>,
 }
 
-fn generate_function(func: &AST) -> Vec<String> {
-    match func {
-        &AST::Function(ref name, ref body) => {
-            let label = format!("_{}", name);
-            let mut lines = vec![indent(&format!(".globl {}", label)), format!("{}:", label)];
-            lines.append(&mut generate_statement(&body));
-            lines
+// The state that va_start initializes a va_list with: how much of the
+// register save area is taken up by named arguments, and where on the stack
+// the arguments that didn't fit in registers start.
+struct VariadicFrame {
+    register_save_area: i64,
+    gp_offset: i64,
+    fp_offset: i64,
+    overflow_arg_area: i64,
+}
+
+#[derive(Clone, Copy)]
+enum ArgumentLocation {
+    Register(usize),
+    SseRegister(usize),
+    // The index of an eightbyte on the stack.
+    Stack(usize),
+}
+
+struct ArgumentLayout {
+    locations: Vec<ArgumentLocation>,
+    sse_registers: usize,
+    registers: usize,
+    stack_slots: usize,
+}
+
+impl Generator {
+    fn new() -> Self {
+        Generator {
+            label_count: 0,
+            strings: Vec::new(),
+            function_name: String::new(),
+            locals: HashMap::new(),
+            frame_size: 0,
+            stack_depth: 0,
+            variadic_frame: None,
+            break_labels: Vec::new(),
+            case_labels: Vec::new(),
         }
-        _ => Vec::new(),
     }
-}
 
-fn generate_statement(stmt: &AST) -> Vec<String> {
-    match stmt {
-        &AST::Return(ref expr) => {
-            let mut lines = generate_expr(&expr);
-            lines.push(indent("ret"));
-            lines
+    fn generate_program(&mut self, program: &Program) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for function in &program.functions {
+            lines.append(&mut self.generate_function(function));
         }
-        _ => Vec::new(),
+
+        for object in &program.objects {
+            lines.append(&mut self.generate_object(object));
+        }
+
+        if !self.strings.is_empty() {
+            lines.push(indent(rodata_section()));
+            for (label, bytes) in &self.strings {
+                lines.push(format!("{}:", label));
+                lines.push(indent(&format!(".asciz \"{}\"", escape_string(bytes))));
+            }
+        }
+
+        lines
+    }
+
+    fn generate_function(&mut self, function: &Function) -> Vec<String> {
+        self.function_name = function.name.to_string();
+        self.locals = HashMap::new();
+        self.frame_size = 0;
+        self.stack_depth = 0;
+
+        let label = symbol(&function.name);
+        let mut lines = Vec::new();
+        if function.global {
+            lines.push(indent(&format!(".globl {}", label)));
+        }
+        lines.append(&mut vec![
+            format!("{}:", label),
+            indent("pushq %rbp"),
+            indent("movq %rsp, %rbp"),
+        ]);
+        let prologue_len = lines.len();
+
+        lines.append(&mut self.generate_parameters(function));
+        lines.append(&mut self.generate_statements(&function.body));
+        self.variadic_frame = None;
+
+        // Now that we know how much space the local variables take up,
+        // allocate it, keeping the stack 16-byte aligned.
+        let frame_size = align_to(self.frame_size, 16);
+        if frame_size > 0 {
+            lines.insert(prologue_len, indent(&format!("subq ${}, %rsp", frame_size)));
+        }
+
+        // Reaching the end of main returns 0.
+        if &*function.name == "main" && *function.ty.return_type.unqualified() == Type::Int {
+            lines.push(indent("movl $0, %eax"));
+        }
+        lines.append(&mut generate_epilogue());
+        lines
+    }
+
+    // Store the parameters that were passed in registers in the stack frame,
+    // where they become the function's first automatic variables.
+    fn generate_parameters(&mut self, function: &Function) -> Vec<String> {
+        let param_types = function.ty.params.clone().unwrap_or_default();
+        let layout = layout_arguments(&param_types);
+        let mut lines = Vec::new();
+
+        if function.ty.variadic {
+            lines.append(&mut self.generate_register_save_area(&layout));
+        }
+
+        for (i, ty) in param_types.iter().enumerate() {
+            let offset = match layout.locations[i] {
+                ArgumentLocation::Register(register) => {
+                    let offset = self.allocate(ty);
+                    let source = ARGUMENT_REGISTERS[register][width_index(ty)];
+                    lines.push(indent(&format!(
+                        "mov{} {}, {}(%rbp)",
+                        size_suffix(ty),
+                        source,
+                        offset
+                    )));
+                    offset
+                }
+                ArgumentLocation::SseRegister(register) => {
+                    let offset = self.allocate(ty);
+                    lines.push(indent(&format!(
+                        "movs{} %xmm{}, {}(%rbp)",
+                        sse_suffix(ty),
+                        register,
+                        offset
+                    )));
+                    offset
+                }
+                // Arguments passed on the stack sit above the return address
+                // and the saved frame pointer, where they can stay.
+                ArgumentLocation::Stack(slot) => 16 + 8 * slot as i64,
+            };
+
+            self.locals.insert(i, offset);
+        }
+
+        lines
+    }
+
+    fn generate_register_save_area(&mut self, layout: &ArgumentLayout) -> Vec<String> {
+        self.frame_size += REGISTER_SAVE_AREA_SIZE;
+        let area = -self.frame_size;
+
+        let mut lines = Vec::new();
+        for (i, registers) in ARGUMENT_REGISTERS.iter().enumerate() {
+            lines.push(indent(&format!(
+                "movq {}, {}(%rbp)",
+                registers[0],
+                area + 8 * i as i64
+            )));
+        }
+
+        // The caller sets %al to the number of SSE registers that it used,
+        // so they only need saving if it isn't zero.
+        let skip_label = self.new_label();
+        lines.push(indent("testb %al, %al"));
+        lines.push(indent(&format!("je {}", skip_label)));
+        for i in 0..SSE_ARGUMENT_REGISTERS {
+            let offset = area + REGISTER_SAVE_AREA_SSE_OFFSET + 16 * i as i64;
+            lines.push(indent(&format!("movsd %xmm{}, {}(%rbp)", i, offset)));
+        }
+        lines.push(format!("{}:", skip_label));
+
+        self.variadic_frame = Some(VariadicFrame {
+            register_save_area: area,
+            gp_offset: 8 * layout.registers as i64,
+            fp_offset: REGISTER_SAVE_AREA_SSE_OFFSET + 16 * layout.sse_registers as i64,
+            overflow_arg_area: 16 + 8 * layout.stack_slots as i64,
+        });
+
+        lines
+    }
+
+    // Reserve space in the stack frame for a variable of the given type, and
+    // return its offset from the frame pointer.
+    fn allocate(&mut self, ty: &Type) -> i64 {
+        let size = ty.size().unwrap_or(0) as i64;
+        let align = ty.align().unwrap_or(1) as i64;
+        self.frame_size = align_to(self.frame_size + size, align);
+        -self.frame_size
+    }
+
+    // The memory operand that refers to a variable.
+    fn address(&self, storage: &Storage) -> String {
+        match *storage {
+            Storage::Local(index) => format!("{}(%rbp)", self.locals[&index]),
+            Storage::Static(ref name) => format!("{}(%rip)", symbol(name)),
+        }
+    }
+
+    fn generate_statements(&mut self, statements: &[Stmt]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for statement in statements {
+            lines.append(&mut self.generate_statement(statement));
+        }
+        lines
+    }
+
+    fn generate_statement(&mut self, stmt: &Stmt) -> Vec<String> {
+        match stmt.kind {
+            StmtKind::Block(ref statements) => self.generate_statements(statements),
+            StmtKind::Declaration(index, ref ty, ref initializers) => {
+                self.generate_declaration(index, ty, initializers.as_deref())
+            }
+            StmtKind::Expression(ref expr) => self.generate_expr(expr),
+            StmtKind::Return(ref expr) => {
+                let mut lines = match *expr {
+                    Some(ref expr) => self.generate_expr(expr),
+                    None => Vec::new(),
+                };
+                lines.append(&mut generate_epilogue());
+                lines
+            }
+            StmtKind::Switch(ref expr, ref cases, ref body) => {
+                self.generate_switch(expr, cases, body)
+            }
+            StmtKind::Case(index, ref stmt) => {
+                let labels = self
+                    .case_labels
+                    .last()
+                    .expect("case label outside of a switch");
+                let mut lines = vec![format!("{}:", labels[index])];
+                lines.append(&mut self.generate_statement(stmt));
+                lines
+            }
+            StmtKind::Break => {
+                let label = self.break_labels.last().expect("break outside of a switch");
+                vec![indent(&format!("jmp {}", label))]
+            }
+            StmtKind::Label(ref name, ref stmt) => {
+                let mut lines = vec![format!("{}:", self.user_label(name))];
+                lines.append(&mut self.generate_statement(stmt));
+                lines
+            }
+            StmtKind::Goto(ref name) => vec![indent(&format!("jmp {}", self.user_label(name)))],
+        }
+    }
+
+    fn generate_declaration(
+        &mut self,
+        index: usize,
+        ty: &Type,
+        initializers: Option<&[Initializer]>,
+    ) -> Vec<String> {
+        let offset = self.allocate(ty);
+        self.locals.insert(index, offset);
+
+        let initializers = match initializers {
+            Some(initializers) => initializers,
+            None => return Vec::new(),
+        };
+
+        // Whatever an initializer list or a string literal leaves out of an
+        // aggregate is zero, so start by zeroing all of it, unless a single
+        // value initializes the whole object.
+        let mut lines = Vec::new();
+        let whole = match *initializers {
+            [ref initializer] => initializer.ty == *ty && !ty.is_array(),
+            _ => false,
+        };
+        if !whole {
+            lines.append(&mut generate_zero(offset, ty.size().unwrap_or(0)));
+        }
+
+        for initializer in initializers {
+            let address = offset + initializer.offset as i64;
+            lines.append(&mut self.generate_initializer_store(initializer, address));
+        }
+
+        lines
+    }
+
+    // Store part of an initializer in a local variable, given its address
+    // relative to the frame pointer.
+    fn generate_initializer_store(
+        &mut self,
+        initializer: &Initializer,
+        address: i64,
+    ) -> Vec<String> {
+        let ty = &initializer.ty;
+        let mut lines = Vec::new();
+
+        match initializer.value.kind {
+            // The rest of the array has already been zeroed, including the
+            // terminating null character.
+            ExprKind::StringLiteral(ref bytes) if ty.is_array() => {
+                let length = ty.size().unwrap_or(0) as usize;
+                let bytes = &bytes[..bytes.len().min(length)];
+                for (i, chunk) in bytes.chunks(4).enumerate() {
+                    let chunk_address = address + 4 * i as i64;
+                    if chunk.len() == 4 {
+                        let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                        lines.push(indent(&format!("movl ${}, {}(%rbp)", value, chunk_address)));
+                    } else {
+                        for (j, byte) in chunk.iter().enumerate() {
+                            lines.push(indent(&format!(
+                                "movb ${}, {}(%rbp)",
+                                byte,
+                                chunk_address + j as i64
+                            )));
+                        }
+                    }
+                }
+            }
+            _ => {
+                lines.append(&mut self.generate_expr(&initializer.value));
+
+                if ty.is_struct() {
+                    lines.push(indent(&format!("leaq {}(%rbp), %rcx", address)));
+                    lines.append(&mut generate_copy(ty.size().unwrap_or(0)));
+                } else {
+                    lines.append(&mut store(ty, &format!("{}(%rbp)", address)));
+                }
+            }
+        }
+
+        lines
+    }
+
+    fn generate_object(&mut self, object: &Object) -> Vec<String> {
+        let data = object
+            .data
+            .as_ref()
+            .map(|initializers| self.generate_static_data(&object.ty, initializers));
+        generate_data(&symbol(&object.name), &object.ty, object.global, data)
+    }
+
+    // The data directives for an object with static storage duration, given
+    // the parts of its initializer. Anything not initialized is zero.
+    fn generate_static_data(
+        &mut self,
+        ty: &Type,
+        initializers: &[StaticInitializer],
+    ) -> Vec<String> {
+        // A later initializer for the same subobject overrides an earlier one.
+        let mut by_offset = BTreeMap::new();
+        for initializer in initializers {
+            by_offset.insert(initializer.offset, initializer);
+        }
+
+        let mut lines = Vec::new();
+        let mut position = 0;
+        for (offset, initializer) in by_offset {
+            if offset < position {
+                continue;
+            }
+            if offset > position {
+                lines.push(indent(&format!(".zero {}", offset - position)));
+            }
+
+            let size = initializer.ty.size().unwrap_or(0);
+            match initializer.value {
+                Data::String(ref bytes) => {
+                    let bytes = &bytes[..bytes.len().min(size as usize)];
+                    lines.push(indent(&format!(".ascii \"{}\"", escape_string(bytes))));
+                    if size > bytes.len() as u64 {
+                        lines.push(indent(&format!(".zero {}", size - bytes.len() as u64)));
+                    }
+                }
+                Data::Integer(value) => {
+                    lines.push(indent(&format!(
+                        "{} {}",
+                        data_directive(&initializer.ty),
+                        value
+                    )));
+                }
+                Data::Address(ref name, offset) => {
+                    let address = offset_label(&symbol(name), offset);
+                    lines.push(indent(&format!(".quad {}", address)));
+                }
+                Data::StringAddress(ref bytes, offset) => {
+                    let label = self.new_label();
+                    self.strings.push((label.clone(), bytes.clone()));
+                    lines.push(indent(&format!(".quad {}", offset_label(&label, offset))));
+                }
+            }
+            position = offset + size;
+        }
+
+        let size = ty.size().unwrap_or(0);
+        if size > position {
+            lines.push(indent(&format!(".zero {}", size - position)));
+        }
+
+        lines
+    }
+
+    fn generate_switch(&mut self, expr: &Expr, cases: &[Option<i64>], body: &Stmt) -> Vec<String> {
+        let switch_type = &expr.ty;
+
+        let mut labels = Vec::new();
+        let mut values = Vec::new();
+        let mut default_label = None;
+        for case in cases {
+            let label = self.new_label();
+            labels.push(label.clone());
+
+            match *case {
+                Some(value) => values.push((value, label)),
+                None => default_label = Some(label),
+            }
+        }
+
+        let end_label = self.new_label();
+        let fallback_label = default_label.unwrap_or_else(|| end_label.clone());
+
+        let mut lines = self.generate_expr(expr);
+
+        if use_jump_table(&values) {
+            let table_label = self.new_label();
+            lines.append(&mut generate_jump_table(
+                &values,
+                switch_type,
+                &fallback_label,
+                &table_label,
+            ));
+        } else {
+            let suffix = suffix(switch_type);
+            let ax = reg("ax", switch_type);
+            for &(value, ref label) in &values {
+                if fits_in_immediate(value) {
+                    lines.push(indent(&format!("cmp{} ${}, {}", suffix, value, ax)));
+                } else {
+                    // cmpq can only take a sign-extended 32-bit immediate.
+                    lines.push(indent(&format!("movabsq ${}, %rcx", value)));
+                    lines.push(indent("cmpq %rcx, %rax"));
+                }
+                lines.push(indent(&format!("je {}", label)));
+            }
+            lines.push(indent(&format!("jmp {}", fallback_label)));
+        }
+
+        self.break_labels.push(end_label.clone());
+        self.case_labels.push(labels);
+        lines.append(&mut self.generate_statement(body));
+        self.case_labels.pop();
+        self.break_labels.pop();
+
+        lines.push(format!("{}:", end_label));
+        lines
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!(".L{}", self.label_count)
+    }
+
+    // The assembly label for a label declared in the source. Qualifying it
+    // with the function name keeps it apart from labels of the same name in
+    // other functions, and since identifiers can't start with a digit it
+    // can't clash with the numbered labels from new_label either.
+    fn user_label(&self, name: &str) -> String {
+        format!(".L{}.{}", self.function_name, name)
     }
 }
 
-fn generate_expr(expr: &AST) -> Vec<String> {
-    match expr {
-        &AST::IntConstant(n) => vec![indent(&format!("movl ${}, %eax", n))],
-        &AST::UnaryOp(ref operator, ref operand) => {
-            let mut lines = generate_expr(operand);
-            lines.append(&mut generate_unary_op(operator));
-            lines
+fn use_jump_table(cases: &[(i64, String)]) -> bool {
+    if cases.len() < JUMP_TABLE_MIN_CASES {
+        return false;
+    }
+
+    let (min, max) = case_range(cases);
+    if !fits_in_immediate(min) {
+        return false;
+    }
+
+    // Work in 128 bits, as the range of 64-bit case values can overflow.
+    let slots = max as i128 - min as i128 + 1;
+    slots <= cases.len() as i128 * JUMP_TABLE_MAX_SLOTS_PER_CASE as i128
+}
+
+fn case_range(cases: &[(i64, String)]) -> (i64, i64) {
+    let min = cases.iter().map(|&(value, _)| value).min().unwrap_or(0);
+    let max = cases.iter().map(|&(value, _)| value).max().unwrap_or(0);
+    (min, max)
+}
+
+// Dispatch on the value in %rax through a table of 32-bit offsets relative to
+// the start of the table, which keeps the table position-independent.
+fn generate_jump_table(
+    cases: &[(i64, String)],
+    ty: &Type,
+    fallback: &str,
+    table: &str,
+) -> Vec<String> {
+    let (min, max) = case_range(cases);
+    let last_slot = max - min;
+    let suffix = suffix(ty);
+    let ax = reg("ax", ty);
+
+    let mut lines = Vec::new();
+    if min != 0 {
+        lines.push(indent(&format!("sub{} ${}, {}", suffix, min, ax)));
+    }
+    lines.append(&mut vec![
+        // Values outside of the table's range, including those below the
+        // minimum which wrap around to large unsigned numbers, go to default.
+        // A 32-bit subl also zeroes the upper half of %rax for the lookup.
+        indent(&format!("cmp{} ${}, {}", suffix, last_slot, ax)),
+        indent(&format!("ja {}", fallback)),
+        indent(&format!("leaq {}(%rip), %rcx", table)),
+        indent("movslq (%rcx,%rax,4), %rax"),
+        indent("addq %rcx, %rax"),
+        indent("jmp *%rax"),
+        indent(rodata_section()),
+        indent(".p2align 2"),
+        format!("{}:", table),
+    ]);
+
+    for slot in 0..=last_slot {
+        let target = cases
+            .iter()
+            .find(|&&(value, _)| value == min + slot)
+            .map(|(_, label)| label.as_str())
+            .unwrap_or(fallback);
+        lines.push(indent(&format!(".long {} - {}", target, table)));
+    }
+
+    lines.push(indent(".text"));
+    lines
+}
+
+fn rodata_section() -> &'static str {
+    if cfg!(target_os = "macos") {
+        ".const"
+    } else {
+        ".section .rodata"
+    }
+}
+
+impl Generator {
+    // Generate code that evaluates an expression, leaving its value in %rax
+    // or, for floating types, in %xmm0.
+    fn generate_expr(&mut self, expr: &Expr) -> Vec<String> {
+        match expr.kind {
+            ExprKind::IntConstant(n) => vec![load_constant(&expr.ty, n)],
+            ExprKind::FloatConstant(n) => load_floating_constant(&expr.ty, n, "%xmm0"),
+            // Evaluating an lvalue on its own, such as a dereferenced pointer
+            // to void, only evaluates its operands.
+            ExprKind::StringLiteral(_)
+            | ExprKind::Object(_)
+            | ExprKind::Function(_)
+            | ExprKind::Dereference(_)
+            | ExprKind::Member(..) => self.generate_address(expr),
+            ExprKind::Load(ref object) => match object.kind {
+                ExprKind::Object(ref storage) if !object.ty.is_struct() => {
+                    load(&object.ty, &self.address(storage))
+                }
+                _ => {
+                    // A structure is represented by its address.
+                    let mut lines = self.generate_address(object);
+                    if !object.ty.is_struct() {
+                        lines.append(&mut load(&object.ty, "(%rax)"));
+                    }
+                    lines
+                }
+            },
+            ExprKind::Decay(ref object) | ExprKind::AddressOf(ref object) => {
+                self.generate_address(object)
+            }
+            ExprKind::Conversion(ref operand) => {
+                let mut lines = self.generate_expr(operand);
+                lines.append(&mut self.generate_conversion(&operand.ty, &expr.ty));
+                lines
+            }
+            ExprKind::UnaryOp(operator, ref operand) => {
+                let mut lines = self.generate_expr(operand);
+                lines.append(&mut generate_unary_op(&operator, &operand.ty));
+                lines
+            }
+            ExprKind::BinaryOp(operator, ref expr1, ref expr2) => match operator {
+                BinaryOperator::And | BinaryOperator::Or => {
+                    self.generate_logical_op(operator, expr1, expr2)
+                }
+                _ => {
+                    let operand_type = &expr1.ty;
+
+                    let mut lines = self.generate_expr(expr1);
+                    lines.append(&mut self.push_value(operand_type));
+                    lines.append(&mut self.generate_expr(expr2));
+                    lines.append(&mut self.pop_operands(operand_type));
+                    lines.append(&mut generate_binary_op(&operator, operand_type));
+                    lines
+                }
+            },
+            ExprKind::PointerArithmetic(operator, ref pointer, ref index) => {
+                self.generate_pointer_arithmetic(operator, pointer, index)
+            }
+            ExprKind::PointerDifference(ref expr1, ref expr2) => {
+                self.generate_pointer_difference(expr1, expr2)
+            }
+            ExprKind::Assign(ref target, ref value) => self.generate_assignment(target, value),
+            ExprKind::Call(ref callee, ref args) => self.generate_call(callee, args, &expr.ty),
+            ExprKind::VaStart(ref ap) => self.generate_va_start(ap),
+            ExprKind::VaArg(ref ap) => self.generate_va_arg(ap, &expr.ty),
+            // There is nothing to clean up beyond evaluating the argument.
+            ExprKind::VaEnd(ref ap) => self.generate_expr(ap),
+            ExprKind::VaCopy(ref dest, ref src) => {
+                let pointer = Type::Pointer(Box::new(Type::VaList));
+                let mut lines = self.generate_expr(src);
+                lines.append(&mut self.push_value(&pointer));
+                lines.append(&mut self.generate_expr(dest));
+                lines.append(&mut self.pop_operands(&pointer));
+                for offset in &[0, 8, 16] {
+                    lines.push(indent(&format!("movq {}(%rcx), %rdx", offset)));
+                    lines.push(indent(&format!("movq %rdx, {}(%rax)", offset)));
+                }
+                lines
+            }
         }
-        &AST::BinaryOp(ref operator, ref expr1, ref expr2) => {
-            let mut lines = generate_expr(expr1);
-            lines.push(indent("pushq %rax"));
-            lines.append(&mut generate_expr(expr2));
-            lines.push(indent("popq %rcx"));
-            lines.append(&mut generate_binary_op(operator));
-            lines
+    }
+
+    // Generate code that leaves the address of the object or function that
+    // an lvalue designates in %rax.
+    fn generate_address(&mut self, expr: &Expr) -> Vec<String> {
+        match expr.kind {
+            ExprKind::Object(ref storage) => {
+                vec![indent(&format!("leaq {}, %rax", self.address(storage)))]
+            }
+            ExprKind::Function(ref name) => {
+                vec![indent(&format!("leaq {}(%rip), %rax", symbol(name)))]
+            }
+            ExprKind::StringLiteral(ref bytes) => {
+                let label = self.new_label();
+                self.strings.push((label.clone(), bytes.clone()));
+                vec![indent(&format!("leaq {}(%rip), %rax", label))]
+            }
+            ExprKind::Dereference(ref pointer) => self.generate_expr(pointer),
+            ExprKind::Member(ref object, offset) => {
+                let mut lines = self.generate_address(object);
+                if offset != 0 {
+                    lines.push(indent(&format!("addq ${}, %rax", offset)));
+                }
+                lines
+            }
+            // A structure that isn't an lvalue, such as the value of an
+            // assignment, is already represented by its address.
+            _ => self.generate_expr(expr),
+        }
+    }
+
+    fn generate_assignment(&mut self, target: &Expr, value: &Expr) -> Vec<String> {
+        let ty = &target.ty;
+
+        // A scalar variable can be stored to directly, but anything else has
+        // to have its address worked out first and kept on the stack.
+        let address = match target.kind {
+            ExprKind::Object(ref storage) if !ty.is_struct() => Some(self.address(storage)),
+            _ => None,
+        };
+
+        let mut lines = Vec::new();
+        if address.is_none() {
+            lines.append(&mut self.generate_address(target));
+            lines.append(&mut self.push_value(&Type::Pointer(Box::new(ty.clone()))));
+        }
+
+        // The value of an assignment is the new value of its lhs.
+        lines.append(&mut self.generate_expr(value));
+
+        match address {
+            Some(address) => lines.append(&mut store(ty, &address)),
+            None if ty.is_struct() => {
+                lines.push(indent("popq %rcx"));
+                self.stack_depth -= 8;
+                lines.append(&mut generate_copy(ty.size().unwrap_or(0)));
+            }
+            None => {
+                lines.push(indent("popq %rcx"));
+                self.stack_depth -= 8;
+                lines.append(&mut store(ty, "(%rcx)"));
+            }
+        }
+        lines
+    }
+
+    // Generate the addition of a number of elements to a pointer, or their
+    // subtraction from it.
+    fn generate_pointer_arithmetic(
+        &mut self,
+        operator: BinaryOperator,
+        pointer: &Expr,
+        index: &Expr,
+    ) -> Vec<String> {
+        let size = pointee_size(&pointer.ty);
+
+        let mut lines = self.generate_expr(pointer);
+        lines.append(&mut self.push_value(&Type::Long));
+        lines.append(&mut self.generate_expr(index));
+        if size != 1 {
+            lines.push(indent(&format!("imulq ${}, %rax", size)));
+        }
+        lines.append(&mut self.pop_operands(&Type::Long));
+
+        match operator {
+            BinaryOperator::Plus => lines.push(indent("addq %rcx, %rax")),
+            _ => {
+                lines.push(indent("subq %rax, %rcx"));
+                lines.push(indent("movq %rcx, %rax"));
+            }
+        }
+        lines
+    }
+
+    // Generate the number of elements between two pointers.
+    fn generate_pointer_difference(&mut self, expr1: &Expr, expr2: &Expr) -> Vec<String> {
+        let size = pointee_size(&expr1.ty);
+
+        let mut lines = self.generate_expr(expr1);
+        lines.append(&mut self.push_value(&Type::Long));
+        lines.append(&mut self.generate_expr(expr2));
+        lines.append(&mut self.pop_operands(&Type::Long));
+        lines.push(indent("subq %rax, %rcx"));
+        lines.push(indent("movq %rcx, %rax"));
+
+        if size != 1 {
+            lines.push(indent(&format!("movq ${}, %rcx", size)));
+            lines.push(indent("cqto"));
+            lines.push(indent("idivq %rcx"));
+        }
+        lines
+    }
+
+    // Generate a && or || operator, which only evaluates its second operand
+    // if the first one doesn't already determine the result.
+    fn generate_logical_op(
+        &mut self,
+        operator: BinaryOperator,
+        expr1: &Expr,
+        expr2: &Expr,
+    ) -> Vec<String> {
+        let end_label = self.new_label();
+        let jump = if operator == BinaryOperator::And {
+            "je"
+        } else {
+            "jne"
+        };
+
+        let mut lines = self.generate_expr(expr1);
+        lines.append(&mut generate_truth_value(&expr1.ty));
+        lines.push(indent("cmpl $0, %eax"));
+        lines.push(indent(&format!("{} {}", jump, end_label)));
+        lines.append(&mut self.generate_expr(expr2));
+        lines.append(&mut generate_truth_value(&expr2.ty));
+        lines.push(format!("{}:", end_label));
+        lines
+    }
+
+    fn generate_call(&mut self, callee: &Expr, args: &[Expr], return_type: &Type) -> Vec<String> {
+        // A function called by name is called directly, rather than through
+        // a pointer to it.
+        let direct = match callee.kind {
+            ExprKind::Decay(ref function) => match function.kind {
+                ExprKind::Function(ref name) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        // A variadic function needs to know how many SSE registers hold
+        // arguments in %al, and so might one without a prototype.
+        let needs_sse_count = match callee.ty.pointee().map(Type::unqualified) {
+            Some(Type::Function(func_type)) => func_type.variadic || func_type.params.is_none(),
+            _ => false,
+        };
+
+        let arg_types = args.iter().map(|arg| arg.ty.clone()).collect::<Vec<_>>();
+        let layout = layout_arguments(&arg_types);
+        let mut lines = Vec::new();
+
+        // The stack must be 16-byte aligned at the call instruction, once
+        // the arguments passed on the stack have been pushed.
+        let padding = align_to(self.stack_depth + 8 * layout.stack_slots as i64, 16)
+            - (self.stack_depth + 8 * layout.stack_slots as i64);
+        if padding > 0 {
+            lines.push(indent(&format!("subq ${}, %rsp", padding)));
+            self.stack_depth += padding;
+        }
+
+        // Push the arguments passed on the stack from last to first, so that
+        // they end up in order. The arguments passed in registers are then
+        // pushed on top of them, and popped into their registers.
+        let (on_stack, in_registers): (Vec<usize>, Vec<usize>) = (0..args.len())
+            .partition(|&i| matches!(layout.locations[i], ArgumentLocation::Stack(_)));
+
+        for &i in on_stack.iter().rev().chain(in_registers.iter().rev()) {
+            lines.append(&mut self.generate_expr(&args[i]));
+            lines.append(&mut self.push_value(&arg_types[i]));
+        }
+
+        // The address of a function called indirectly is worked out last,
+        // and kept in %rax while the arguments are popped into registers.
+        if direct.is_none() {
+            lines.append(&mut self.generate_expr(callee));
+        }
+
+        for &i in &in_registers {
+            match layout.locations[i] {
+                ArgumentLocation::Register(register) => {
+                    lines.push(indent(&format!("popq {}", ARGUMENT_REGISTERS[register][0])));
+                }
+                ArgumentLocation::SseRegister(register) => {
+                    lines.push(indent(&format!("movq (%rsp), %xmm{}", register)));
+                    lines.push(indent("addq $8, %rsp"));
+                }
+                ArgumentLocation::Stack(_) => {}
+            }
+            self.stack_depth -= 8;
+        }
+
+        // Setting %al means an indirect call has to go through %r11 instead
+        // of %rax.
+        let mut target = "*%rax".to_owned();
+        if needs_sse_count {
+            if direct.is_none() {
+                lines.push(indent("movq %rax, %r11"));
+                target = "*%r11".to_owned();
+            }
+            lines.push(indent(&format!("movl ${}, %eax", layout.sse_registers)));
+        }
+
+        if let Some(name) = direct {
+            target = symbol(&name);
+        }
+        lines.push(indent(&format!("call {}", target)));
+
+        let cleanup = padding + 8 * layout.stack_slots as i64;
+        if cleanup > 0 {
+            lines.push(indent(&format!("addq ${}, %rsp", cleanup)));
+            self.stack_depth -= cleanup;
+        }
+
+        // Return values narrower than an int aren't guaranteed to have been
+        // extended to 32 bits.
+        if return_type.is_integer() && return_type.size() < Some(4) {
+            lines.append(&mut self.generate_conversion(&Type::Int, return_type));
         }
-        _ => Vec::new(),
+
+        lines
+    }
+
+    fn generate_va_start(&mut self, ap: &Expr) -> Vec<String> {
+        let frame = self
+            .variadic_frame
+            .as_ref()
+            .expect("va_start in a function with fixed args");
+        let (gp_offset, fp_offset) = (frame.gp_offset, frame.fp_offset);
+        let (overflow_arg_area, register_save_area) =
+            (frame.overflow_arg_area, frame.register_save_area);
+
+        let mut lines = self.generate_expr(ap);
+        lines.append(&mut vec![
+            indent(&format!("movl ${}, (%rax)", gp_offset)),
+            indent(&format!("movl ${}, 4(%rax)", fp_offset)),
+            indent(&format!("leaq {}(%rbp), %rcx", overflow_arg_area)),
+            indent("movq %rcx, 8(%rax)"),
+            indent(&format!("leaq {}(%rbp), %rcx", register_save_area)),
+            indent("movq %rcx, 16(%rax)"),
+        ]);
+        lines
+    }
+
+    fn generate_va_arg(&mut self, ap: &Expr, ty: &Type) -> Vec<String> {
+        // The argument was passed with the default argument promotions
+        // applied, so read it as the promoted type.
+        let promoted_type = ty.promote_argument();
+
+        // The offset into the register save area is at the start of the
+        // va_list for integer arguments and 4 bytes in for floating ones.
+        let (offset_field, limit, step) = if promoted_type.is_floating() {
+            (4, REGISTER_SAVE_AREA_SIZE, 16)
+        } else {
+            (0, REGISTER_SAVE_AREA_SSE_OFFSET, 8)
+        };
+
+        let stack_label = self.new_label();
+        let load_label = self.new_label();
+
+        let mut lines = self.generate_expr(ap);
+        lines.append(&mut vec![
+            indent("movq %rax, %rcx"),
+            indent(&format!("movl {}(%rcx), %edx", offset_field)),
+            indent(&format!("cmpl ${}, %edx", limit)),
+            indent(&format!("jae {}", stack_label)),
+            // The argument is in the register save area.
+            indent("movq 16(%rcx), %rax"),
+            indent("addq %rdx, %rax"),
+            indent(&format!("addl ${}, {}(%rcx)", step, offset_field)),
+            indent(&format!("jmp {}", load_label)),
+            // The argument is in the overflow area on the stack.
+            format!("{}:", stack_label),
+            indent("movq 8(%rcx), %rax"),
+            indent("addq $8, 8(%rcx)"),
+            format!("{}:", load_label),
+        ]);
+        lines.append(&mut load(&promoted_type, "(%rax)"));
+        lines.append(&mut self.generate_conversion(&promoted_type, ty));
+        lines
+    }
+
+    // Push the value of an expression, which has the given type, onto the stack.
+    fn push_value(&mut self, ty: &Type) -> Vec<String> {
+        self.stack_depth += 8;
+
+        if ty.is_floating() {
+            vec![indent("movq %xmm0, %rax"), indent("pushq %rax")]
+        } else {
+            vec![indent("pushq %rax")]
+        }
+    }
+
+    // Pop the first operand of a binary operator, pushed by push_value, into
+    // %rcx or %xmm0. The second operand ends up in %rax or %xmm1.
+    fn pop_operands(&mut self, ty: &Type) -> Vec<String> {
+        self.stack_depth -= 8;
+
+        if ty.is_floating() {
+            vec![
+                indent("movaps %xmm0, %xmm1"),
+                indent("popq %rax"),
+                indent("movq %rax, %xmm0"),
+            ]
+        } else {
+            vec![indent("popq %rcx")]
+        }
+    }
+
+    // Convert the value of an expression from one type to another. Integer
+    // values narrower than 64 bits live in %eax, already sign- or
+    // zero-extended to 32 bits, with the upper half of %rax left undefined.
+    fn generate_conversion(&mut self, from: &Type, to: &Type) -> Vec<String> {
+        let (from, to) = (from.unqualified(), to.unqualified());
+
+        // Converting to _Bool compares with zero rather than truncating.
+        if *to == Type::Bool && *from != Type::Bool {
+            return generate_truth_value(from);
+        }
+
+        if from.is_floating() || to.is_floating() {
+            return self.generate_floating_conversion(from, to);
+        }
+
+        match (from.size(), to.size(), to.is_signed()) {
+            (_, Some(1), true) => vec![indent("movsbl %al, %eax")],
+            (_, Some(1), false) => vec![indent("movzbl %al, %eax")],
+            (_, Some(2), true) => vec![indent("movswl %ax, %eax")],
+            (_, Some(2), false) => vec![indent("movzwl %ax, %eax")],
+            (Some(size), Some(8), _) if size < 8 => {
+                if from.is_signed() {
+                    vec![indent("movslq %eax, %rax")]
+                } else {
+                    // Writing to %eax zeroes the upper half of %rax.
+                    vec![indent("movl %eax, %eax")]
+                }
+            }
+            // Narrowing to 32 bits just drops the upper half of %rax, and
+            // conversions to void discard the value altogether.
+            _ => Vec::new(),
+        }
+    }
+
+    fn generate_floating_conversion(&mut self, from: &Type, to: &Type) -> Vec<String> {
+        match (from, to) {
+            (_, &Type::Void) => Vec::new(),
+            (&Type::Float, &Type::Double) => vec![indent("cvtss2sd %xmm0, %xmm0")],
+            (&Type::Double, &Type::Float) => vec![indent("cvtsd2ss %xmm0, %xmm0")],
+            (from, to) if from == to => Vec::new(),
+            (from, to) if to.is_floating() => self.generate_int_to_floating(from, to),
+            (from, to) => self.generate_floating_to_int(from, to),
+        }
+    }
+
+    fn generate_int_to_floating(&mut self, from: &Type, to: &Type) -> Vec<String> {
+        let x = sse_suffix(to);
+
+        match (from.size(), from.is_signed()) {
+            (Some(8), false) => {
+                // There is no instruction for converting from an unsigned
+                // 64-bit integer. Values with the top bit set are halved,
+                // keeping the lowest bit so that they round correctly, and
+                // doubled again once converted.
+                let big_label = self.new_label();
+                let done_label = self.new_label();
+                vec![
+                    indent("testq %rax, %rax"),
+                    indent(&format!("js {}", big_label)),
+                    indent(&format!("cvtsi2s{}q %rax, %xmm0", x)),
+                    indent(&format!("jmp {}", done_label)),
+                    format!("{}:", big_label),
+                    indent("movq %rax, %rcx"),
+                    indent("shrq %rcx"),
+                    indent("andl $1, %eax"),
+                    indent("orq %rax, %rcx"),
+                    indent(&format!("cvtsi2s{}q %rcx, %xmm0", x)),
+                    indent(&format!("adds{} %xmm0, %xmm0", x)),
+                    format!("{}:", done_label),
+                ]
+            }
+            (Some(8), true) => vec![indent(&format!("cvtsi2s{}q %rax, %xmm0", x))],
+            // Unsigned 32-bit values are zero-extended so that they can be
+            // converted as signed 64-bit ones.
+            (_, false) => vec![
+                indent("movl %eax, %eax"),
+                indent(&format!("cvtsi2s{}q %rax, %xmm0", x)),
+            ],
+            (_, true) => vec![indent(&format!("cvtsi2s{}l %eax, %xmm0", x))],
+        }
+    }
+
+    fn generate_floating_to_int(&mut self, from: &Type, to: &Type) -> Vec<String> {
+        let x = sse_suffix(from);
+
+        let mut lines = match (to.size(), to.is_signed()) {
+            (Some(8), false) => {
+                // Values of 2^63 and above are out of the range of cvtts*2si,
+                // so they have 2^63 taken off before the conversion and put
+                // back in the top bit afterwards.
+                let big_label = self.new_label();
+                let done_label = self.new_label();
+                let mut lines = load_floating_constant(from, 9_223_372_036_854_775_808.0, "%xmm1");
+                lines.append(&mut vec![
+                    indent(&format!("ucomis{} %xmm1, %xmm0", x)),
+                    indent(&format!("jae {}", big_label)),
+                    indent(&format!("cvtts{}2siq %xmm0, %rax", x)),
+                    indent(&format!("jmp {}", done_label)),
+                    format!("{}:", big_label),
+                    indent(&format!("subs{} %xmm1, %xmm0", x)),
+                    indent(&format!("cvtts{}2siq %xmm0, %rax", x)),
+                    indent("btcq $63, %rax"),
+                    format!("{}:", done_label),
+                ]);
+                lines
+            }
+            // Unsigned 32-bit values are converted as signed 64-bit ones, of
+            // which we then keep the lower half.
+            (Some(8), true) | (Some(4), false) => {
+                vec![indent(&format!("cvtts{}2siq %xmm0, %rax", x))]
+            }
+            _ => vec![indent(&format!("cvtts{}2si %xmm0, %eax", x))],
+        };
+
+        // Integers narrower than an int are truncated from the converted int.
+        if to.size() < Some(4) {
+            lines.append(&mut self.generate_conversion(&Type::Int, to));
+        }
+        lines
     }
 }
 
-fn generate_unary_op(operator: &UnaryOperator) -> Vec<String> {
+fn load_constant(ty: &Type, value: i64) -> String {
+    if ty.size() != Some(8) {
+        indent(&format!("movl ${}, %eax", value))
+    } else if fits_in_immediate(value) {
+        indent(&format!("movq ${}, %rax", value))
+    } else {
+        indent(&format!("movabsq ${}, %rax", value))
+    }
+}
+
+// Load a floating constant of the given type into an SSE register, going
+// through %rax as SSE instructions can't take immediate operands.
+fn load_floating_constant(ty: &Type, value: f64, register: &str) -> Vec<String> {
+    if *ty == Type::Float {
+        vec![
+            indent(&format!("movl ${}, %eax", (value as f32).to_bits())),
+            indent(&format!("movd %eax, {}", register)),
+        ]
+    } else {
+        vec![
+            indent(&format!("movabsq ${}, %rax", value.to_bits())),
+            indent(&format!("movq %rax, {}", register)),
+        ]
+    }
+}
+
+// Turn the value of an expression of the given type into an int that is 1 if
+// the value is nonzero, and 0 otherwise.
+fn generate_truth_value(ty: &Type) -> Vec<String> {
+    if ty.is_floating() {
+        let x = sse_suffix(ty);
+        vec![
+            indent("xorps %xmm1, %xmm1"),
+            indent(&format!("ucomis{} %xmm1, %xmm0", x)),
+            indent("movl $0, %eax"),
+            // NaN compares unordered with zero, and counts as true.
+            indent("setne %al"),
+            indent("setp %cl"),
+            indent("orb %cl, %al"),
+        ]
+    } else {
+        vec![
+            indent(&format!("cmp{} $0, {}", suffix(ty), reg("ax", ty))),
+            indent("movl $0, %eax"),
+            indent("setne %al"),
+        ]
+    }
+}
+
+fn generate_unary_op(operator: &UnaryOperator, ty: &Type) -> Vec<String> {
+    if ty.is_floating() {
+        return generate_floating_unary_op(operator, ty);
+    }
+
+    let suffix = suffix(ty);
+    let ax = reg("ax", ty);
+
     match *operator {
-        UnaryOperator::Minus => vec![indent("neg %eax")],
-        UnaryOperator::Tilde => vec![indent("not %eax")],
+        UnaryOperator::Minus => vec![indent(&format!("neg{} {}", suffix, ax))],
+        UnaryOperator::Tilde => vec![indent(&format!("not{} {}", suffix, ax))],
         UnaryOperator::Bang => vec![
-            indent("cmpl $0, %eax"),
+            indent(&format!("cmp{} $0, {}", suffix, ax)),
             indent("movl $0, %eax"),
             indent("sete %al"),
         ],
     }
 }
 
-fn generate_binary_op(operator: &BinaryOperator) -> Vec<String> {
+fn generate_floating_unary_op(operator: &UnaryOperator, ty: &Type) -> Vec<String> {
     match *operator {
-        BinaryOperator::Plus => vec![indent("addl %ecx, %eax")],
-        BinaryOperator::Minus => vec![
-            indent("subl %eax, %ecx"),
-            // subl will store the result in %ecx, but we need it in %eax.
-            indent("movl %ecx, %eax"),
+        // Negation flips the sign bit, which unlike subtracting from zero
+        // also turns 0.0 into -0.0.
+        UnaryOperator::Minus if *ty == Type::Float => vec![
+            indent("movd %xmm0, %eax"),
+            indent("xorl $0x80000000, %eax"),
+            indent("movd %eax, %xmm0"),
         ],
-        BinaryOperator::Times => vec![indent("imul %ecx, %eax")],
-        BinaryOperator::Divide => vec![
-            // Zero out %edx, as idivl dst computes [%edx:%eax] / dst.
-            indent("movl $0, %edx"),
-            // Store expr1 in %eax, expr2 in %ebx.
-            indent("movl %eax, %ebx"),
-            indent("movl %ecx, %eax"),
-            // The quotient of idivl is written to %eax.
-            indent("idivl %ebx"),
+        UnaryOperator::Minus => vec![
+            indent("movq %xmm0, %rax"),
+            indent("btcq $63, %rax"),
+            indent("movq %rax, %xmm0"),
         ],
-        BinaryOperator::Or => vec![
-            indent("orl %ecx, %eax"),
-            indent("movl $0, %eax"),
-            indent("setne %al"),
-        ],
-        BinaryOperator::And => vec![
-            indent("cmpl $0, %ecx"),
-            indent("setne %cl"),
-            indent("cmpl $0, %eax"),
-            indent("movl $0, %eax"),
-            indent("setne %al"),
-            indent("andb %cl, %al"),
+        UnaryOperator::Bang => {
+            let mut lines = generate_truth_value(ty);
+            lines.push(indent("xorl $1, %eax"));
+            lines
+        }
+        // Semantic analysis only allows ~ on integers.
+        UnaryOperator::Tilde => unreachable!("~ applied to a floating operand"),
+    }
+}
+
+// Apply a binary operator to the operands left by pop_operands, both of which
+// have already been converted to the given type.
+fn generate_binary_op(operator: &BinaryOperator, ty: &Type) -> Vec<String> {
+    if ty.is_floating() {
+        return generate_floating_binary_op(operator, ty);
+    }
+
+    let suffix = suffix(ty);
+    let (ax, cx) = (reg("ax", ty), reg("cx", ty));
+
+    match *operator {
+        BinaryOperator::Plus => vec![indent(&format!("add{} {}, {}", suffix, cx, ax))],
+        BinaryOperator::Minus => vec![
+            indent(&format!("sub{} {}, {}", suffix, ax, cx)),
+            // sub will store the result in %rcx, but we need it in %rax.
+            indent(&format!("mov{} {}, {}", suffix, cx, ax)),
         ],
+        BinaryOperator::Times => vec![indent(&format!("imul{} {}, {}", suffix, cx, ax))],
+        BinaryOperator::Divide => {
+            // Store expr1 in %rax, expr2 in %rcx.
+            let mut lines = vec![indent(&format!("xchg{} {}, {}", suffix, cx, ax))];
+            if ty.is_signed() {
+                // idiv dst computes [%rdx:%rax] / dst, so sign-extend %rax
+                // into %rdx.
+                lines.push(indent(if suffix == "q" { "cqto" } else { "cltd" }));
+                lines.push(indent(&format!("idiv{} {}", suffix, cx)));
+            } else {
+                // div dst computes [%rdx:%rax] / dst, so zero out %rdx.
+                lines.push(indent("movl $0, %edx"));
+                lines.push(indent(&format!("div{} {}", suffix, cx)));
+            }
+            // The quotient is written to %rax.
+            lines
+        }
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+            // Signed values are shifted right arithmetically, keeping their
+            // sign, and unsigned ones logically.
+            let instruction = match (operator, ty.is_signed()) {
+                (&BinaryOperator::ShiftLeft, _) => "sal",
+                (_, true) => "sar",
+                (_, false) => "shr",
+            };
+            // Store expr1 in %rax and the count in %rcx, of which only %cl
+            // is used.
+            vec![
+                indent(&format!("xchg{} {}, {}", suffix, cx, ax)),
+                indent(&format!("{}{} %cl, {}", instruction, suffix, ax)),
+            ]
+        }
         // Handle ==, !=, <, <=, >, >=
-        _ => generate_binary_comparison(operator),
+        _ => generate_binary_comparison(operator, ty),
     }
 }
 
-fn generate_binary_comparison(operator: &BinaryOperator) -> Vec<String> {
-    let mut lines = vec![indent("cmpl %eax, %ecx"), indent("movl $0, %eax")];
-    let set_al = match *operator {
-        BinaryOperator::Equal => indent("sete %al"),
-        BinaryOperator::NotEqual => indent("setne %al"),
-        BinaryOperator::LessThan => indent("setl %al"),
-        BinaryOperator::LessThanOrEqual => indent("setle %al"),
-        BinaryOperator::GreaterThan => indent("setg %al"),
-        BinaryOperator::GreaterThanOrEqual => indent("setge %al"),
-        _ => String::new(),
+fn generate_binary_comparison(operator: &BinaryOperator, ty: &Type) -> Vec<String> {
+    let mut lines = vec![
+        indent(&format!(
+            "cmp{} {}, {}",
+            suffix(ty),
+            reg("ax", ty),
+            reg("cx", ty)
+        )),
+        indent("movl $0, %eax"),
+    ];
+
+    // Signed comparisons test the sign and overflow flags, whereas unsigned
+    // ones test the carry flag ("below" and "above").
+    let condition = match (operator, ty.is_signed()) {
+        (&BinaryOperator::Equal, _) => "e",
+        (&BinaryOperator::NotEqual, _) => "ne",
+        (&BinaryOperator::LessThan, true) => "l",
+        (&BinaryOperator::LessThan, false) => "b",
+        (&BinaryOperator::LessThanOrEqual, true) => "le",
+        (&BinaryOperator::LessThanOrEqual, false) => "be",
+        (&BinaryOperator::GreaterThan, true) => "g",
+        (&BinaryOperator::GreaterThan, false) => "a",
+        (&BinaryOperator::GreaterThanOrEqual, true) => "ge",
+        (&BinaryOperator::GreaterThanOrEqual, false) => "ae",
+        _ => unreachable!("{:?} is not a comparison", operator),
     };
-    lines.push(set_al);
+    lines.push(indent(&format!("set{} %al", condition)));
     lines
 }
 
+// Apply a binary operator to expr1 in %xmm0 and expr2 in %xmm1.
+fn generate_floating_binary_op(operator: &BinaryOperator, ty: &Type) -> Vec<String> {
+    let x = sse_suffix(ty);
+
+    let instruction = match *operator {
+        BinaryOperator::Plus => "add",
+        BinaryOperator::Minus => "sub",
+        BinaryOperator::Times => "mul",
+        BinaryOperator::Divide => "div",
+        _ => return generate_floating_comparison(operator, ty),
+    };
+
+    vec![indent(&format!("{}s{} %xmm1, %xmm0", instruction, x))]
+}
+
+fn generate_floating_comparison(operator: &BinaryOperator, ty: &Type) -> Vec<String> {
+    let x = sse_suffix(ty);
+
+    // ucomis* sets the flags like an unsigned comparison, except that if
+    // either operand is NaN it sets all of ZF, PF and CF. Only "above" and
+    // "above or equal" are false for NaN, so < and <= swap their operands to
+    // use those.
+    let (operands, condition) = match *operator {
+        BinaryOperator::GreaterThan => ("%xmm1, %xmm0", "a"),
+        BinaryOperator::GreaterThanOrEqual => ("%xmm1, %xmm0", "ae"),
+        BinaryOperator::LessThan => ("%xmm0, %xmm1", "a"),
+        BinaryOperator::LessThanOrEqual => ("%xmm0, %xmm1", "ae"),
+        BinaryOperator::Equal => {
+            return vec![
+                indent(&format!("ucomis{} %xmm1, %xmm0", x)),
+                indent("movl $0, %eax"),
+                indent("sete %al"),
+                indent("setnp %cl"),
+                indent("andb %cl, %al"),
+            ];
+        }
+        BinaryOperator::NotEqual => {
+            return vec![
+                indent(&format!("ucomis{} %xmm1, %xmm0", x)),
+                indent("movl $0, %eax"),
+                indent("setne %al"),
+                indent("setp %cl"),
+                indent("orb %cl, %al"),
+            ];
+        }
+        _ => return Vec::new(),
+    };
+
+    vec![
+        indent(&format!("ucomis{} {}", x, operands)),
+        indent("movl $0, %eax"),
+        indent(&format!("set{} %al", condition)),
+    ]
+}
+
+// The suffix of scalar SSE instructions operating on the given floating type:
+// "s" for single precision, "d" for double precision.
+fn sse_suffix(ty: &Type) -> &'static str {
+    if *ty.unqualified() == Type::Float {
+        "s"
+    } else {
+        "d"
+    }
+}
+
+// The assembly symbol for a function or variable with linkage.
+fn symbol(name: &str) -> String {
+    format!("_{}", name)
+}
+
+// Define the data of a variable with static storage duration. Variables
+// without an initializer are zero-initialized in the .bss section, except
+// for those that can never be modified, which are read-only.
+fn generate_data(label: &str, ty: &Type, global: bool, data: Option<Vec<String>>) -> Vec<String> {
+    let mut lines = Vec::new();
+    if global {
+        lines.push(indent(&format!(".globl {}", label)));
+    }
+
+    // A volatile object may be changed by means unknown to the program even
+    // if it's const, so it can't go in read-only memory.
+    let qualifiers = ty.qualifiers();
+    let section = if qualifiers.is_const && !qualifiers.is_volatile {
+        rodata_section()
+    } else if data.is_some() {
+        ".data"
+    } else {
+        ".bss"
+    };
+
+    let align = ty.align().unwrap_or(1);
+    lines.push(indent(section));
+    lines.push(indent(&format!(".p2align {}", align.trailing_zeros())));
+    lines.push(format!("{}:", label));
+    match data {
+        Some(mut data) => lines.append(&mut data),
+        None => lines.push(indent(&format!(".zero {}", ty.size().unwrap_or(0)))),
+    }
+    lines.push(indent(".text"));
+    lines
+}
+
+// The directive that emits an integer of the size of the given type.
+// Generate code that zeroes an object in the stack frame, given its offset
+// from the frame pointer and its size.
+fn generate_zero(offset: i64, size: u64) -> Vec<String> {
+    if size > 64 {
+        return vec![
+            indent(&format!("leaq {}(%rbp), %rdi", offset)),
+            indent(&format!("movl ${}, %ecx", size)),
+            indent("xorl %eax, %eax"),
+            indent("rep stosb"),
+        ];
+    }
+
+    let mut lines = Vec::new();
+    let mut position = 0;
+    for &(width, suffix) in &[(8, 'q'), (4, 'l'), (2, 'w'), (1, 'b')] {
+        while size - position >= width {
+            lines.push(indent(&format!(
+                "mov{} $0, {}(%rbp)",
+                suffix,
+                offset + position as i64
+            )));
+            position += width;
+        }
+    }
+    lines
+}
+
+// Generate code that copies a structure from the address in %rax to the
+// address in %rcx, leaving the destination address in %rax.
+fn generate_copy(size: u64) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut position = 0;
+    for &(width, register) in &[(8, "%rdx"), (4, "%edx"), (2, "%dx"), (1, "%dl")] {
+        let suffix = match width {
+            8 => 'q',
+            4 => 'l',
+            2 => 'w',
+            _ => 'b',
+        };
+        while size - position >= width {
+            lines.push(indent(&format!(
+                "mov{} {}(%rax), {}",
+                suffix, position, register
+            )));
+            lines.push(indent(&format!(
+                "mov{} {}, {}(%rcx)",
+                suffix, register, position
+            )));
+            position += width;
+        }
+    }
+    lines.push(indent("movq %rcx, %rax"));
+    lines
+}
+
+fn data_directive(ty: &Type) -> &'static str {
+    match ty.size() {
+        Some(1) => ".byte",
+        Some(2) => ".short",
+        Some(4) => ".long",
+        _ => ".quad",
+    }
+}
+
+// Work out where each argument of a call goes, following the System V
+// classification of integer and pointer arguments as INTEGER and floating
+// ones as SSE.
+fn layout_arguments(types: &[Type]) -> ArgumentLayout {
+    let mut layout = ArgumentLayout {
+        locations: Vec::new(),
+        registers: 0,
+        sse_registers: 0,
+        stack_slots: 0,
+    };
+
+    for ty in types {
+        let location = if ty.is_floating() && layout.sse_registers < SSE_ARGUMENT_REGISTERS {
+            layout.sse_registers += 1;
+            ArgumentLocation::SseRegister(layout.sse_registers - 1)
+        } else if !ty.is_floating() && layout.registers < ARGUMENT_REGISTERS.len() {
+            layout.registers += 1;
+            ArgumentLocation::Register(layout.registers - 1)
+        } else {
+            layout.stack_slots += 1;
+            ArgumentLocation::Stack(layout.stack_slots - 1)
+        };
+
+        layout.locations.push(location);
+    }
+
+    layout
+}
+
+// The size of the elements that a pointer points to.
+fn pointee_size(ty: &Type) -> u64 {
+    ty.pointee().and_then(Type::size).unwrap_or(1)
+}
+
+// A label plus an offset, as the operand of a data directive.
+fn offset_label(label: &str, offset: i64) -> String {
+    match offset {
+        0 => label.to_owned(),
+        offset if offset > 0 => format!("{}+{}", label, offset),
+        offset => format!("{}{}", label, offset),
+    }
+}
+
+fn generate_epilogue() -> Vec<String> {
+    vec![
+        indent("movq %rbp, %rsp"),
+        indent("popq %rbp"),
+        indent("ret"),
+    ]
+}
+
+// Load a value of the given type from memory into %rax or %xmm0, extending
+// integers narrower than 32 bits.
+fn load(ty: &Type, address: &str) -> Vec<String> {
+    let instruction = match *ty.unqualified() {
+        Type::Char => "movsbl",
+        Type::Bool | Type::UnsignedChar => "movzbl",
+        Type::Short => "movswl",
+        Type::UnsignedShort => "movzwl",
+        Type::Float => "movss",
+        Type::Double => "movsd",
+        _ if ty.size() == Some(8) => "movq",
+        _ => "movl",
+    };
+
+    let register = if ty.is_floating() {
+        "%xmm0"
+    } else {
+        ARGUMENT_WIDTH_ACCUMULATOR[width_index(ty).min(1)]
+    };
+
+    vec![indent(&format!(
+        "{} {}, {}",
+        instruction, address, register
+    ))]
+}
+
+// Store a value of the given type from %rax or %xmm0 into memory.
+fn store(ty: &Type, address: &str) -> Vec<String> {
+    if ty.is_floating() {
+        return vec![indent(&format!(
+            "movs{} %xmm0, {}",
+            sse_suffix(ty),
+            address
+        ))];
+    }
+
+    vec![indent(&format!(
+        "mov{} {}, {}",
+        size_suffix(ty),
+        ARGUMENT_WIDTH_ACCUMULATOR[width_index(ty)],
+        address
+    ))]
+}
+
+// The names of %rax at each width, indexed by width_index.
+const ARGUMENT_WIDTH_ACCUMULATOR: [&str; 4] = ["%rax", "%eax", "%ax", "%al"];
+
+// The index of the register name for the size of the given type, in tables
+// of register names ordered from 64 bits down to 8 bits.
+fn width_index(ty: &Type) -> usize {
+    match ty.size() {
+        Some(1) => 3,
+        Some(2) => 2,
+        Some(4) => 1,
+        _ => 0,
+    }
+}
+
+// The suffix of integer instructions that operate on values of the size of
+// the given type.
+fn size_suffix(ty: &Type) -> &'static str {
+    match ty.size() {
+        Some(1) => "b",
+        Some(2) => "w",
+        Some(4) => "l",
+        _ => "q",
+    }
+}
+
+fn align_to(value: i64, align: i64) -> i64 {
+    (value + align - 1) / align * align
+}
+
+// Escape the bytes of a string literal for use in an .asciz directive.
+fn escape_string(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+// The instruction suffix for operating on values of the given type.
+fn suffix(ty: &Type) -> &'static str {
+    if ty.size() == Some(8) {
+        "q"
+    } else {
+        "l"
+    }
+}
+
+// The name of a general-purpose register such as "ax", at the width of the
+// given type.
+fn reg(name: &str, ty: &Type) -> String {
+    if ty.size() == Some(8) {
+        format!("%r{}", name)
+    } else {
+        format!("%e{}", name)
+    }
+}
+
+// Whether a value can be used as the sign-extended 32-bit immediate operand
+// that most 64-bit instructions take.
+fn fits_in_immediate(value: i64) -> bool {
+    value >= i32::MIN as i64 && value <= i32::MAX as i64
+}
+
 fn indent(line: &str) -> String {
     "\t".to_owned() + line
 }
@@ -0,0 +1,557 @@
+use diagnostics;
+use lexer::{IntSuffix, Span};
+use parser::{ASTKind, BinaryOperator, UnaryOperator, AST};
+use types::Type;
+
+// The value of an integer constant expression. Values are represented as
+// 64-bit integers holding the result converted to its type, so that they
+// wrap around exactly like the code generated for the expression.
+#[derive(Debug)]
+pub struct Constant {
+    pub value: i64,
+    pub ty: Type,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // The expression is valid, but isn't an integer constant expression, for
+    // the reason given about the offending operand at the span.
+    NotConstant(Span, String),
+    // The expression isn't valid at all.
+    Invalid(diagnostics::Error),
+}
+
+impl Error {
+    // The error for an expression at a span in a context that requires an
+    // integer constant expression, which is described by the given message.
+    // The reason that the expression isn't constant is given in a note at
+    // the operand that it's about.
+    pub fn into_error(self, context: &str, span: Span) -> diagnostics::Error {
+        match self {
+            Error::NotConstant(operand, reason) => {
+                diagnostics::Error::at(context.to_owned(), span).with_note(operand, reason)
+            }
+            Error::Invalid(error) => error.or_at(span),
+        }
+    }
+}
+
+// Evaluate an integer constant expression. The operand of a sizeof
+// expression is never evaluated, but its type is worked out by the given
+// function, as that depends on the declarations in scope.
+pub fn evaluate<F>(expr: &AST, type_of: &mut F) -> Result<Constant, Error>
+where
+    F: FnMut(&AST) -> Result<Type, Error>,
+{
+    let not_constant = |reason| Error::NotConstant(expr.span, reason);
+    match expr.kind {
+        ASTKind::IntConstant(n, suffix) => Ok(Constant {
+            value: n as i64,
+            ty: integer_constant_type(n, suffix),
+        }),
+        ASTKind::Cast(ref ty, ref operand) => {
+            let ty = ty.unqualified();
+            if !ty.is_integer() {
+                return Err(not_constant(format!(
+                    "cast to non-integer type '{}' is not allowed in an integer constant expression",
+                    ty
+                )));
+            }
+
+            // A floating constant may only appear as the immediate operand
+            // of a cast to an integer type.
+            let out_of_range = |reason| Error::NotConstant(operand.span, reason);
+            let value = match operand.kind {
+                ASTKind::FloatConstant(n) => floating_to_integer(n as f64, ty),
+                ASTKind::DoubleConstant(n) => floating_to_integer(n, ty),
+                _ => Ok(ty.wrap(evaluate(operand, type_of)?.value)),
+            }
+            .map_err(out_of_range)?;
+            Ok(Constant {
+                value,
+                ty: ty.clone(),
+            })
+        }
+        ASTKind::SizeOfExpr(ref operand) => {
+            let ty = type_of(operand)?;
+            type_query("sizeof", &ty, ty.size())
+        }
+        ASTKind::SizeOfType(ref ty) => type_query("sizeof", ty, ty.size()),
+        ASTKind::AlignOfType(ref ty) => type_query("_Alignof", ty, ty.align()),
+        ASTKind::OffsetOf(ref ty, ref member) => {
+            let member = ty
+                .member(member)
+                .map_err(|error| Error::Invalid(error.into()))?;
+            Ok(Constant {
+                value: member.offset as i64,
+                ty: Type::UnsignedLong,
+            })
+        }
+        ASTKind::UnaryOp(operator, ref operand) => {
+            let operand = evaluate(operand, type_of)?;
+            let ty = operand.ty.promote();
+            let value = match operator {
+                UnaryOperator::Minus => arithmetic(BinaryOperator::Minus, &ty, 0, operand.value)
+                    .map_err(not_constant)?,
+                UnaryOperator::Tilde => ty.wrap(!operand.value),
+                UnaryOperator::Bang => {
+                    return Ok(Constant {
+                        value: (operand.value == 0) as i64,
+                        ty: Type::Int,
+                    });
+                }
+            };
+            Ok(Constant { value, ty })
+        }
+        // The second operand of && and || is only evaluated if the first
+        // doesn't already determine the result.
+        ASTKind::BinaryOp(operator @ BinaryOperator::And, ref expr1, ref expr2)
+        | ASTKind::BinaryOp(operator @ BinaryOperator::Or, ref expr1, ref expr2) => {
+            let lhs = evaluate(expr1, type_of)?.value != 0;
+            let value = if lhs == (operator == BinaryOperator::Or) {
+                lhs
+            } else {
+                evaluate(expr2, type_of)?.value != 0
+            };
+            Ok(Constant {
+                value: value as i64,
+                ty: Type::Int,
+            })
+        }
+        // The operands of a shift are promoted separately, and the result
+        // has the type of the left one.
+        ASTKind::BinaryOp(operator @ BinaryOperator::ShiftLeft, ref expr1, ref expr2)
+        | ASTKind::BinaryOp(operator @ BinaryOperator::ShiftRight, ref expr1, ref expr2) => {
+            let lhs = evaluate(expr1, type_of)?;
+            let rhs = evaluate(expr2, type_of)?;
+            let ty = lhs.ty.promote();
+            let count = widen(rhs.value, &rhs.ty.promote());
+            let value = shift(operator, &ty, ty.wrap(lhs.value), count).map_err(not_constant)?;
+            Ok(Constant { value, ty })
+        }
+        ASTKind::BinaryOp(operator, ref expr1, ref expr2) => {
+            let lhs = evaluate(expr1, type_of)?;
+            let rhs = evaluate(expr2, type_of)?;
+            let ty = lhs.ty.common(&rhs.ty);
+            let value = arithmetic(operator, &ty, ty.wrap(lhs.value), ty.wrap(rhs.value))
+                .map_err(not_constant)?;
+
+            let ty = match operator {
+                BinaryOperator::Plus
+                | BinaryOperator::Minus
+                | BinaryOperator::Times
+                | BinaryOperator::Divide => ty,
+                _ => Type::Int,
+            };
+            Ok(Constant { value, ty })
+        }
+        ASTKind::FloatConstant(_) | ASTKind::DoubleConstant(_) => Err(not_constant(
+            "floating constant is not allowed in an integer constant expression, except as \
+             the operand of a cast to an integer type"
+                .to_owned(),
+        )),
+        ASTKind::Variable(ref name) => Err(not_constant(format!(
+            "read of variable '{}' is not allowed in a constant expression",
+            name
+        ))),
+        _ => Err(not_constant(format!(
+            "{} is not allowed in a constant expression",
+            describe(expr)
+        ))),
+    }
+}
+
+// The type of an integer constant, which is the first of int, unsigned int,
+// long and unsigned long that its suffix allows and that can represent it.
+// Without a u suffix, only an octal or hexadecimal constant can be unsigned,
+// except that, as in other compilers, a decimal constant too large for long
+// is an unsigned long.
+pub fn integer_constant_type(n: u64, suffix: IntSuffix) -> Type {
+    if n <= i32::MAX as u64 && !suffix.unsigned && !suffix.long {
+        Type::Int
+    } else if n <= u32::MAX as u64 && (suffix.unsigned || suffix.non_decimal) && !suffix.long {
+        Type::UnsignedInt
+    } else if n <= i64::MAX as u64 && !suffix.unsigned {
+        Type::Long
+    } else {
+        Type::UnsignedLong
+    }
+}
+
+// Apply a binary operator to two operands that have been converted to their
+// common type. Unsigned arithmetic wraps around, whereas signed arithmetic
+// that overflows, like division by zero, has no value.
+pub fn arithmetic(operator: BinaryOperator, ty: &Type, lhs: i64, rhs: i64) -> Result<i64, String> {
+    // Unsigned values are compared and divided as the unsigned 64-bit
+    // integers with the same bits, which preserves their order.
+    let (ulhs, urhs) = (lhs as u64, rhs as u64);
+    let signed = ty.is_signed();
+    let (wide_lhs, wide_rhs) = if signed {
+        (lhs as i128, rhs as i128)
+    } else {
+        (ulhs as i128, urhs as i128)
+    };
+
+    let value = match operator {
+        BinaryOperator::Plus => return check_range(wide_lhs + wide_rhs, ty),
+        BinaryOperator::Minus => return check_range(wide_lhs - wide_rhs, ty),
+        BinaryOperator::Times => return check_range(wide_lhs * wide_rhs, ty),
+        BinaryOperator::Divide if rhs == 0 => return Err("division by zero".to_owned()),
+        BinaryOperator::Divide => return check_range(wide_lhs / wide_rhs, ty),
+        BinaryOperator::And => lhs != 0 && rhs != 0,
+        BinaryOperator::Or => lhs != 0 || rhs != 0,
+        BinaryOperator::Equal => lhs == rhs,
+        BinaryOperator::NotEqual => lhs != rhs,
+        BinaryOperator::LessThan => wide_lhs < wide_rhs,
+        BinaryOperator::LessThanOrEqual => wide_lhs <= wide_rhs,
+        BinaryOperator::GreaterThan => wide_lhs > wide_rhs,
+        BinaryOperator::GreaterThanOrEqual => wide_lhs >= wide_rhs,
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+            return shift(operator, ty, lhs, wide_rhs)
+        }
+    };
+
+    Ok(value as i64)
+}
+
+// Shift a value of the given type, which is the promoted type of the left
+// operand, by a count that has been widened from its own type. A count that
+// is negative or at least the width of the type has no value, nor does a
+// left shift of a signed value that overflows. Signed values are shifted
+// right arithmetically, as the generated code does.
+pub fn shift(operator: BinaryOperator, ty: &Type, lhs: i64, count: i128) -> Result<i64, String> {
+    let width = ty.size().unwrap_or(8) as i128 * 8;
+    if count < 0 {
+        return Err("shift count is negative".to_owned());
+    }
+    if count >= width {
+        return Err("shift count >= width of type".to_owned());
+    }
+
+    let wide_lhs = widen(lhs, ty);
+    if operator == BinaryOperator::ShiftRight {
+        return Ok(ty.wrap((wide_lhs >> count) as i64));
+    }
+
+    // Shifting into the sign bit without losing any bits, as in `1 << 31`,
+    // is allowed as it is by other compilers.
+    let value = wide_lhs << count;
+    if ty.is_signed() && wide_lhs >= 0 && value >> (width - 1) == 1 {
+        return Ok(ty.wrap(value as i64));
+    }
+    check_range(value, ty)
+}
+
+// A value of an integer type as an i128, which holds the values of both
+// the signed and the unsigned types exactly.
+pub fn widen(value: i64, ty: &Type) -> i128 {
+    if ty.is_signed() {
+        value as i128
+    } else {
+        value as u64 as i128
+    }
+}
+
+// Convert a floating value to an integer type, which truncates it toward
+// zero. The value has to be in the range of the type, except that any
+// nonzero value converts to a _Bool of 1.
+pub fn floating_to_integer(value: f64, ty: &Type) -> Result<i64, String> {
+    if *ty.unqualified() == Type::Bool {
+        return Ok((value != 0.0) as i64);
+    }
+
+    let (min, max) = range(ty);
+    let truncated = value.trunc();
+    if truncated.is_nan() || truncated < min as f64 || truncated > max as f64 {
+        return Err(format!(
+            "value {} is outside the range of representable values of type '{}'",
+            value, ty
+        ));
+    }
+
+    Ok(ty.wrap(truncated as i128 as i64))
+}
+
+// Format a value of the given type for a diagnostic.
+pub fn format_value(value: i64, ty: &Type) -> String {
+    if ty.is_signed() {
+        value.to_string()
+    } else {
+        (value as u64).to_string()
+    }
+}
+
+// The exact result of signed arithmetic must be in the range of its type,
+// whereas the result of unsigned arithmetic is reduced modulo 2^N.
+fn check_range(value: i128, ty: &Type) -> Result<i64, String> {
+    if !ty.is_signed() {
+        return Ok(ty.wrap(value as i64));
+    }
+
+    let (min, max) = range(ty);
+    if value < min || value > max {
+        return Err(format!(
+            "value {} is outside the range of representable values of type '{}'",
+            value, ty
+        ));
+    }
+
+    Ok(value as i64)
+}
+
+// The smallest and largest values of an integer type.
+fn range(ty: &Type) -> (i128, i128) {
+    let bits = 8 * ty.size().unwrap_or(8) as u32;
+    if ty.is_signed() {
+        (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+    } else {
+        (0, (1 << bits) - 1)
+    }
+}
+
+fn type_query(operator: &str, ty: &Type, value: Option<u64>) -> Result<Constant, Error> {
+    match value {
+        Some(value) => Ok(Constant {
+            value: value as i64,
+            ty: Type::UnsignedLong,
+        }),
+        None => Err(Error::Invalid(
+            format!(
+                "invalid application of '{}' to incomplete type '{}'",
+                operator, ty
+            )
+            .into(),
+        )),
+    }
+}
+
+// A description of an expression that can't appear in an integer constant
+// expression.
+fn describe(expr: &AST) -> &'static str {
+    match expr.kind {
+        ASTKind::Assign(..) => "assignment",
+        ASTKind::Call(..) => "function call",
+        ASTKind::StringLiteral(_) => "string literal",
+        ASTKind::AddressOf(_) => "taking the address of an object",
+        ASTKind::Dereference(_) => "dereferencing a pointer",
+        ASTKind::Member(..) => "member access",
+        ASTKind::VaStart(..) | ASTKind::VaArg(..) | ASTKind::VaEnd(_) | ASTKind::VaCopy(..) => {
+            "variable argument access"
+        }
+        _ => "this expression",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPAN: Span = Span {
+        start: 0,
+        end: 0,
+        line: 1,
+        column: 1,
+    };
+
+    fn int(n: u64) -> AST {
+        AST {
+            kind: ASTKind::IntConstant(n, IntSuffix::default()),
+            span: SPAN,
+        }
+    }
+
+    fn binary(operator: BinaryOperator, lhs: AST, rhs: AST) -> AST {
+        AST {
+            kind: ASTKind::BinaryOp(operator, Box::new(lhs), Box::new(rhs)),
+            span: SPAN,
+        }
+    }
+
+    fn eval(expr: &AST) -> Result<Constant, Error> {
+        evaluate(expr, &mut |_: &AST| -> Result<Type, Error> {
+            unreachable!()
+        })
+    }
+
+    #[test]
+    fn int_min_divided_by_minus_one_overflows() {
+        let int_min = i32::MIN as i64;
+        assert!(arithmetic(BinaryOperator::Divide, &Type::Int, int_min, -1).is_err());
+        assert!(arithmetic(BinaryOperator::Divide, &Type::Long, i64::MIN, -1).is_err());
+        assert_eq!(
+            arithmetic(BinaryOperator::Divide, &Type::Long, int_min, -1),
+            Ok(1 << 31)
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(arithmetic(BinaryOperator::Divide, &Type::Int, 1, 0).is_err());
+        assert!(arithmetic(BinaryOperator::Divide, &Type::UnsignedInt, 1, 0).is_err());
+    }
+
+    #[test]
+    fn signed_overflow() {
+        let int_max = i32::MAX as i64;
+        assert!(arithmetic(BinaryOperator::Plus, &Type::Int, int_max, 1).is_err());
+        assert!(arithmetic(BinaryOperator::Times, &Type::Int, 65536, 65536).is_err());
+        assert!(arithmetic(BinaryOperator::Minus, &Type::Long, i64::MIN, 1).is_err());
+        assert_eq!(
+            arithmetic(BinaryOperator::Plus, &Type::Long, int_max, 1),
+            Ok(1 << 31)
+        );
+    }
+
+    #[test]
+    fn unsigned_arithmetic_wraps() {
+        let uint_max = u32::MAX as i64;
+        assert_eq!(
+            arithmetic(BinaryOperator::Plus, &Type::UnsignedInt, uint_max, 1),
+            Ok(0)
+        );
+        assert_eq!(
+            arithmetic(BinaryOperator::Minus, &Type::UnsignedInt, 0, 1),
+            Ok(uint_max)
+        );
+        assert_eq!(
+            arithmetic(BinaryOperator::Minus, &Type::UnsignedLong, 0, 1),
+            Ok(-1)
+        );
+        assert_eq!(
+            arithmetic(BinaryOperator::Times, &Type::UnsignedLong, 1 << 32, 1 << 32),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn unsigned_comparisons_use_unsigned_order() {
+        let ulong_max = -1;
+        let compare = |operator| arithmetic(operator, &Type::UnsignedLong, ulong_max, 0);
+        assert_eq!(compare(BinaryOperator::GreaterThan), Ok(1));
+        assert_eq!(compare(BinaryOperator::LessThan), Ok(0));
+        assert_eq!(
+            arithmetic(BinaryOperator::Divide, &Type::UnsignedLong, -2, 2),
+            Ok(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn shift_into_the_sign_bit() {
+        let int_min = i32::MIN as i64;
+        assert_eq!(
+            shift(BinaryOperator::ShiftLeft, &Type::Int, 1, 31),
+            Ok(int_min)
+        );
+        assert!(shift(BinaryOperator::ShiftLeft, &Type::Int, 2, 31).is_err());
+        let value = shift(BinaryOperator::ShiftLeft, &Type::Int, 3, 30);
+        assert_eq!(value, Ok(0xc000_0000_u32 as i32 as i64));
+        assert!(shift(BinaryOperator::ShiftLeft, &Type::Int, 3, 31).is_err());
+        assert_eq!(
+            shift(BinaryOperator::ShiftLeft, &Type::Long, 1, 63),
+            Ok(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn shift_counts_out_of_range() {
+        assert!(shift(BinaryOperator::ShiftLeft, &Type::Int, 1, 32).is_err());
+        assert!(shift(BinaryOperator::ShiftRight, &Type::Long, 1, 64).is_err());
+        assert!(shift(BinaryOperator::ShiftLeft, &Type::Int, 1, -1).is_err());
+    }
+
+    #[test]
+    fn right_shift_keeps_the_sign_of_signed_values() {
+        assert_eq!(shift(BinaryOperator::ShiftRight, &Type::Int, -8, 1), Ok(-4));
+        let uint_max = u32::MAX as i64;
+        let value = shift(BinaryOperator::ShiftRight, &Type::UnsignedInt, uint_max, 1);
+        assert_eq!(value, Ok(i32::MAX as i64));
+    }
+
+    #[test]
+    fn unsigned_left_shift_discards_bits() {
+        let value = shift(
+            BinaryOperator::ShiftLeft,
+            &Type::UnsignedInt,
+            u32::MAX as i64,
+            4,
+        );
+        assert_eq!(value, Ok(0xffff_fff0));
+    }
+
+    #[test]
+    fn integer_constant_types() {
+        let decimal = IntSuffix::default();
+        let hex = IntSuffix {
+            non_decimal: true,
+            ..decimal
+        };
+        let unsigned = IntSuffix {
+            unsigned: true,
+            ..decimal
+        };
+        assert_eq!(integer_constant_type(0x7fff_ffff, decimal), Type::Int);
+        assert_eq!(integer_constant_type(0xffff_ffff, decimal), Type::Long);
+        assert_eq!(integer_constant_type(0xffff_ffff, hex), Type::UnsignedInt);
+        assert_eq!(
+            integer_constant_type(0xffff_ffff, unsigned),
+            Type::UnsignedInt
+        );
+        assert_eq!(integer_constant_type(1 << 32, hex), Type::Long);
+        assert_eq!(integer_constant_type(u64::MAX, decimal), Type::UnsignedLong);
+    }
+
+    #[test]
+    fn floating_to_integer_range() {
+        assert_eq!(floating_to_integer(-1.9, &Type::Int), Ok(-1));
+        assert_eq!(floating_to_integer(255.5, &Type::UnsignedChar), Ok(255));
+        assert!(floating_to_integer(256.0, &Type::UnsignedChar).is_err());
+        assert!(floating_to_integer(-1.0, &Type::UnsignedInt).is_err());
+        assert!(floating_to_integer(f64::NAN, &Type::Int).is_err());
+        assert_eq!(floating_to_integer(0.5, &Type::Bool), Ok(1));
+    }
+
+    #[test]
+    fn evaluates_with_the_usual_arithmetic_conversions() {
+        // -1 < 0u compares as unsigned.
+        let minus_one = AST {
+            kind: ASTKind::UnaryOp(UnaryOperator::Minus, Box::new(int(1))),
+            span: SPAN,
+        };
+        let unsigned_zero = AST {
+            kind: ASTKind::IntConstant(
+                0,
+                IntSuffix {
+                    unsigned: true,
+                    ..IntSuffix::default()
+                },
+            ),
+            span: SPAN,
+        };
+        let constant = eval(&binary(BinaryOperator::LessThan, minus_one, unsigned_zero)).unwrap();
+        assert_eq!(constant.value, 0);
+        assert_eq!(constant.ty, Type::Int);
+    }
+
+    #[test]
+    fn short_circuit_skips_the_unevaluated_operand() {
+        let division = binary(BinaryOperator::Divide, int(1), int(0));
+        let expr = binary(BinaryOperator::And, int(0), division);
+        assert_eq!(eval(&expr).unwrap().value, 0);
+
+        let division = binary(BinaryOperator::Divide, int(1), int(0));
+        let expr = binary(BinaryOperator::Or, int(1), division);
+        assert_eq!(eval(&expr).unwrap().value, 1);
+    }
+
+    #[test]
+    fn overflow_isnt_constant() {
+        let expr = binary(BinaryOperator::Plus, int(i32::MAX as u64), int(1));
+        match eval(&expr) {
+            Err(Error::NotConstant(_, reason)) => assert_eq!(
+                reason,
+                "value 2147483648 is outside the range of representable values of type 'int'"
+            ),
+            result => panic!("expected an overflow, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use cfg::{Cfg, ENTRY};
+use diagnostics::{Diagnostics, Warning};
+use lexer::Span;
+use parser::BinaryOperator;
+use semantic::{Expr, ExprKind, Function, Stmt, StmtKind, Storage};
+
+// What a statement does with the scalar automatic variables of a function,
+// in the order in which it does it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    // A read of a variable, at the expression that reads it.
+    Read(usize, Span),
+    Write(usize),
+    // A declaration with an initializer.
+    Initialize(usize),
+    // A declaration without an initializer, which leaves the variable with
+    // an indeterminate value every time it's reached.
+    Declare(usize),
+    // The address of the variable is taken, so it can be read and written
+    // through pointers that the analysis doesn't follow.
+    Escape(usize),
+    // The events between these two only happen on some paths through the
+    // statement, as in the second operand of && and ||.
+    BeginBranch,
+    EndBranch,
+}
+
+// Which variables have been assigned a value at a point in the function: on
+// every path that leads to it, and on at least one.
+#[derive(Clone, PartialEq)]
+struct Assigned {
+    definitely: Vec<bool>,
+    possibly: Vec<bool>,
+}
+
+// Warn about reads of variables that may not have been assigned a value,
+// and about variables that are assigned but never read.
+pub fn check(function: &Function, cfg: &Cfg, diagnostics: &mut Diagnostics) {
+    let events = cfg
+        .blocks
+        .iter()
+        .map(|block| {
+            let mut events = Vec::new();
+            for statement in &block.statements {
+                statement_events(statement, &mut events);
+            }
+            events
+        })
+        .collect::<Vec<_>>();
+
+    let locals = function.locals.len();
+    let mut escaped = vec![false; locals];
+    let mut reads = vec![0; locals];
+    let mut writes = vec![0; locals];
+    for event in events.iter().flatten() {
+        match *event {
+            Event::Read(local, _) => reads[local] += 1,
+            Event::Write(local) => writes[local] += 1,
+            Event::Escape(local) => escaped[local] = true,
+            _ => {}
+        }
+    }
+
+    check_uninitialized(function, cfg, &events, &escaped, diagnostics);
+
+    // A variable that's only initialized is reported as unused by semantic
+    // analysis, and a parameter is always written.
+    let params = function.ty.params.as_ref().map_or(0, Vec::len);
+    for local in params..locals {
+        if reads[local] == 0 && writes[local] > 0 && !escaped[local] {
+            let (ref name, span) = function.locals[local];
+            diagnostics.warn(
+                Warning::UnusedButSetVariable,
+                span,
+                format!("variable '{}' set but not used", name),
+            );
+        }
+    }
+}
+
+fn check_uninitialized(
+    function: &Function,
+    cfg: &Cfg,
+    events: &[Vec<Event>],
+    escaped: &[bool],
+    diagnostics: &mut Diagnostics,
+) {
+    let locals = function.locals.len();
+    let reachable = cfg.reachable();
+    let mut predecessors = vec![Vec::new(); cfg.blocks.len()];
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        if reachable[i] {
+            for &successor in &block.successors {
+                predecessors[successor].push(i);
+            }
+        }
+    }
+
+    // The parameters are assigned on entry. Every other block starts out as
+    // if everything were assigned, which the meet over its predecessors then
+    // narrows down until nothing changes.
+    let params = function.ty.params.as_ref().map_or(0, Vec::len);
+    let entry = Assigned {
+        definitely: (0..locals).map(|local| local < params).collect(),
+        possibly: (0..locals).map(|local| local < params).collect(),
+    };
+    let top = Assigned {
+        definitely: vec![true; locals],
+        possibly: vec![false; locals],
+    };
+    let mut outputs = vec![top.clone(); cfg.blocks.len()];
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..cfg.blocks.len()).filter(|&i| reachable[i]) {
+            let mut state = block_input(i, &entry, &top, &predecessors, &outputs);
+            transfer(&mut state, &events[i], |_, _, _| {});
+            if state != outputs[i] {
+                outputs[i] = state;
+                changed = true;
+            }
+        }
+    }
+
+    // Each variable is only reported at its first doubtful read.
+    let mut reported = escaped.to_vec();
+    for i in (0..cfg.blocks.len()).filter(|&i| reachable[i]) {
+        let mut state = block_input(i, &entry, &top, &predecessors, &outputs);
+        transfer(&mut state, &events[i], |state, local, span| {
+            if reported[local] || state.definitely[local] {
+                return;
+            }
+            reported[local] = true;
+
+            let certainty = if state.possibly[local] {
+                "may be"
+            } else {
+                "is"
+            };
+            diagnostics.warn(
+                Warning::Uninitialized,
+                span,
+                format!(
+                    "variable '{}' {} uninitialized when used here",
+                    function.locals[local].0, certainty
+                ),
+            );
+        });
+    }
+}
+
+// The state at the start of a block: the meet of the states at the end of
+// its predecessors.
+fn block_input(
+    block: usize,
+    entry: &Assigned,
+    top: &Assigned,
+    predecessors: &[Vec<usize>],
+    outputs: &[Assigned],
+) -> Assigned {
+    if block == ENTRY {
+        return entry.clone();
+    }
+
+    let mut state = top.clone();
+    for &predecessor in &predecessors[block] {
+        let output = &outputs[predecessor];
+        for local in 0..state.definitely.len() {
+            state.definitely[local] &= output.definitely[local];
+            state.possibly[local] |= output.possibly[local];
+        }
+    }
+    state
+}
+
+// Apply the events of a block to the state at its start, calling the given
+// function with the state before each read.
+fn transfer<F>(state: &mut Assigned, events: &[Event], mut on_read: F)
+where
+    F: FnMut(&Assigned, usize, Span),
+{
+    let mut branches = Vec::new();
+    for event in events {
+        match *event {
+            Event::Read(local, span) => on_read(state, local, span),
+            Event::Write(local) | Event::Initialize(local) => {
+                state.definitely[local] = true;
+                state.possibly[local] = true;
+            }
+            Event::Declare(local) => {
+                state.definitely[local] = false;
+                state.possibly[local] = false;
+            }
+            Event::Escape(_) => {}
+            Event::BeginBranch => branches.push(state.definitely.clone()),
+            Event::EndBranch => state.definitely = branches.pop().expect("unbalanced branch"),
+        }
+    }
+}
+
+fn statement_events(statement: &Stmt, events: &mut Vec<Event>) {
+    match statement.kind {
+        StmtKind::Declaration(local, ref ty, ref initializers) => {
+            if let Some(ref initializers) = *initializers {
+                for initializer in initializers {
+                    expr_events(&initializer.value, events);
+                }
+            }
+
+            if ty.is_scalar() {
+                events.push(match *initializers {
+                    Some(_) => Event::Initialize(local),
+                    None => Event::Declare(local),
+                });
+            }
+        }
+        StmtKind::Expression(ref expr)
+        | StmtKind::Return(Some(ref expr))
+        | StmtKind::Switch(ref expr, ..) => expr_events(expr, events),
+        _ => {}
+    }
+}
+
+// The events of an expression, in the order in which it's evaluated. Only
+// scalar variables are tracked, as the parts of aggregates can be set one
+// at a time.
+fn expr_events(expr: &Expr, events: &mut Vec<Event>) {
+    match expr.kind {
+        ExprKind::Load(ref object) => match scalar_local(object) {
+            Some(local) => events.push(Event::Read(local, expr.span)),
+            None => expr_events(object, events),
+        },
+        ExprKind::Assign(ref target, ref value) => {
+            expr_events(value, events);
+            match scalar_local(target) {
+                Some(local) => events.push(Event::Write(local)),
+                None => expr_events(target, events),
+            }
+        }
+        ExprKind::Object(Storage::Local(local)) if expr.ty.is_scalar() => {
+            events.push(Event::Escape(local));
+        }
+        ExprKind::BinaryOp(BinaryOperator::And, ref expr1, ref expr2)
+        | ExprKind::BinaryOp(BinaryOperator::Or, ref expr1, ref expr2) => {
+            expr_events(expr1, events);
+            events.push(Event::BeginBranch);
+            expr_events(expr2, events);
+            events.push(Event::EndBranch);
+        }
+        ExprKind::IntConstant(_)
+        | ExprKind::FloatConstant(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::Object(_)
+        | ExprKind::Function(_) => {}
+        ExprKind::Dereference(ref operand)
+        | ExprKind::Member(ref operand, _)
+        | ExprKind::Decay(ref operand)
+        | ExprKind::Conversion(ref operand)
+        | ExprKind::AddressOf(ref operand)
+        | ExprKind::UnaryOp(_, ref operand)
+        | ExprKind::VaStart(ref operand)
+        | ExprKind::VaArg(ref operand)
+        | ExprKind::VaEnd(ref operand) => expr_events(operand, events),
+        ExprKind::BinaryOp(_, ref expr1, ref expr2)
+        | ExprKind::PointerArithmetic(_, ref expr1, ref expr2)
+        | ExprKind::PointerDifference(ref expr1, ref expr2)
+        | ExprKind::VaCopy(ref expr1, ref expr2) => {
+            expr_events(expr1, events);
+            expr_events(expr2, events);
+        }
+        ExprKind::Call(ref callee, ref args) => {
+            expr_events(callee, events);
+            for arg in args {
+                expr_events(arg, events);
+            }
+        }
+    }
+}
+
+// The index of the scalar automatic variable that an lvalue designates, if
+// that's what it is.
+fn scalar_local(expr: &Expr) -> Option<usize> {
+    match expr.kind {
+        ExprKind::Object(Storage::Local(local)) if expr.ty.is_scalar() => Some(local),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diagnostics::{Diagnostics, Options, Warning};
+    use lexer::Lexer;
+    use parser::Parser;
+    use preprocessor::Preprocessor;
+    use semantic;
+    use std::path::Path;
+
+    // The warnings from this pass about a translation unit, with the lines
+    // they're on.
+    fn warnings(source: &str) -> Vec<(usize, String)> {
+        let output = Preprocessor::new(Vec::new())
+            .preprocess(Path::new("test.c"), source)
+            .expect("preprocessing failed");
+        let mut options = Options::default();
+        options.apply("-Wall");
+        let mut diagnostics = Diagnostics::new(options, output.origins);
+        let ast = Parser::new(Lexer::new(&output.text), &mut diagnostics)
+            .parse()
+            .expect("parsing failed");
+        semantic::analyze(&ast, &mut diagnostics).expect("analysis failed");
+        diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                matches!(
+                    diagnostic.warning,
+                    Some(Warning::Uninitialized | Warning::UnusedButSetVariable)
+                )
+            })
+            .map(|diagnostic| {
+                let line = diagnostic
+                    .location
+                    .as_ref()
+                    .map_or(0, |location| location.line);
+                (line, diagnostic.message.clone())
+            })
+            .collect()
+    }
+
+    fn uninitialized(line: usize, name: &str, certainty: &str) -> (usize, String) {
+        let message = format!(
+            "variable '{}' {} uninitialized when used here",
+            name, certainty
+        );
+        (line, message)
+    }
+
+    #[test]
+    fn read_before_any_assignment() {
+        let source = "int f(void) {\n    int x;\n    return x;\n}\n";
+        assert_eq!(warnings(source), [uninitialized(3, "x", "is")]);
+    }
+
+    #[test]
+    fn only_the_first_read_is_reported() {
+        let source = "int f(void) {\n    int x;\n    int y = x;\n    return x + y;\n}\n";
+        assert_eq!(warnings(source), [uninitialized(3, "x", "is")]);
+    }
+
+    #[test]
+    fn parameters_are_initialized() {
+        assert_eq!(warnings("int f(int a) {\n    return a;\n}\n"), []);
+    }
+
+    #[test]
+    fn assignment_in_second_operand_of_and() {
+        let source = "int f(int a) {\n    int x;\n    a && (x = 1);\n    return x;\n}\n";
+        assert_eq!(warnings(source), [uninitialized(4, "x", "may be")]);
+
+        let source = "int f(int a) {\n    int x;\n    a || (x = 1);\n    return x;\n}\n";
+        assert_eq!(warnings(source), [uninitialized(4, "x", "may be")]);
+    }
+
+    #[test]
+    fn assignment_in_first_operand_of_and() {
+        let source = "int f(int a) {\n    int x;\n    (x = 1) && a;\n    return x;\n}\n";
+        assert_eq!(warnings(source), []);
+    }
+
+    #[test]
+    fn switch_with_and_without_default() {
+        let source = "int f(int a) {
+    int x;
+    switch (a) {
+    case 1:
+        x = 1;
+        break;
+    default:
+        x = 2;
+    }
+    return x;
+}
+";
+        assert_eq!(warnings(source), []);
+
+        let source = "int f(int a) {
+    int x;
+    switch (a) {
+    case 1:
+        x = 1;
+    }
+    return x;
+}
+";
+        assert_eq!(warnings(source), [uninitialized(7, "x", "may be")]);
+    }
+
+    #[test]
+    fn goto_over_an_assignment() {
+        let source = "int f(void) {
+    int x;
+    goto done;
+    x = 1;
+done:
+    return x;
+}
+";
+        assert_eq!(warnings(source), [uninitialized(6, "x", "is")]);
+    }
+
+    #[test]
+    fn loop_with_goto_only_leaves_after_assigning() {
+        let source = "int f(int a) {
+    int x;
+again:
+    switch (a) {
+    case 0:
+        x = 1;
+        goto out;
+    }
+    a = 0;
+    goto again;
+out:
+    return x;
+}
+";
+        assert_eq!(warnings(source), []);
+    }
+
+    #[test]
+    fn escaped_variables_are_not_tracked() {
+        let source = "int f(void) {\n    int x;\n    int *p = &x;\n    *p = 1;\n    return x;\n}\n";
+        assert_eq!(warnings(source), []);
+    }
+
+    #[test]
+    fn set_but_not_used() {
+        let source = "int f(void) {\n    int x;\n    x = 1;\n    return 0;\n}\n";
+        assert_eq!(
+            warnings(source),
+            [(2, "variable 'x' set but not used".to_owned())]
+        );
+    }
+}
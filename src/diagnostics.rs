@@ -0,0 +1,856 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+
+use lexer::Span;
+use source_map::OriginMap;
+
+// A warning that can be turned on and off by name with -W and -Wno-.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Warning {
+    UnusedVariable,
+    ReturnType,
+    ImplicitIntConversion,
+    DivByZero,
+    Shadow,
+    Parentheses,
+    UnreachableCode,
+    Uninitialized,
+    UnusedButSetVariable,
+    IntegerOverflow,
+    ShiftCountNegative,
+    ShiftCountOverflow,
+    IncompatiblePointerTypes,
+    IncompatiblePointerTypesDiscardsQualifiers,
+    IntConversion,
+}
+
+const WARNINGS: [Warning; 15] = [
+    Warning::UnusedVariable,
+    Warning::ReturnType,
+    Warning::ImplicitIntConversion,
+    Warning::DivByZero,
+    Warning::Shadow,
+    Warning::Parentheses,
+    Warning::UnreachableCode,
+    Warning::Uninitialized,
+    Warning::UnusedButSetVariable,
+    Warning::IntegerOverflow,
+    Warning::ShiftCountNegative,
+    Warning::ShiftCountOverflow,
+    Warning::IncompatiblePointerTypes,
+    Warning::IncompatiblePointerTypesDiscardsQualifiers,
+    Warning::IntConversion,
+];
+
+impl Warning {
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused-variable",
+            Warning::ReturnType => "return-type",
+            Warning::ImplicitIntConversion => "implicit-int-conversion",
+            Warning::DivByZero => "div-by-zero",
+            Warning::Shadow => "shadow",
+            Warning::Parentheses => "parentheses",
+            Warning::UnreachableCode => "unreachable-code",
+            Warning::Uninitialized => "uninitialized",
+            Warning::UnusedButSetVariable => "unused-but-set-variable",
+            Warning::IntegerOverflow => "integer-overflow",
+            Warning::ShiftCountNegative => "shift-count-negative",
+            Warning::ShiftCountOverflow => "shift-count-overflow",
+            Warning::IncompatiblePointerTypes => "incompatible-pointer-types",
+            Warning::IncompatiblePointerTypesDiscardsQualifiers => {
+                "incompatible-pointer-types-discards-qualifiers"
+            }
+            Warning::IntConversion => "int-conversion",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        WARNINGS
+            .iter()
+            .cloned()
+            .find(|warning| warning.name() == name)
+    }
+
+    // The group that turns the warning on: warnings that are almost always
+    // bugs are on by default, -Wall adds the ones that flag questionable
+    // code, and -Wextra the ones that also fire on code that is often fine.
+    fn group(self) -> Group {
+        match self {
+            Warning::ReturnType
+            | Warning::DivByZero
+            | Warning::IntegerOverflow
+            | Warning::ShiftCountNegative
+            | Warning::ShiftCountOverflow
+            | Warning::IncompatiblePointerTypes
+            | Warning::IncompatiblePointerTypesDiscardsQualifiers
+            | Warning::IntConversion => Group::Default,
+            Warning::UnusedVariable
+            | Warning::Parentheses
+            | Warning::UnreachableCode
+            | Warning::Uninitialized
+            | Warning::UnusedButSetVariable => Group::All,
+            Warning::ImplicitIntConversion | Warning::Shadow => Group::Extra,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Group {
+    Default,
+    All,
+    Extra,
+}
+
+// The warnings that are enabled, as set by the -W options in the order in
+// which they're given.
+#[derive(Debug)]
+pub struct Options {
+    enabled: HashSet<Warning>,
+    // Whether warnings are turned into errors.
+    errors: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            enabled: enable_groups(Group::Default),
+            errors: false,
+        }
+    }
+}
+
+impl Options {
+    // Apply a -W option, e.g. `-Wall` or `-Wno-shadow`. Returns false if it
+    // isn't a known option.
+    pub fn apply(&mut self, option: &str) -> bool {
+        let name = match option.strip_prefix("-W") {
+            Some(name) => name,
+            None => return false,
+        };
+
+        match name {
+            "all" => self.enabled.extend(enable_groups(Group::All)),
+            "extra" => self.enabled.extend(enable_groups(Group::Extra)),
+            "error" => self.errors = true,
+            "no-error" => self.errors = false,
+            _ => match name.strip_prefix("no-") {
+                Some(name) => match Warning::from_name(name) {
+                    Some(warning) => {
+                        self.enabled.remove(&warning);
+                    }
+                    None => return false,
+                },
+                None => match Warning::from_name(name) {
+                    Some(warning) => {
+                        self.enabled.insert(warning);
+                    }
+                    None => return false,
+                },
+            },
+        }
+
+        true
+    }
+}
+
+// The warnings in a group and the groups it includes.
+fn enable_groups(group: Group) -> HashSet<Warning> {
+    WARNINGS
+        .iter()
+        .cloned()
+        .filter(|warning| warning.group() <= group)
+        .collect()
+}
+
+// How diagnostics are written out: as text for people to read, or as one
+// JSON object per line for tools, selected with --diagnostics-format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// Where in the source a diagnostic applies.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    // The columns of the line, counting bytes from 1, that the diagnostic
+    // is about, if they're known.
+    pub columns: Option<Range<usize>>,
+    // The text of the line, which is shown under the message.
+    pub source_line: Option<String>,
+}
+
+// Where a fix-it applies in a file as it's written: the line and columns
+// it spans, as in a Location, and the byte offsets of the same text, which
+// is where --fixit edits the file. The range of an insertion is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRange {
+    pub file: String,
+    pub line: usize,
+    pub columns: Range<usize>,
+    pub offsets: Range<usize>,
+}
+
+// A change to the source that would fix what a diagnostic reports, at a
+// range of the source unless it's somewhere that can't be edited, like a
+// macro expansion.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixIt {
+    // Insert text where something was expected, e.g. a missing `;`.
+    Insert(Option<SourceRange>, String),
+    // Replace a misspelled name with the one it's probably meant to be.
+    Replace(Option<SourceRange>, String, String),
+}
+
+impl FixIt {
+    pub fn range(&self) -> Option<&SourceRange> {
+        match *self {
+            FixIt::Insert(ref range, _) | FixIt::Replace(ref range, _, _) => range.as_ref(),
+        }
+    }
+
+    // The text that the fix-it puts in place of its range.
+    pub fn text(&self) -> &str {
+        match *self {
+            FixIt::Insert(_, ref text) | FixIt::Replace(_, _, ref text) => text,
+        }
+    }
+}
+
+impl fmt::Display for FixIt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FixIt::Insert(_, ref text) => write!(f, "insert '{}'", text)?,
+            FixIt::Replace(_, ref original, ref text) => {
+                write!(f, "replace '{}' with '{}'", original, text)?
+            }
+        }
+        match self.range() {
+            Some(range) => write!(f, " at {}:{}", range.line, range.columns.start),
+            None => Ok(()),
+        }
+    }
+}
+
+// An error that stops the compilation, found at a span of the preprocessed
+// text if it's known where it is, with notes about other places in it.
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<(Span, String)>,
+}
+
+impl Error {
+    pub fn at(message: String, span: Span) -> Self {
+        Error {
+            message,
+            span: Some(span),
+            notes: Vec::new(),
+        }
+    }
+
+    // Place the error at a span, unless it's already placed more precisely.
+    pub fn or_at(mut self, span: Span) -> Self {
+        self.span = self.span.or(Some(span));
+        self
+    }
+
+    pub fn with_note(mut self, span: Span, message: String) -> Self {
+        self.notes.push((span, message));
+        self
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error {
+            message,
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // The warning that the diagnostic was reported as, if it's one.
+    pub warning: Option<Warning>,
+    pub location: Option<Location>,
+    pub message: String,
+    // Notes that point out other places related to the diagnostic, or say
+    // more about it, in the order they're shown.
+    pub notes: Vec<(Location, String)>,
+    pub fixits: Vec<FixIt>,
+}
+
+impl Diagnostic {
+    pub fn error(message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            warning: None,
+            location: None,
+            message,
+            notes: Vec::new(),
+            fixits: Vec::new(),
+        }
+    }
+
+    pub fn with_fixits(mut self, fixits: Vec<FixIt>) -> Self {
+        self.fixits = fixits;
+        self
+    }
+
+    pub fn at(mut self, location: Location) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    // The diagnostic as it's written out. Text is colored like clang's if
+    // `color` is set, which JSON never is.
+    pub fn render(&self, format: Format, color: bool) -> String {
+        match format {
+            Format::Text => self.to_text(color),
+            Format::Json => self.to_json(),
+        }
+    }
+
+    // Diagnostics are printed like clang's, e.g.
+    // `file.c:3:5: warning: unused variable 'x' [-Wunused-variable]`. The
+    // line of source that it's about follows, with a caret under the start
+    // of what's wrong and the rest of it underlined, and then any notes,
+    // which are shown the same way, and fix-its on lines of their own.
+    fn to_text(&self, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", style, text)
+            } else {
+                text.to_owned()
+            }
+        };
+
+        let (severity, style) = match self.severity {
+            Severity::Warning => ("warning:", WARNING_COLOR),
+            Severity::Error => ("error:", ERROR_COLOR),
+        };
+        let mut message = self.message.clone();
+        match self.warning {
+            Some(warning) if self.severity == Severity::Error => {
+                message.push_str(&format!(" [-Werror,-W{}]", warning.name()))
+            }
+            Some(warning) => message.push_str(&format!(" [-W{}]", warning.name())),
+            None => {}
+        }
+
+        let mut text = String::new();
+        write_message(
+            &mut text,
+            self.location.as_ref(),
+            (severity, style),
+            &message,
+            paint,
+        );
+        for (location, note) in &self.notes {
+            text.push('\n');
+            write_message(
+                &mut text,
+                Some(location),
+                ("note:", NOTE_COLOR),
+                note,
+                paint,
+            );
+        }
+        for fixit in &self.fixits {
+            text.push_str(&format!("\n{} {}", paint(FIXIT_COLOR, "fix-it:"), fixit));
+        }
+
+        text
+    }
+
+    // The diagnostic as a JSON object, e.g.
+    // `{"severity":"warning","code":"unused-variable","message":"unused
+    // variable 'x'","file":"a.c","ranges":[...],"notes":[],"fixits":[]}`.
+    // Each note is an object with its own "message", "file" and "ranges".
+    // Columns are null where they aren't known. Each fix-it has the "range"
+    // of the file that it changes, with its own "file", or null if it can't
+    // be applied, and "original" is the text it replaces.
+    fn to_json(&self) -> String {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let code = match self.warning {
+            Some(warning) => json_string(warning.name()),
+            None => "null".to_owned(),
+        };
+
+        let notes = self
+            .notes
+            .iter()
+            .map(|(location, note)| {
+                let (file, ranges) = json_location(Some(location));
+                format!(
+                    "{{\"message\":{},\"file\":{},\"ranges\":{}}}",
+                    json_string(note),
+                    file,
+                    ranges
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let (file, ranges) = json_location(self.location.as_ref());
+
+        let fixits = self
+            .fixits
+            .iter()
+            .map(|fixit| {
+                let (original, text) = match *fixit {
+                    FixIt::Insert(_, ref text) => ("null".to_owned(), text),
+                    FixIt::Replace(_, ref original, ref text) => (json_string(original), text),
+                };
+                let range = fixit.range().map_or("null".to_owned(), |range| {
+                    let columns = json_range(range.line, Some(&range.columns));
+                    format!("{{\"file\":{},{}}}", json_string(&range.file), columns)
+                });
+                format!(
+                    "{{\"range\":{},\"original\":{},\"text\":{}}}",
+                    range,
+                    original,
+                    json_string(text)
+                )
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"file\":{},\"ranges\":{},\
+             \"notes\":[{}],\"fixits\":[{}]}}",
+            severity,
+            code,
+            json_string(&self.message),
+            file,
+            ranges,
+            notes.join(","),
+            fixits.join(",")
+        )
+    }
+}
+
+// The diagnostics reported while compiling a translation unit, which know
+// where the tokens of its preprocessed text came from.
+pub struct Diagnostics {
+    options: Options,
+    origins: OriginMap,
+    // The diagnostics in the order of the spans that they're about, which
+    // isn't the order that the passes find them in.
+    diagnostics: Vec<(usize, Diagnostic)>,
+    // The fix-its for the error that stopped the compilation, which is
+    // reported by whoever stopped it.
+    fixits: Vec<FixIt>,
+}
+
+impl Diagnostics {
+    pub fn new(options: Options, origins: OriginMap) -> Self {
+        Diagnostics {
+            options,
+            origins,
+            diagnostics: Vec::new(),
+            fixits: Vec::new(),
+        }
+    }
+
+    // Report a warning at a span, unless it's turned off. With -Werror, it's
+    // reported as an error instead.
+    pub fn warn(&mut self, warning: Warning, span: Span, message: String) {
+        self.warn_with_notes(warning, span, message, Vec::new());
+    }
+
+    pub fn warn_with_notes(
+        &mut self,
+        warning: Warning,
+        span: Span,
+        message: String,
+        notes: Vec<(Span, String)>,
+    ) {
+        if !self.options.enabled.contains(&warning) {
+            return;
+        }
+
+        let severity = if self.options.errors {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        let diagnostic = Diagnostic {
+            severity,
+            warning: Some(warning),
+            location: Some(self.locate(span)),
+            message,
+            notes: self.locate_notes(notes),
+            fixits: Vec::new(),
+        };
+        let index = self
+            .diagnostics
+            .partition_point(|&(start, _)| start <= span.start);
+        self.diagnostics.insert(index, (span.start, diagnostic));
+    }
+
+    // Where a span of the preprocessed text was written.
+    fn locate(&self, span: Span) -> Location {
+        self.origins.locate(span)
+    }
+
+    // Suggest inserting text at an empty span, or replacing the text at a
+    // span, to fix the error that's about to stop the compilation.
+    pub fn suggest_insertion(&mut self, span: Span, text: &str) {
+        let range = self.origins.source_range(span);
+        self.fixits.push(FixIt::Insert(range, text.to_owned()));
+    }
+
+    pub fn suggest_replacement(&mut self, span: Span, original: &str, text: &str) {
+        let range = self.origins.source_range(span);
+        self.fixits
+            .push(FixIt::Replace(range, original.to_owned(), text.to_owned()));
+    }
+
+    pub fn take_fixits(&mut self) -> Vec<FixIt> {
+        mem::take(&mut self.fixits)
+    }
+
+    // The diagnostic for the error that stopped the compilation, with the
+    // fix-its suggested for it.
+    pub fn error(&mut self, error: Error) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(error.message).with_fixits(self.take_fixits());
+        if let Some(span) = error.span {
+            diagnostic = diagnostic.at(self.locate(span));
+        }
+        diagnostic.notes = self.locate_notes(error.notes);
+        diagnostic
+    }
+
+    fn locate_notes(&self, notes: Vec<(Span, String)>) -> Vec<(Location, String)> {
+        notes
+            .into_iter()
+            .map(|(span, note)| (self.locate(span), note))
+            .collect()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().map(|(_, diagnostic)| diagnostic)
+    }
+}
+
+// The candidate that a misspelled name was most likely meant to be, for a
+// "did you mean" suggestion: the closest one by edit distance, as long as
+// no more than a third of the name has to change. Ties go to the candidate
+// that sorts first, so the suggestion doesn't depend on the order given.
+pub fn closest_match<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = name.chars().count().div_ceil(3);
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+// The number of characters that have to be inserted, deleted or replaced,
+// or pairs of adjacent ones swapped, to turn one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // The distances between the prefixes of a and those of b, a row per
+    // prefix of a; only the last two rows are needed for the next one.
+    let mut previous: Vec<usize> = Vec::new();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for i in 1..=a.len() {
+        let mut next = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            next[j] = (row[j] + 1).min(next[j - 1] + 1).min(row[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                next[j] = next[j].min(previous[j - 2] + 1);
+            }
+        }
+        previous = row;
+        row = next;
+    }
+    row[b.len()]
+}
+
+// The escape sequences that color text the way clang does.
+const BOLD: &str = "1";
+const ERROR_COLOR: &str = "1;31";
+const WARNING_COLOR: &str = "1;35";
+const NOTE_COLOR: &str = "1;30";
+const FIXIT_COLOR: &str = "32";
+const CARET_COLOR: &str = "1;32";
+
+// The line under a line of source that marks some of its columns, with a
+// caret at the first and tildes under the rest. Tabs before them are kept so
+// that they line up on screen.
+fn underline<F>(source_line: &str, columns: &Range<usize>, paint: F) -> String
+where
+    F: Fn(&str, &str) -> String,
+{
+    let indent = source_line
+        .bytes()
+        .take(columns.start - 1)
+        .filter(|&byte| byte & 0xc0 != 0x80)
+        .map(|byte| if byte == b'\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let marks = format!("^{}", "~".repeat(columns.len().saturating_sub(1)));
+    indent + &paint(CARET_COLOR, &marks)
+}
+
+// Write a message with its severity, after where it is and followed by the
+// line of source it's about, if those are known.
+fn write_message<F>(
+    text: &mut String,
+    location: Option<&Location>,
+    (severity, style): (&str, &str),
+    message: &str,
+    paint: F,
+) where
+    F: Fn(&str, &str) -> String,
+{
+    if let Some(location) = location {
+        let position = match location.columns {
+            Some(ref columns) => format!("{}:{}:{}:", location.file, location.line, columns.start),
+            None => format!("{}:{}:", location.file, location.line),
+        };
+        text.push_str(&paint(BOLD, &position));
+        text.push(' ');
+    }
+
+    text.push_str(&paint(style, severity));
+    text.push(' ');
+    text.push_str(&paint(BOLD, message));
+
+    if let Some(&Location {
+        source_line: Some(ref source_line),
+        ref columns,
+        ..
+    }) = location
+    {
+        text.push('\n');
+        text.push_str(source_line);
+        if let Some(ref columns) = *columns {
+            text.push('\n');
+            text.push_str(&underline(source_line, columns, &paint));
+        }
+    }
+}
+
+// The "file" and "ranges" of a JSON diagnostic or note at a location.
+fn json_location(location: Option<&Location>) -> (String, String) {
+    let location = match location {
+        Some(location) => location,
+        None => return ("null".to_owned(), "[]".to_owned()),
+    };
+
+    let range = json_range(location.line, location.columns.as_ref());
+    (json_string(&location.file), format!("[{{{}}}]", range))
+}
+
+// The "start" and "end" members of a JSON object for a range of columns on
+// a line.
+fn json_range(line: usize, columns: Option<&Range<usize>>) -> String {
+    let position = |column: Option<usize>| {
+        let column = column.map_or("null".to_owned(), |column| column.to_string());
+        format!("{{\"line\":{},\"column\":{}}}", line, column)
+    };
+    format!(
+        "\"start\":{},\"end\":{}",
+        position(columns.map(|columns| columns.start)),
+        position(columns.map(|columns| columns.end))
+    )
+}
+
+// A string as a JSON string literal.
+fn json_string(string: &str) -> String {
+    let mut json = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(line: usize, columns: Range<usize>) -> Location {
+        Location {
+            file: "test.c".to_owned(),
+            line,
+            columns: Some(columns),
+            source_line: None,
+        }
+    }
+
+    fn span(start: usize, line: usize) -> Span {
+        Span {
+            start,
+            end: start + 1,
+            line,
+            column: 1,
+        }
+    }
+
+    #[test]
+    fn json_string_escaping() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(json_string(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"a\b"), r#""a\\b""#);
+        assert_eq!(json_string("line\nnext\ttab"), r#""line\nnext\ttab""#);
+        assert_eq!(json_string("\r\u{1}\u{1f}"), r#""\u000d\u0001\u001f""#);
+        assert_eq!(json_string("caf\u{e9} \u{7f}"), "\"caf\u{e9} \u{7f}\"");
+    }
+
+    #[test]
+    fn json_error() {
+        let diagnostic = Diagnostic::error("expected ';'".to_owned()).at(location(3, 5..6));
+        assert_eq!(
+            diagnostic.render(Format::Json, false),
+            concat!(
+                r#"{"severity":"error","code":null,"message":"expected ';'","file":"test.c","#,
+                r#""ranges":[{"start":{"line":3,"column":5},"end":{"line":3,"column":6}}],"#,
+                r#""notes":[],"fixits":[]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn json_without_location() {
+        let diagnostic = Diagnostic::error("no input files".to_owned());
+        assert_eq!(
+            diagnostic.render(Format::Json, false),
+            concat!(
+                r#"{"severity":"error","code":null,"message":"no input files","file":null,"#,
+                r#""ranges":[],"notes":[],"fixits":[]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn json_notes_and_fixits() {
+        let range = SourceRange {
+            file: "test.c".to_owned(),
+            line: 2,
+            columns: 1..4,
+            offsets: 10..13,
+        };
+        let mut diagnostic = Diagnostic::error("use of undeclared identifier 'fo'".to_owned())
+            .at(location(2, 1..3))
+            .with_fixits(vec![
+                FixIt::Replace(Some(range), "fo".to_owned(), "foo".to_owned()),
+                FixIt::Insert(None, ";".to_owned()),
+            ]);
+        diagnostic.notes = vec![(location(1, 5..8), "'foo' declared here".to_owned())];
+        assert_eq!(
+            diagnostic.render(Format::Json, false),
+            concat!(
+                r#"{"severity":"error","code":null,"message":"use of undeclared identifier 'fo'","#,
+                r#""file":"test.c","#,
+                r#""ranges":[{"start":{"line":2,"column":1},"end":{"line":2,"column":3}}],"#,
+                r#""notes":[{"message":"'foo' declared here","file":"test.c","#,
+                r#""ranges":[{"start":{"line":1,"column":5},"end":{"line":1,"column":8}}]}],"#,
+                r#""fixits":[{"range":{"file":"test.c","start":{"line":2,"column":1},"#,
+                r#""end":{"line":2,"column":4}},"original":"fo","text":"foo"},"#,
+                r#"{"range":null,"original":null,"text":";"}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn json_warning_has_its_option_as_code() {
+        let mut diagnostics = Diagnostics::new(Options::default(), OriginMap::new());
+        diagnostics.warn(
+            Warning::DivByZero,
+            span(0, 1),
+            "division by zero is undefined".to_owned(),
+        );
+        let json = diagnostics
+            .iter()
+            .next()
+            .unwrap()
+            .render(Format::Json, false);
+        assert!(json.starts_with(r#"{"severity":"warning","code":"div-by-zero","#));
+    }
+
+    #[test]
+    fn warnings_are_kept_in_source_order() {
+        let mut diagnostics = Diagnostics::new(Options::default(), OriginMap::new());
+        diagnostics.warn(Warning::DivByZero, span(20, 3), "third".to_owned());
+        diagnostics.warn(Warning::DivByZero, span(5, 1), "first".to_owned());
+        diagnostics.warn(Warning::DivByZero, span(10, 2), "second".to_owned());
+        diagnostics.warn(Warning::DivByZero, span(10, 2), "also second".to_owned());
+        let messages = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["first", "second", "also second", "third"]);
+    }
+
+    #[test]
+    fn warning_options() {
+        let mut options = Options::default();
+        assert!(options.apply("-Wall"));
+        assert!(options.apply("-Wno-div-by-zero"));
+        assert!(!options.apply("-Wbogus"));
+        assert!(options.apply("-Werror"));
+
+        let mut diagnostics = Diagnostics::new(options, OriginMap::new());
+        diagnostics.warn(Warning::DivByZero, span(0, 1), "off".to_owned());
+        assert!(!diagnostics.has_errors());
+        diagnostics.warn(Warning::UnusedVariable, span(0, 1), "on".to_owned());
+        assert!(diagnostics.has_errors());
+    }
+
+    #[test]
+    fn closest_match_suggestions() {
+        let candidates = ["count", "counter", "total"];
+        assert_eq!(closest_match("cuont", candidates), Some("count"));
+        assert_eq!(closest_match("totl", candidates), Some("total"));
+        assert_eq!(closest_match("xyz", candidates), None);
+        assert_eq!(closest_match("count", candidates), Some("counter"));
+    }
+}
@@ -0,0 +1,390 @@
+use constant;
+use diagnostics::{Diagnostics, Warning};
+use lexer::Span;
+use parser::{BinaryOperator, UnaryOperator};
+use semantic::{Expr, ExprKind, Program, Stmt, StmtKind};
+use std::mem;
+use types::Type;
+
+// Replace the integer expressions in a program whose operands are all
+// constants with their values, warning about the ones whose evaluation is
+// undefined. Only constants are folded, so volatile objects are always
+// read and written as the program says.
+pub fn fold_program(program: &mut Program, diagnostics: &mut Diagnostics) {
+    let mut folder = Folder { diagnostics };
+    for function in &mut program.functions {
+        for statement in &mut function.body {
+            folder.fold_statement(statement);
+        }
+    }
+}
+
+// Fold an expression outside of any function, such as the initializer of an
+// object with static storage duration.
+pub fn fold_expression(expr: &mut Expr, diagnostics: &mut Diagnostics) {
+    Folder { diagnostics }.fold_expr(expr);
+}
+
+struct Folder<'a> {
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a> Folder<'a> {
+    fn fold_statement(&mut self, statement: &mut Stmt) {
+        match statement.kind {
+            StmtKind::Block(ref mut statements) => {
+                for statement in statements {
+                    self.fold_statement(statement);
+                }
+            }
+            StmtKind::Declaration(_, _, Some(ref mut initializers)) => {
+                for initializer in initializers {
+                    self.fold_expr(&mut initializer.value);
+                }
+            }
+            StmtKind::Expression(ref mut expr) | StmtKind::Return(Some(ref mut expr)) => {
+                self.fold_expr(expr)
+            }
+            StmtKind::Switch(ref mut expr, _, ref mut body) => {
+                self.fold_expr(expr);
+                self.fold_statement(body);
+            }
+            StmtKind::Case(_, ref mut statement) | StmtKind::Label(_, ref mut statement) => {
+                self.fold_statement(statement)
+            }
+            StmtKind::Declaration(_, _, None)
+            | StmtKind::Return(None)
+            | StmtKind::Break
+            | StmtKind::Goto(_) => {}
+        }
+    }
+
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        let value = match expr.kind {
+            ExprKind::Conversion(ref mut operand) => {
+                self.fold_expr(operand);
+                match constant_value(operand) {
+                    Some(value) if expr.ty.is_integer() => Some(expr.ty.wrap(value)),
+                    _ => None,
+                }
+            }
+            ExprKind::UnaryOp(operator, ref mut operand) => {
+                self.fold_expr(operand);
+                match constant_value(operand) {
+                    Some(value) if expr.ty.is_integer() => match operator {
+                        UnaryOperator::Minus => {
+                            let operator = BinaryOperator::Minus;
+                            Some(self.arithmetic(operator, &expr.ty, 0, value, expr.span))
+                        }
+                        UnaryOperator::Tilde => Some(expr.ty.wrap(!value)),
+                        UnaryOperator::Bang => Some((value == 0) as i64),
+                    },
+                    _ => None,
+                }
+            }
+            // The second operand of && and || isn't evaluated, and so not
+            // folded either, if the first already decides the result.
+            ExprKind::BinaryOp(operator @ BinaryOperator::And, ref mut expr1, ref mut expr2)
+            | ExprKind::BinaryOp(operator @ BinaryOperator::Or, ref mut expr1, ref mut expr2) => {
+                self.fold_expr(expr1);
+                match constant_value(expr1) {
+                    Some(lhs) if (lhs != 0) == (operator == BinaryOperator::Or) => {
+                        Some((lhs != 0) as i64)
+                    }
+                    Some(_) => {
+                        self.fold_expr(expr2);
+                        constant_value(expr2).map(|rhs| (rhs != 0) as i64)
+                    }
+                    None => {
+                        self.fold_expr(expr2);
+                        None
+                    }
+                }
+            }
+            ExprKind::BinaryOp(operator, ref mut expr1, ref mut expr2) => {
+                self.fold_expr(expr1);
+                self.fold_expr(expr2);
+                self.fold_binary_op(operator, expr1, expr2)
+            }
+            ExprKind::IntConstant(_)
+            | ExprKind::FloatConstant(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Object(_)
+            | ExprKind::Function(_) => None,
+            ExprKind::Dereference(ref mut operand)
+            | ExprKind::Member(ref mut operand, _)
+            | ExprKind::Load(ref mut operand)
+            | ExprKind::Decay(ref mut operand)
+            | ExprKind::AddressOf(ref mut operand)
+            | ExprKind::VaStart(ref mut operand)
+            | ExprKind::VaArg(ref mut operand)
+            | ExprKind::VaEnd(ref mut operand) => {
+                self.fold_expr(operand);
+                None
+            }
+            ExprKind::PointerArithmetic(_, ref mut expr1, ref mut expr2)
+            | ExprKind::PointerDifference(ref mut expr1, ref mut expr2)
+            | ExprKind::Assign(ref mut expr1, ref mut expr2)
+            | ExprKind::VaCopy(ref mut expr1, ref mut expr2) => {
+                self.fold_expr(expr1);
+                self.fold_expr(expr2);
+                None
+            }
+            ExprKind::Call(ref mut callee, ref mut args) => {
+                self.fold_expr(callee);
+                for arg in args {
+                    self.fold_expr(arg);
+                }
+                None
+            }
+        };
+
+        if let Some(value) = value {
+            let ty = mem::replace(&mut expr.ty, Type::Int);
+            *expr = Expr {
+                kind: ExprKind::IntConstant(ty.wrap(value)),
+                ty,
+                span: expr.span,
+            };
+        }
+    }
+
+    // The value of an arithmetic operator or a comparison on integers. The
+    // operands have been converted to their common type, but the result of
+    // a comparison is an int.
+    fn fold_binary_op(
+        &mut self,
+        operator: BinaryOperator,
+        expr1: &Expr,
+        expr2: &Expr,
+    ) -> Option<i64> {
+        if !expr1.ty.is_integer() {
+            return None;
+        }
+
+        if let BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight = operator {
+            return self.fold_shift(operator, expr1, expr2);
+        }
+
+        let rhs = constant_value(expr2);
+        if operator == BinaryOperator::Divide && rhs == Some(0) {
+            self.warn(
+                Warning::DivByZero,
+                expr2.span,
+                "division by zero is undefined",
+            );
+            return None;
+        }
+
+        let span = expr1.span.to(expr2.span);
+        let (lhs, rhs) = (constant_value(expr1)?, rhs?);
+        match constant::arithmetic(operator, &expr1.ty, lhs, rhs) {
+            Ok(value) => Some(value),
+            // Overflowing division traps, so it's left for run time.
+            Err(_) if operator == BinaryOperator::Divide => {
+                self.report_overflow(&expr1.ty, expr1.ty.wrap(lhs.wrapping_div(rhs)), span);
+                None
+            }
+            Err(_) => Some(self.arithmetic(operator, &expr1.ty, lhs, rhs, span)),
+        }
+    }
+
+    // The value of a shift, whose count has a type of its own. A count that
+    // is out of range is warned about even if the value being shifted isn't
+    // a constant, and is left for run time.
+    fn fold_shift(&mut self, operator: BinaryOperator, expr1: &Expr, expr2: &Expr) -> Option<i64> {
+        let count = constant::widen(constant_value(expr2)?, &expr2.ty);
+        let width = expr1.ty.size()? as i128 * 8;
+        if count < 0 {
+            self.warn(
+                Warning::ShiftCountNegative,
+                expr2.span,
+                "shift count is negative",
+            );
+            return None;
+        }
+        if count >= width {
+            self.warn(
+                Warning::ShiftCountOverflow,
+                expr2.span,
+                "shift count >= width of type",
+            );
+            return None;
+        }
+
+        let lhs = constant_value(expr1)?;
+        match constant::shift(operator, &expr1.ty, lhs, count) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                let value = expr1.ty.wrap(lhs.wrapping_shl(count as u32));
+                self.report_overflow(&expr1.ty, value, expr1.span.to(expr2.span));
+                Some(value)
+            }
+        }
+    }
+
+    // Apply +, - or * to integers, which wraps around on overflow like the
+    // code generated for it would. An overflow is reported at the span of
+    // the expression.
+    fn arithmetic(
+        &mut self,
+        operator: BinaryOperator,
+        ty: &Type,
+        lhs: i64,
+        rhs: i64,
+        span: Span,
+    ) -> i64 {
+        if let Ok(value) = constant::arithmetic(operator, ty, lhs, rhs) {
+            return value;
+        }
+
+        let value = ty.wrap(match operator {
+            BinaryOperator::Plus => lhs.wrapping_add(rhs),
+            BinaryOperator::Minus => lhs.wrapping_sub(rhs),
+            _ => lhs.wrapping_mul(rhs),
+        });
+        self.report_overflow(ty, value, span);
+        value
+    }
+
+    fn report_overflow(&mut self, ty: &Type, value: i64, span: Span) {
+        let message = format!(
+            "overflow in expression; result is {} with type '{}'",
+            constant::format_value(value, ty),
+            ty
+        );
+        self.warn(Warning::IntegerOverflow, span, &message);
+    }
+
+    fn warn(&mut self, warning: Warning, span: Span, message: &str) {
+        self.diagnostics.warn(warning, span, message.to_owned());
+    }
+}
+
+fn constant_value(expr: &Expr) -> Option<i64> {
+    match expr.kind {
+        ExprKind::IntConstant(value) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostics::Options;
+    use lexer::Lexer;
+    use parser::Parser;
+    use preprocessor::Preprocessor;
+    use semantic::{self, Data};
+    use std::path::Path;
+
+    // A translation unit after folding, with the messages of the warnings
+    // about it.
+    fn fold(source: &str) -> (Program, Vec<String>) {
+        let output = Preprocessor::new(Vec::new())
+            .preprocess(Path::new("test.c"), source)
+            .expect("preprocessing failed");
+        let mut diagnostics = Diagnostics::new(Options::default(), output.origins);
+        let ast = Parser::new(Lexer::new(&output.text), &mut diagnostics)
+            .parse()
+            .expect("parsing failed");
+        let mut program = semantic::analyze(&ast, &mut diagnostics).expect("analysis failed");
+        fold_program(&mut program, &mut diagnostics);
+        let warnings = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect();
+        (program, warnings)
+    }
+
+    // The value that the first function returns, if it was folded into a
+    // constant, and the warnings.
+    fn fold_return(body: &str) -> (Option<i64>, Vec<String>) {
+        let (program, warnings) = fold(&format!("long f(long a) {{ return {}; }}", body));
+        let value = match program.functions[0].body[0].kind {
+            StmtKind::Return(Some(ref expr)) => constant_value(expr),
+            ref kind => panic!("expected a return, got {:?}", kind),
+        };
+        (value, warnings)
+    }
+
+    fn overflow(value: &str, ty: &str) -> String {
+        format!(
+            "overflow in expression; result is {} with type '{}'",
+            value, ty
+        )
+    }
+
+    #[test]
+    fn folds_constant_arithmetic() {
+        assert_eq!(fold_return("2 + 3 * 4"), (Some(14), vec![]));
+        assert_eq!(fold_return("-(7 / 2) + ~0 + !5"), (Some(-4), vec![]));
+        assert_eq!(fold_return("10 > 3 && 1 == 1"), (Some(1), vec![]));
+    }
+
+    #[test]
+    fn leaves_variables_alone() {
+        assert_eq!(fold_return("a + 1"), (None, vec![]));
+    }
+
+    #[test]
+    fn unsigned_arithmetic_wraps_silently() {
+        assert_eq!(fold_return("0u - 1"), (Some(u32::MAX as i64), vec![]));
+        assert_eq!(fold_return("4294967295u * 2u"), (Some(0xffff_fffe), vec![]));
+    }
+
+    #[test]
+    fn signed_overflow_wraps_with_a_warning() {
+        let (value, warnings) = fold_return("2147483647 + 1");
+        assert_eq!(value, Some(i32::MIN as i64));
+        assert_eq!(warnings, [overflow("-2147483648", "int")]);
+    }
+
+    #[test]
+    fn int_min_divided_by_minus_one_is_left_for_run_time() {
+        let (value, warnings) = fold_return("(-2147483647 - 1) / -1");
+        assert_eq!(value, None);
+        assert_eq!(warnings, [overflow("-2147483648", "int")]);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let (value, warnings) = fold_return("1 / 0");
+        assert_eq!(value, None);
+        assert_eq!(warnings, ["division by zero is undefined"]);
+    }
+
+    #[test]
+    fn unevaluated_operand_isnt_folded() {
+        assert_eq!(fold_return("0 && 1 / 0"), (Some(0), vec![]));
+        assert_eq!(fold_return("1 || 1 / 0"), (Some(1), vec![]));
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(fold_return("1 << 31"), (Some(i32::MIN as i64), vec![]));
+        assert_eq!(fold_return("-16 >> 2"), (Some(-4), vec![]));
+
+        let (value, warnings) = fold_return("1 << 32");
+        assert_eq!(value, None);
+        assert_eq!(warnings, ["shift count >= width of type"]);
+
+        let (value, warnings) = fold_return("a << -1");
+        assert_eq!(value, None);
+        assert_eq!(warnings, ["shift count is negative"]);
+    }
+
+    #[test]
+    fn conversions_wrap() {
+        assert_eq!(fold_return("(char)300"), (Some(44), vec![]));
+        assert_eq!(fold_return("(unsigned char)-1"), (Some(255), vec![]));
+    }
+
+    #[test]
+    fn static_initializer_overflow_wraps_with_a_warning() {
+        let (program, warnings) = fold("int g = 2147483647 + 1;\n");
+        let data = program.objects[0].data.as_ref().expect("no initializer");
+        assert!(matches!(data[0].value, Data::Integer(value) if value == i32::MIN as i64));
+        assert_eq!(warnings, [overflow("-2147483648", "int")]);
+    }
+}
@@ -0,0 +1,19 @@
+// The standard headers that ship with minicc, which are found after any in
+// the include paths. They only declare what minicc can parse, unlike the
+// host's headers.
+const HEADERS: [(&str, &str); 7] = [
+    ("limits.h", include_str!("../include/limits.h")),
+    ("stdarg.h", include_str!("../include/stdarg.h")),
+    ("stdbool.h", include_str!("../include/stdbool.h")),
+    ("stddef.h", include_str!("../include/stddef.h")),
+    ("stdint.h", include_str!("../include/stdint.h")),
+    ("stdio.h", include_str!("../include/stdio.h")),
+    ("stdlib.h", include_str!("../include/stdlib.h")),
+];
+
+pub fn find(name: &str) -> Option<&'static str> {
+    HEADERS
+        .iter()
+        .find(|&&(header, _)| header == name)
+        .map(|&(_, contents)| contents)
+}
@@ -1,21 +1,60 @@
 use std::{iter, str};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     OpenBrace,
     CloseBrace,
     OpenParens,
     CloseParens,
+    OpenBracket,
+    CloseBracket,
     Semicolon,
+    Colon,
+    Comma,
+    Dot,
+    Arrow,
+    Ellipsis,
     Return,
+    Switch,
+    Case,
+    Default,
+    Break,
+    Goto,
+    Sizeof,
+    Alignof,
+    Offsetof,
+    StaticAssert,
+    Static,
+    Extern,
+    Const,
+    Volatile,
+    VoidType,
+    BoolType,
+    CharType,
+    ShortType,
     IntType,
-    NumLiteral(u64),
+    LongType,
+    FloatType,
+    DoubleType,
+    Signed,
+    Unsigned,
+    StructType,
+    VaListType,
+    VaStart,
+    VaArg,
+    VaEnd,
+    VaCopy,
+    NumLiteral(u64, IntSuffix),
+    FloatLiteral(f32),
+    DoubleLiteral(f64),
+    StringLiteral(Box<[u8]>),
     Identifier(Box<str>),
     Minus,
     Tilde,
     Bang,
     Plus,
     Times,
+    Ampersand,
     Divide,
     And,
     Or,
@@ -25,6 +64,57 @@ pub enum Token {
     LessThanOrEqual,
     GreaterThan,
     GreaterThanOrEqual,
+    ShiftLeft,
+    ShiftRight,
+    Assign,
+    // Only a preprocessed source is compiled, but a raw one can still be
+    // lexed, in which directives start with this.
+    Hash,
+    // Whitespace and comments, which are only kept when lexing losslessly.
+    Whitespace,
+    Comment,
+    // A character that starts no token, such as a quote, a stray backslash
+    // or an operator that isn't supported. A lossless lexer passes it on
+    // rather than failing, as the source may still be fine C.
+    Other,
+}
+
+// The suffix of an integer constant, which affects its type. A long long
+// suffix is the same as a long one, as both types have the same size. An
+// octal or hexadecimal constant can have more types than a decimal one, so
+// its base is noted too.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct IntSuffix {
+    pub unsigned: bool,
+    pub long: bool,
+    pub non_decimal: bool,
+}
+
+// Where a token is in the source: the byte offsets of its start and end,
+// and the line and column, counting from 1, that it starts at. Columns count
+// bytes, as in the rest of the diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    // The span from the start of this one to the end of a later one.
+    pub fn to(self, end: Span) -> Span {
+        Span {
+            end: end.end,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
 impl Token {
@@ -34,147 +124,487 @@ impl Token {
             '}' => Some(Token::CloseBrace),
             '(' => Some(Token::OpenParens),
             ')' => Some(Token::CloseParens),
+            '[' => Some(Token::OpenBracket),
+            ']' => Some(Token::CloseBracket),
             ';' => Some(Token::Semicolon),
-            '-' => Some(Token::Minus),
+            ':' => Some(Token::Colon),
+            ',' => Some(Token::Comma),
             '~' => Some(Token::Tilde),
             '+' => Some(Token::Plus),
             '*' => Some(Token::Times),
             '/' => Some(Token::Divide),
+            '#' => Some(Token::Hash),
             _ => None,
         }
     }
 }
 
+// The keywords and the tokens that they are lexed as.
+static KEYWORDS: [(&str, Token); 30] = [
+    ("void", Token::VoidType),
+    ("_Bool", Token::BoolType),
+    ("char", Token::CharType),
+    ("short", Token::ShortType),
+    ("int", Token::IntType),
+    ("long", Token::LongType),
+    ("float", Token::FloatType),
+    ("double", Token::DoubleType),
+    ("struct", Token::StructType),
+    ("signed", Token::Signed),
+    ("unsigned", Token::Unsigned),
+    ("sizeof", Token::Sizeof),
+    ("_Alignof", Token::Alignof),
+    ("_Static_assert", Token::StaticAssert),
+    ("static", Token::Static),
+    ("extern", Token::Extern),
+    ("const", Token::Const),
+    ("volatile", Token::Volatile),
+    ("__builtin_va_list", Token::VaListType),
+    ("__builtin_va_start", Token::VaStart),
+    ("__builtin_va_arg", Token::VaArg),
+    ("__builtin_va_end", Token::VaEnd),
+    ("__builtin_va_copy", Token::VaCopy),
+    ("__builtin_offsetof", Token::Offsetof),
+    ("return", Token::Return),
+    ("switch", Token::Switch),
+    ("case", Token::Case),
+    ("default", Token::Default),
+    ("break", Token::Break),
+    ("goto", Token::Goto),
+];
+
+// The token for a keyword, or None if the word isn't one.
+pub fn keyword(word: &str) -> Option<&'static Token> {
+    KEYWORDS
+        .iter()
+        .find(|&&(keyword, _)| keyword == word)
+        .map(|(_, token)| token)
+}
+
+pub fn keywords() -> impl Iterator<Item = &'static str> {
+    KEYWORDS.iter().map(|&(keyword, _)| keyword)
+}
+
+// An error found while lexing, which ends the tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub span: Span,
+}
+
+// The tokens of a source, lexed one at a time as they're asked for. After
+// an error, there are no more tokens.
 pub struct Lexer<'a> {
     chars: iter::Peekable<str::Chars<'a>>,
+    // Where the next character is.
+    offset: usize,
+    line: usize,
+    column: usize,
+    // Whether whitespace and comments are kept as tokens, so that the
+    // source can be put back together from the spans of its tokens.
+    lossless: bool,
+    failed: bool,
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            let (start, line, column) = (self.offset, self.line, self.column);
+            let c = self.next_char()?;
+            let token = self.lex_token(c);
+
+            let span = Span {
+                start,
+                end: self.offset,
+                line,
+                column,
+            };
+            match token {
+                Ok(Token::Whitespace) | Ok(Token::Comment) if !self.lossless => continue,
+                Ok(token) => return Some(Ok(SpannedToken { token, span })),
+                Err(message) => {
+                    self.failed = true;
+                    return Some(Err(LexError { message, span }));
+                }
+            }
+        }
+
+        None
+    }
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
         Lexer {
             chars: source.chars().peekable(),
+            offset: 0,
+            line: 1,
+            column: 1,
+            lossless: false,
+            failed: false,
         }
     }
 
-    pub fn lex(&mut self) -> Option<Vec<Token>> {
-        let mut tokens = Vec::new();
+    // A lexer that keeps whitespace and comments, for tools that work on the
+    // source as it's written rather than compile it.
+    pub fn lossless(source: &'a str) -> Self {
+        Lexer {
+            lossless: true,
+            ..Lexer::new(source)
+        }
+    }
 
-        loop {
-            match self.next() {
-                Some(c) => {
-                    if c.is_whitespace() {
-                        continue;
-                    }
+    // Lex the token that starts with a character that has been consumed.
+    fn lex_token(&mut self, c: char) -> Result<Token, String> {
+        if c.is_whitespace() {
+            self.skip_while(char::is_whitespace);
+            return Ok(Token::Whitespace);
+        }
+        if c == '/' && self.peek_char() == Some(&'/') {
+            self.skip_while(|next| next != '\n');
+            return Ok(Token::Comment);
+        }
+        if c == '/' && self.peek_char() == Some(&'*') {
+            return self.lex_block_comment();
+        }
 
-                    let maybe_token = Token::from_char(c).or_else(|| {
-                        if c.is_digit(10) {
-                            self.lex_number(c)
-                        } else if c.is_alphabetic() || c == '_' {
-                            self.lex_word(c)
-                        } else {
-                            self.lex_multichar_operator(c)
-                        }
-                    });
+        match Token::from_char(c) {
+            Some(token) => Ok(token),
+            None if c.is_ascii_digit() || (c == '.' && self.peek_digit()) => self.lex_number(c),
+            None if c.is_alphabetic() || c == '_' => Ok(self.lex_word(c)),
+            None if c == '"' => self.lex_string(),
+            None => match self.lex_multichar_operator(c) {
+                Some(token) => Ok(token),
+                None if self.lossless => Ok(Token::Other),
+                None => Err(format!("unexpected character '{}'", c)),
+            },
+        }
+    }
 
-                    if let Some(token) = maybe_token {
-                        tokens.push(token);
-                    } else {
-                        break;
-                    }
+    // Lex a comment whose opening slash has already been consumed, which
+    // must be closed before the end of the source.
+    fn lex_block_comment(&mut self) -> Result<Token, String> {
+        self.next_char();
+        loop {
+            match self.next_char() {
+                Some('*') if self.peek_char() == Some(&'/') => {
+                    self.next_char();
+                    return Ok(Token::Comment);
                 }
-                None => {
-                    // If there are no more characters, return all tokens.
-                    return Some(tokens);
+                Some(_) => {}
+                None => return Err("unterminated /* comment".to_owned()),
+            }
+        }
+    }
+
+    fn lex_number(&mut self, first: char) -> Result<Token, String> {
+        // Hexadecimal floating constants aren't supported, so a hexadecimal
+        // constant is always an integer.
+        if first == '0' && matches!(self.peek_char(), Some(&('x' | 'X'))) {
+            let x = self.next_char().unwrap_or('x');
+            let mut digits = String::new();
+            self.lex_digits(&mut digits, 16);
+            if digits.is_empty() {
+                let letters = x.to_string() + &self.lex_letters();
+                return Err(format!("invalid suffix '{}' on integer constant", letters));
+            }
+            return self.lex_integer(&digits, 16);
+        }
+
+        let mut digits = first.to_string();
+        let mut is_floating = first == '.';
+        self.lex_digits(&mut digits, 10);
+
+        if !is_floating && self.peek_char() == Some(&'.') {
+            is_floating = true;
+            digits.push('.');
+            self.next_char();
+            self.lex_digits(&mut digits, 10);
+        }
+
+        if let Some(&exponent @ ('e' | 'E')) = self.peek_char() {
+            is_floating = true;
+            digits.push(exponent);
+            self.next_char();
+
+            if let Some(&sign @ ('+' | '-')) = self.peek_char() {
+                digits.push(sign);
+                self.next_char();
+            }
+
+            // The exponent must have at least one digit.
+            if !self.peek_digit() {
+                return Err("exponent has no digits".to_owned());
+            }
+            self.lex_digits(&mut digits, 10);
+        }
+
+        // An integer constant starting with 0 is in octal.
+        if !is_floating {
+            if digits.len() > 1 && digits.starts_with('0') {
+                if let Some(digit) = digits.chars().find(|digit| !digit.is_digit(8)) {
+                    return Err(format!("invalid digit '{}' in octal constant", digit));
                 }
+                return self.lex_integer(&digits[1..], 8);
             }
+            return self.lex_integer(&digits, 10);
         }
 
-        None
+        let invalid = |_| format!("invalid floating constant '{}'", digits);
+        match self.peek_char() {
+            Some(&'f') | Some(&'F') => {
+                self.next_char();
+                digits
+                    .parse::<f32>()
+                    .map(Token::FloatLiteral)
+                    .map_err(invalid)
+            }
+            _ => digits
+                .parse::<f64>()
+                .map(Token::DoubleLiteral)
+                .map_err(invalid),
+        }
     }
 
-    fn lex_number(&mut self, digit: char) -> Option<Token> {
-        let mut digits = digit.to_string();
+    // Finish an integer constant whose digits in the given base have been
+    // lexed by lexing its suffix.
+    fn lex_integer(&mut self, digits: &str, radix: u32) -> Result<Token, String> {
+        let suffix = IntSuffix {
+            non_decimal: radix != 10,
+            ..self.lex_int_suffix()?
+        };
+        u64::from_str_radix(digits, radix)
+            .map(|value| Token::NumLiteral(value, suffix))
+            .map_err(|_| {
+                "integer literal is too large to be represented in any integer type".to_owned()
+            })
+    }
 
-        loop {
-            match self.peek() {
-                Some(next) => {
-                    if next.is_digit(10) {
-                        digits.push(*next);
-                    } else {
-                        break;
-                    }
-                }
-                None => break,
+    // Lex the letters that follow the digits of an integer constant, which
+    // can be a u and an l or ll, in either order and either case.
+    fn lex_int_suffix(&mut self) -> Result<IntSuffix, String> {
+        let letters = self.lex_letters();
+        let rest = letters
+            .strip_prefix(['u', 'U'])
+            .or_else(|| letters.strip_suffix(['u', 'U']));
+        let unsigned = rest.is_some();
+        let long = match rest.unwrap_or(&letters) {
+            "" => false,
+            "l" | "L" | "ll" | "LL" => true,
+            _ => return Err(format!("invalid suffix '{}' on integer constant", letters)),
+        };
+
+        Ok(IntSuffix {
+            unsigned,
+            long,
+            non_decimal: false,
+        })
+    }
+
+    // Lex the letters, digits and underscores that run on from a number.
+    fn lex_letters(&mut self) -> String {
+        let mut letters = String::new();
+        while let Some(&next) = self.peek_char() {
+            if next.is_alphanumeric() || next == '_' {
+                letters.push(next);
+            } else {
+                break;
             }
 
-            self.next();
+            self.next_char();
         }
+        letters
+    }
+
+    fn lex_digits(&mut self, digits: &mut String, radix: u32) {
+        while let Some(&next) = self.peek_char() {
+            if next.is_digit(radix) {
+                digits.push(next);
+            } else {
+                break;
+            }
 
-        digits.parse::<u64>().map(Token::NumLiteral).ok()
+            self.next_char();
+        }
     }
 
-    fn lex_word(&mut self, ch: char) -> Option<Token> {
+    fn lex_word(&mut self, ch: char) -> Token {
         let mut word = ch.to_string();
 
-        loop {
-            match self.peek() {
-                Some(next) => {
-                    if next.is_alphabetic() || next.is_digit(10) || *next == '_' {
-                        word.push(*next);
-                    } else {
-                        break;
-                    }
-                }
-                None => break,
+        while let Some(&next) = self.peek_char() {
+            if next.is_alphabetic() || next.is_ascii_digit() || next == '_' {
+                word.push(next);
+            } else {
+                break;
             }
 
-            self.next();
+            self.next_char();
         }
 
-        match word.as_ref() {
-            "int" => Some(Token::IntType),
-            "return" => Some(Token::Return),
-            _ => Some(Token::Identifier(word.into_boxed_str())),
+        match keyword(&word) {
+            Some(token) => token.clone(),
+            None => Token::Identifier(word.into_boxed_str()),
         }
     }
 
+    // Lex a string literal whose opening quote has already been consumed,
+    // decoding its escape sequences into the bytes that it stands for.
+    fn lex_string(&mut self) -> Result<Token, String> {
+        let mut bytes = Vec::new();
+
+        loop {
+            match self.next_char() {
+                Some('"') => return Ok(Token::StringLiteral(bytes.into_boxed_slice())),
+                Some('\\') => bytes.push(self.lex_escape_sequence()?),
+                Some('\n') | None => return Err("missing terminating '\"' character".to_owned()),
+                Some(c) => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+    }
+
+    fn lex_escape_sequence(&mut self) -> Result<u8, String> {
+        let c = match self.next_char() {
+            Some(c) => c,
+            None => return Err("missing terminating '\"' character".to_owned()),
+        };
+        let byte = match c {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '\\' => b'\\',
+            '\'' => b'\'',
+            '"' => b'"',
+            '?' => b'?',
+            'x' => {
+                let mut value: u32 = 0;
+                let mut digits = 0;
+                while let Some(digit) = self.peek_char().and_then(|next| next.to_digit(16)) {
+                    value = value.saturating_mul(16).saturating_add(digit);
+                    digits += 1;
+                    self.next_char();
+                }
+
+                if digits == 0 {
+                    return Err("\\x used with no following hex digits".to_owned());
+                }
+                if value > 0xff {
+                    return Err("hex escape sequence out of range".to_owned());
+                }
+                value as u8
+            }
+            '0'..='7' => {
+                // Octal escapes have at most three digits.
+                let mut value = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match self.peek_char().and_then(|next| next.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            self.next_char();
+                        }
+                        None => break,
+                    }
+                }
+
+                if value > 0xff {
+                    return Err("octal escape sequence out of range".to_owned());
+                }
+                value as u8
+            }
+            c => return Err(format!("unknown escape sequence '\\{}'", c)),
+        };
+
+        Ok(byte)
+    }
+
     fn lex_multichar_operator(&mut self, ch: char) -> Option<Token> {
         match ch {
-            '&' => self.next()
-                .and_then(|next| char_to_token(next, '&', Token::And)),
-            '|' => self.next()
+            '&' => self
+                .peek_char()
+                .and_then(|next| char_to_token(*next, '&', Token::And))
+                .map(|token| self.advance_token(token))
+                .or(Some(Token::Ampersand)),
+            '|' => self
+                .next_char()
                 .and_then(|next| char_to_token(next, '|', Token::Or)),
-            '=' => self.next()
-                .and_then(|next| char_to_token(next, '=', Token::Equal)),
-            '!' => self.peek()
-                .and_then(|next| char_to_token(*next, '=', Token::NotEqual))
+            '=' => self
+                .peek_char()
+                .and_then(|next| char_to_token(*next, '=', Token::Equal))
                 .map(|token| self.advance_token(token))
-                .or_else(|| Some(Token::Bang)),
-            '<' => self.peek()
-                .and_then(|next| char_to_token(*next, '=', Token::LessThanOrEqual))
+                .or(Some(Token::Assign)),
+            '-' => self
+                .peek_char()
+                .and_then(|next| char_to_token(*next, '>', Token::Arrow))
                 .map(|token| self.advance_token(token))
-                .or_else(|| Some(Token::LessThan)),
-            '>' => self.peek()
-                .and_then(|next| char_to_token(*next, '=', Token::GreaterThanOrEqual))
+                .or(Some(Token::Minus)),
+            // A dot is either a member access or the first of the three
+            // dots of an ellipsis.
+            '.' => match self.peek_char() {
+                Some(&'.') => {
+                    self.next_char();
+                    self.next_char()
+                        .and_then(|next| char_to_token(next, '.', Token::Ellipsis))
+                }
+                _ => Some(Token::Dot),
+            },
+            '!' => self
+                .peek_char()
+                .and_then(|next| char_to_token(*next, '=', Token::NotEqual))
                 .map(|token| self.advance_token(token))
-                .or_else(|| Some(Token::GreaterThan)),
+                .or(Some(Token::Bang)),
+            '<' => match self.peek_char() {
+                Some(&'=') => Some(self.advance_token(Token::LessThanOrEqual)),
+                Some(&'<') => Some(self.advance_token(Token::ShiftLeft)),
+                _ => Some(Token::LessThan),
+            },
+            '>' => match self.peek_char() {
+                Some(&'=') => Some(self.advance_token(Token::GreaterThanOrEqual)),
+                Some(&'>') => Some(self.advance_token(Token::ShiftRight)),
+                _ => Some(Token::GreaterThan),
+            },
             _ => None,
         }
     }
 
     // Consume the next character and return the given token.
     fn advance_token(&mut self, token: Token) -> Token {
-        self.next();
+        self.next_char();
         token
     }
 
-    fn next(&mut self) -> Option<char> {
-        self.chars.next()
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += c.len_utf8();
+        }
+        Some(c)
+    }
+
+    fn skip_while<P: Fn(char) -> bool>(&mut self, predicate: P) {
+        while self.peek_char().is_some_and(|&next| predicate(next)) {
+            self.next_char();
+        }
     }
 
-    fn peek(&mut self) -> Option<&char> {
+    fn peek_char(&mut self) -> Option<&char> {
         self.chars.peek()
     }
+
+    fn peek_digit(&mut self) -> bool {
+        self.peek_char().is_some_and(|next| next.is_ascii_digit())
+    }
 }
 
 fn char_to_token(ch: char, want: char, token: Token) -> Option<Token> {
@@ -1,46 +1,254 @@
+mod cfg;
 mod codegen;
+mod constant;
+mod dataflow;
+mod diagnostics;
+mod fold;
+mod headers;
 mod lexer;
 mod parser;
+mod preprocessor;
+mod semantic;
+mod source_map;
+mod types;
 
 use std::env;
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use lexer::Lexer;
+use diagnostics::{Diagnostic, Diagnostics, FixIt, Format, Location};
+use lexer::{Lexer, Span, SpannedToken};
 use parser::Parser;
+use preprocessor::Preprocessor;
 
 fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
-    if args.is_empty() {
-        eprintln!("usage: minicc [input.c]");
-        std::process::exit(1);
-    }
+    let options = match parse_options(env::args().skip(1)) {
+        Some(options) => options,
+        None => {
+            eprintln!(
+                "usage: minicc [-E | -dump-tokens | -dump-raw-tokens] [-I dir]... [-Wwarning]... \
+                 [-f[no-]color-diagnostics] [--diagnostics-format=text|json] [--fixit] [input.c]"
+            );
+            std::process::exit(1);
+        }
+    };
+
+    // Colors are only used on a terminal, and not if NO_COLOR is set,
+    // unless they're asked for.
+    let color = options.color_diagnostics.unwrap_or_else(|| {
+        io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+    });
+    let format = options.diagnostics_format;
+    let render = move |diagnostic: &Diagnostic| diagnostic.render(format, color);
 
-    let filepath = Path::new(&args[0]);
+    let filepath = options.input.as_path();
     let source = read_source(filepath);
 
-    let asm = Lexer::new(&source)
-        .lex()
-        .and_then(|tokens| Parser::new(&tokens).parse())
-        .map(|ast| codegen::generate(ast));
+    // The raw tokens are those of the source as it's written, with its
+    // whitespace and comments, before any preprocessing.
+    if options.dump_raw_tokens {
+        let locate = |span| raw_location(filepath, &source, span);
+        dump_tokens(&source, Lexer::lossless(&source), locate, render);
+        return;
+    }
+
+    let output = match Preprocessor::new(options.include_paths).preprocess(filepath, &source) {
+        Ok(output) => output,
+        Err(error) => {
+            eprintln!(
+                "{}",
+                render(&Diagnostic::error(error.message).at(error.location))
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let source = &output.text;
+
+    // With -E, the preprocessed source is the output.
+    if options.preprocess_only {
+        print!("{}", source);
+        return;
+    }
+
+    if options.dump_tokens {
+        let locate = |span| output.origins.locate(span);
+        dump_tokens(source, Lexer::new(source), locate, render);
+        return;
+    }
+
+    let mut diagnostics = Diagnostics::new(options.warnings, output.origins);
+    let asm = Parser::new(Lexer::new(source), &mut diagnostics)
+        .parse()
+        .and_then(|ast| semantic::analyze(&ast, &mut diagnostics))
+        .map(|mut program| {
+            fold::fold_program(&mut program, &mut diagnostics);
+            codegen::generate(&program)
+        });
+
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", render(diagnostic));
+    }
 
     match asm {
-        Some(lines) => {
+        // With -Werror, warnings stop the compilation just like errors.
+        Ok(_) if diagnostics.has_errors() => std::process::exit(1),
+        Ok(lines) => {
             let asm_filename = filepath.with_extension("s");
-            write_assembly(&asm_filename, lines.join("\n").as_bytes());
+            write_file(&asm_filename, lines.join("\n").as_bytes());
 
             let binary_filename = filepath.with_extension("");
             assemble(&asm_filename, &binary_filename);
         }
-        None => {
-            eprintln!("an error has occurred");
+        Err(error) => {
+            let diagnostic = diagnostics.error(error);
+            eprintln!("{}", render(&diagnostic));
+            if options.fixit {
+                apply_fixits(filepath, &diagnostic.fixits);
+            }
             std::process::exit(1);
         }
     }
 }
 
+// Write a copy of the input with the fix-its in it applied, next to it as
+// `name.fixed.c`. Only those that are in the input itself are applied.
+fn apply_fixits(filepath: &Path, fixits: &[FixIt]) {
+    let name = filepath.display().to_string();
+    let mut ranges = fixits
+        .iter()
+        .filter_map(|fixit| fixit.range().map(|range| (range, fixit.text())))
+        .filter(|(range, _)| range.file == name)
+        .collect::<Vec<_>>();
+    if ranges.is_empty() {
+        return;
+    }
+
+    // Applying them from the end of the file on keeps the offsets of the
+    // rest valid.
+    ranges.sort_by_key(|(range, _)| range.offsets.start);
+    let mut source = read_source(filepath);
+    for (range, text) in ranges.into_iter().rev() {
+        source.replace_range(range.offsets.clone(), text);
+    }
+    write_file(&filepath.with_extension("fixed.c"), source.as_bytes());
+}
+
+struct Options {
+    input: PathBuf,
+    include_paths: Vec<PathBuf>,
+    preprocess_only: bool,
+    dump_tokens: bool,
+    dump_raw_tokens: bool,
+    warnings: diagnostics::Options,
+    diagnostics_format: Format,
+    // Whether diagnostics are colored, if it's been chosen with
+    // -fcolor-diagnostics or -fno-color-diagnostics.
+    color_diagnostics: Option<bool>,
+    // Whether a copy of the input with the fix-its applied is written.
+    fixit: bool,
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: I) -> Option<Options> {
+    let mut input = None;
+    let mut include_paths = Vec::new();
+    let mut preprocess_only = false;
+    let mut dump_tokens = false;
+    let mut dump_raw_tokens = false;
+    let mut warnings = diagnostics::Options::default();
+    let mut diagnostics_format = Format::Text;
+    let mut color_diagnostics = None;
+    let mut fixit = false;
+
+    while let Some(arg) = args.next() {
+        if arg == "-E" {
+            preprocess_only = true;
+        } else if arg == "-dump-tokens" {
+            dump_tokens = true;
+        } else if arg == "-dump-raw-tokens" {
+            dump_raw_tokens = true;
+        } else if arg == "-I" {
+            include_paths.push(PathBuf::from(args.next()?));
+        } else if let Some(path) = arg.strip_prefix("-I") {
+            include_paths.push(PathBuf::from(path));
+        } else if arg == "-fcolor-diagnostics" {
+            color_diagnostics = Some(true);
+        } else if arg == "-fno-color-diagnostics" {
+            color_diagnostics = Some(false);
+        } else if arg == "--fixit" {
+            fixit = true;
+        } else if let Some(name) = arg.strip_prefix("--diagnostics-format=") {
+            diagnostics_format = Format::from_name(name)?;
+        } else if arg.starts_with("-W") {
+            if !warnings.apply(&arg) {
+                return None;
+            }
+        } else if arg.starts_with('-') || input.is_some() {
+            return None;
+        } else {
+            input = Some(PathBuf::from(arg));
+        }
+    }
+
+    Some(Options {
+        input: input?,
+        include_paths,
+        preprocess_only,
+        dump_tokens,
+        dump_raw_tokens,
+        warnings,
+        diagnostics_format,
+        color_diagnostics,
+        fixit,
+    })
+}
+
+// Print the tokens of some source one per line, with their text and where
+// `locate` finds them in the files that the source came from, e.g.
+// `IntType "int" a.c:1:1`. A token that doesn't lex is reported there.
+fn dump_tokens<L, F>(source: &str, lexer: Lexer, locate: L, render: F)
+where
+    L: Fn(Span) -> Location,
+    F: Fn(&Diagnostic) -> String,
+{
+    for token in lexer {
+        let SpannedToken { token, span } = match token {
+            Ok(token) => token,
+            Err(error) => {
+                let diagnostic = Diagnostic::error(error.message).at(locate(error.span));
+                eprintln!("{}", render(&diagnostic));
+                std::process::exit(1);
+            }
+        };
+        let location = locate(span);
+        println!(
+            "{:?} {:?} {}:{}:{}",
+            token,
+            &source[span.start..span.end],
+            location.file,
+            location.line,
+            location
+                .columns
+                .map_or(span.column, |columns| columns.start)
+        );
+    }
+}
+
+// Where a span of a source that hasn't been preprocessed is. It's only
+// marked up to the end of the line it starts on.
+fn raw_location(filepath: &Path, source: &str, span: Span) -> Location {
+    let text = &source[span.start..span.end];
+    let length = text.find(['\r', '\n']).unwrap_or(text.len()).max(1);
+    Location {
+        file: filepath.display().to_string(),
+        line: span.line,
+        columns: Some(span.column..span.column + length),
+        source_line: source_map::physical_line(source, span.line).map(str::to_owned),
+    }
+}
+
 fn read_source(filepath: &Path) -> String {
     let mut source = String::new();
 
@@ -52,7 +260,7 @@ fn read_source(filepath: &Path) -> String {
     source
 }
 
-fn write_assembly(path: &Path, bytes: &[u8]) {
+fn write_file(path: &Path, bytes: &[u8]) {
     File::create(path)
         .expect("couldn't open file for writing")
         .write_all(bytes)
@@ -61,7 +269,7 @@ fn write_assembly(path: &Path, bytes: &[u8]) {
 
 fn assemble(asm_filename: &Path, binary_filename: &Path) {
     Command::new("gcc")
-        .args(&[
+        .args([
             "-o",
             binary_filename.to_str().unwrap(),
             asm_filename.to_str().unwrap(),
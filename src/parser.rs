@@ -1,17 +1,99 @@
-use lexer::Token;
-use std::{iter, slice};
+use constant;
+use diagnostics::{self, Diagnostics, Error, Warning};
+use lexer::{self, IntSuffix, LexError, Lexer, Span, SpannedToken, Token};
+use std::collections::{HashMap, VecDeque};
+use types::{FunctionType, Qualifiers, StructType, Type};
 
+// A node of the syntax tree, with the span of the preprocessed text that it
+// was parsed from. Declarations are at the names that they declare.
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
-pub enum AST {
-    Program(Box<AST>),
-    Function(Box<str>, Box<AST>),
-    Return(Box<AST>),
+pub struct AST {
+    pub kind: ASTKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ASTKind {
+    Program(Vec<AST>),
+    // A function definition, with the names of its parameters and where
+    // they are, and its body.
+    Function(
+        Box<str>,
+        FunctionType,
+        Option<StorageClass>,
+        Vec<(Box<str>, Span)>,
+        Box<AST>,
+    ),
+    FunctionDeclaration(Box<str>, FunctionType, Option<StorageClass>),
+    Declaration(Box<str>, Type, Option<StorageClass>, Option<Box<AST>>),
+    // A static assertion, with the message to report if it fails.
+    StaticAssert(Box<AST>, Box<[u8]>),
+    Block(Vec<AST>),
+    Expression(Box<AST>),
+    Return(Option<Box<AST>>),
+    Switch(Box<AST>, Box<AST>),
+    Case(Box<AST>, Box<AST>),
+    Default(Box<AST>),
+    Break,
+    Label(Box<str>, Box<AST>),
+    Goto(Box<str>),
     UnaryOp(UnaryOperator, Box<AST>),
     BinaryOp(BinaryOperator, Box<AST>, Box<AST>),
-    IntConstant(u64),
+    Assign(Box<AST>, Box<AST>),
+    Call(Box<AST>, Vec<AST>),
+    Variable(Box<str>),
+    Dereference(Box<AST>),
+    AddressOf(Box<AST>),
+    Member(Box<AST>, Box<str>),
+    // A brace-enclosed initializer, whose items may each be preceded by
+    // designators.
+    InitializerList(Vec<(Vec<Designator>, AST)>),
+    Cast(Type, Box<AST>),
+    SizeOfExpr(Box<AST>),
+    SizeOfType(Type),
+    AlignOfType(Type),
+    // The offset of a member within a structure type.
+    OffsetOf(Type, Box<str>),
+    IntConstant(u64, IntSuffix),
+    FloatConstant(f32),
+    DoubleConstant(f64),
+    StringLiteral(Box<[u8]>),
+    VaStart(Box<AST>, Box<AST>),
+    VaArg(Box<AST>, Type),
+    VaEnd(Box<AST>),
+    VaCopy(Box<AST>, Box<AST>),
+}
+
+impl AST {
+    fn new(kind: ASTKind, span: Span) -> Self {
+        AST { kind, span }
+    }
 }
 
 #[derive(Debug, PartialEq)]
+pub enum Designator {
+    Index(AST),
+    Member(Box<str>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+    Static,
+    Extern,
+}
+
+impl StorageClass {
+    fn from_token(token: &Token) -> Option<Self> {
+        match *token {
+            Token::Static => Some(StorageClass::Static),
+            Token::Extern => Some(StorageClass::Extern),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UnaryOperator {
     Minus,
     Tilde,
@@ -29,7 +111,7 @@ impl UnaryOperator {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOperator {
     Plus,
     Minus,
@@ -43,6 +125,8 @@ pub enum BinaryOperator {
     LessThanOrEqual,
     GreaterThan,
     GreaterThanOrEqual,
+    ShiftLeft,
+    ShiftRight,
 }
 
 impl BinaryOperator {
@@ -60,146 +144,597 @@ impl BinaryOperator {
             Token::LessThanOrEqual => Some(BinaryOperator::LessThanOrEqual),
             Token::GreaterThan => Some(BinaryOperator::GreaterThan),
             Token::GreaterThanOrEqual => Some(BinaryOperator::GreaterThanOrEqual),
+            Token::ShiftLeft => Some(BinaryOperator::ShiftLeft),
+            Token::ShiftRight => Some(BinaryOperator::ShiftRight),
             _ => None,
         }
     }
 }
 
+// A name declared by a declarator, with where it is.
+type Name = (Box<str>, Span);
+
+// One step in deriving the type of a declarator from the type named by its
+// specifiers.
+enum Derivation {
+    Pointer(Qualifiers),
+    Array(Option<u64>),
+    // The parameters and whether the function is variadic, as returned by
+    // parse_parameter_list.
+    Function(Option<Vec<(Type, Option<Name>)>>, bool),
+}
+
+struct Declarator {
+    name: Option<Box<str>>,
+    // Where the name is, or where it would be in an abstract declarator.
+    span: Span,
+    ty: Type,
+    // The names of the parameters, if the declarator declares a function.
+    param_names: Vec<Option<Name>>,
+}
+
+// The tokens being parsed, which are lexed as they're needed. Only the ones
+// that have been looked ahead at are kept, along with their spans.
+struct Tokens<'a> {
+    lexer: Lexer<'a>,
+    lookahead: VecDeque<SpannedToken>,
+    // The span of the last token taken.
+    previous: Span,
+    // The error that ended the tokens, if lexing them failed.
+    error: Option<LexError>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.fill(1);
+        let token = self.lookahead.pop_front()?;
+        self.previous = token.span;
+        Some(token.token)
+    }
+}
+
+impl<'a> Tokens<'a> {
+    fn peek(&mut self) -> Option<&Token> {
+        self.fill(1);
+        self.lookahead.front().map(|token| &token.token)
+    }
+
+    // The span of the next token, or an empty one after the last token if
+    // there are no more.
+    fn peek_span(&mut self) -> Span {
+        self.fill(1);
+        match self.lookahead.front() {
+            Some(token) => token.span,
+            None => self.end(),
+        }
+    }
+
+    // An empty span just after the last token taken.
+    fn end(&self) -> Span {
+        Span {
+            start: self.previous.end,
+            ..self.previous
+        }
+    }
+
+    // Look at the token after the next one without consuming either.
+    fn peek_second(&mut self) -> Option<&Token> {
+        self.fill(2);
+        self.lookahead.get(1).map(|token| &token.token)
+    }
+
+    // Lex tokens until the given number of them are waiting, unless the
+    // source ends first.
+    fn fill(&mut self, count: usize) {
+        while self.lookahead.len() < count {
+            match self.lexer.next() {
+                Some(Ok(token)) => self.lookahead.push_back(token),
+                Some(Err(error)) => self.error = Some(error),
+                None => break,
+            }
+        }
+    }
+}
+
 pub struct Parser<'a> {
-    tokens: iter::Peekable<slice::Iter<'a, Token>>,
+    tokens: Tokens<'a>,
+    // The structure tags declared in each enclosing scope, innermost last.
+    struct_scopes: Vec<HashMap<Box<str>, StructType>>,
+    // An error found while parsing, if there's anything more specific to
+    // say than that the program doesn't parse.
+    error: Option<Error>,
+    diagnostics: &'a mut Diagnostics,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(tokens: &'a [Token]) -> Self {
+    pub fn new(lexer: Lexer<'a>, diagnostics: &'a mut Diagnostics) -> Self {
         Parser {
-            tokens: tokens.iter().peekable(),
+            tokens: Tokens {
+                lexer,
+                lookahead: VecDeque::new(),
+                previous: Span {
+                    start: 0,
+                    end: 0,
+                    line: 1,
+                    column: 1,
+                },
+                error: None,
+            },
+            struct_scopes: vec![HashMap::new()],
+            error: None,
+            diagnostics,
         }
     }
 
-    pub fn parse(&mut self) -> Option<AST> {
-        self.parse_program()
+    // Parse a translation unit. If it doesn't parse, and there's nothing
+    // more specific to say about why, the error is at the last token taken.
+    pub fn parse(&mut self) -> Result<AST, Error> {
+        let ast = self.parse_program();
+
+        // A token that doesn't lex ends the tokens where it is, so whatever
+        // went wrong with parsing from there on is down to it.
+        if let Some(error) = self.tokens.error.take() {
+            self.diagnostics.take_fixits();
+            return Err(Error::at(error.message, error.span));
+        }
+
+        match (self.error.take(), ast) {
+            (Some(error), _) => Err(error),
+            (None, Some(ast)) => Ok(ast),
+            (None, None) => Err(Error::at(
+                "an error has occurred".to_owned(),
+                self.tokens.previous,
+            )),
+        }
     }
 
     fn parse_program(&mut self) -> Option<AST> {
-        self.parse_function()
-            .map(|func| AST::Program(Box::new(func)))
+        let start = self.tokens.peek_span();
+        let mut items = Vec::new();
+        while self.tokens.peek().is_some() {
+            items.append(&mut self.parse_external_declaration()?);
+        }
+
+        Some(self.node(ASTKind::Program(items), start))
     }
 
-    fn parse_function(&mut self) -> Option<AST> {
-        if !self.consume(Token::IntType) {
-            return None;
+    // Parse a function definition or a declaration at file scope.
+    fn parse_external_declaration(&mut self) -> Option<Vec<AST>> {
+        if self.tokens.peek() == Some(&Token::StaticAssert) {
+            return self.parse_static_assert().map(|assertion| vec![assertion]);
         }
 
-        match self.tokens.next() {
-            Some(&Token::Identifier(ref name)) => {
-                let func_name = name.clone();
+        let (storage, base_type) = self.parse_declaration_specifiers()?;
+        if self.tokens.peek() == Some(&Token::Semicolon) {
+            self.tokens.next();
+            return Some(Vec::new());
+        }
 
-                if !self.consume(Token::OpenParens) {
-                    return None;
-                }
+        let declarator = self.parse_declarator(base_type.clone())?;
 
-                if !self.consume(Token::CloseParens) {
-                    return None;
-                }
+        if declarator.ty.is_function() && self.tokens.peek() == Some(&Token::OpenBrace) {
+            return self
+                .parse_function(declarator, storage)
+                .map(|function| vec![function]);
+        }
 
-                if !self.consume(Token::OpenBrace) {
-                    return None;
-                }
+        self.parse_init_declarators(storage, base_type, declarator)
+    }
+
+    // Parse the body of a function definition.
+    fn parse_function(
+        &mut self,
+        declarator: Declarator,
+        storage: Option<StorageClass>,
+    ) -> Option<AST> {
+        let func_type = match declarator.ty {
+            Type::Function(func_type) => *func_type,
+            _ => return None,
+        };
+
+        // Every parameter of a function definition must be named.
+        let param_names = declarator
+            .param_names
+            .into_iter()
+            .collect::<Option<Vec<_>>>()?;
+        let name = declarator.name?;
+        let span = declarator.span;
+
+        self.parse_block().map(|body| {
+            let kind = ASTKind::Function(name, func_type, storage, param_names, Box::new(body));
+            AST::new(kind, span)
+        })
+    }
+
+    // Parse the parameters of a function declarator, whose opening
+    // parenthesis has already been consumed. Returns None for the parameters
+    // of a declarator without a prototype, along with whether the function is
+    // variadic.
+    #[allow(clippy::type_complexity)]
+    fn parse_parameter_list(&mut self) -> Option<(Option<Vec<(Type, Option<Name>)>>, bool)> {
+        if self.tokens.peek() == Some(&Token::CloseParens) {
+            self.tokens.next();
+            return Some((None, false));
+        }
 
-                let func = self.parse_statement()
-                    .map(|body| AST::Function(func_name, Box::new(body)));
+        // A lone `void` declares that there are no parameters.
+        if self.tokens.peek() == Some(&Token::VoidType)
+            && self.tokens.peek_second() == Some(&Token::CloseParens)
+        {
+            self.tokens.next();
+            self.tokens.next();
+            return Some((Some(Vec::new()), false));
+        }
 
-                if self.consume(Token::CloseBrace) {
-                    func
+        let mut params = Vec::new();
+        loop {
+            if self.tokens.peek() == Some(&Token::Ellipsis) && !params.is_empty() {
+                self.tokens.next();
+                return if self.consume(Token::CloseParens) {
+                    Some((Some(params), true))
                 } else {
                     None
+                };
+            }
+
+            let base_type = self.parse_type_specifiers()?;
+            let declarator = self.parse_declarator(base_type)?;
+            let span = declarator.span;
+            let name = declarator.name.map(|name| (name, span));
+            params.push((adjust_parameter_type(declarator.ty), name));
+
+            match self.tokens.peek() {
+                Some(&Token::Comma) => self.tokens.next(),
+                Some(&Token::CloseParens) => {
+                    self.tokens.next();
+                    return Some((Some(params), false));
+                }
+                _ => return self.expected(")", ""),
+            };
+        }
+    }
+
+    fn parse_block(&mut self) -> Option<AST> {
+        let start = self.tokens.peek_span();
+        if !self.consume(Token::OpenBrace) {
+            return None;
+        }
+
+        let mut items = Vec::new();
+        self.struct_scopes.push(HashMap::new());
+
+        loop {
+            match self.tokens.peek() {
+                Some(&Token::CloseBrace) => {
+                    self.tokens.next();
+                    self.struct_scopes.pop();
+                    return Some(self.node(ASTKind::Block(items), start));
+                }
+                Some(token) if is_declaration_specifier(token) => {
+                    items.append(&mut self.parse_declaration()?);
                 }
+                Some(&Token::StaticAssert) => items.push(self.parse_static_assert()?),
+                Some(_) => items.push(self.parse_statement()?),
+                None => return self.expected("}", ""),
             }
-            _ => None,
         }
     }
 
-    fn parse_statement(&mut self) -> Option<AST> {
-        // The only type of statement that we support is a return statement.
-        if !self.consume(Token::Return) {
+    // Parse a declaration of one or more variables, e.g. `int a, *b = 0;`.
+    fn parse_declaration(&mut self) -> Option<Vec<AST>> {
+        let (storage, base_type) = self.parse_declaration_specifiers()?;
+        // A declaration without declarators can still declare a structure.
+        if self.tokens.peek() == Some(&Token::Semicolon) {
+            self.tokens.next();
+            return Some(Vec::new());
+        }
+
+        let declarator = self.parse_declarator(base_type.clone())?;
+        self.parse_init_declarators(storage, base_type, declarator)
+    }
+
+    // Parse a static assertion, e.g. `_Static_assert(sizeof(int) == 4, "int");`.
+    // Its expression is evaluated once the declarations it refers to are known.
+    fn parse_static_assert(&mut self) -> Option<AST> {
+        let start = self.tokens.peek_span();
+        self.tokens.next();
+        if !self.consume(Token::OpenParens) {
             return None;
         }
 
-        let statement = self.parse_expression()
-            .map(|expr| AST::Return(Box::new(expr)));
+        let expr = self.parse_logical_or_exp()?;
+        if !self.consume(Token::Comma) {
+            return None;
+        }
 
-        if self.consume(Token::Semicolon) {
-            statement
-        } else {
-            None
+        let message = match self.parse_primary()?.kind {
+            ASTKind::StringLiteral(message) => message,
+            _ => return None,
+        };
+        if !self.consume(Token::CloseParens) {
+            return None;
         }
+
+        self.consume_semicolon(ASTKind::StaticAssert(Box::new(expr), message), start)
     }
 
-    fn parse_expression(&mut self) -> Option<AST> {
-        self.parse_logical_and_exp().and_then(|mut term| {
+    // Parse the declarators of a declaration up to its semicolon, given the
+    // first one, which has already been parsed.
+    fn parse_init_declarators(
+        &mut self,
+        storage: Option<StorageClass>,
+        base_type: Type,
+        mut declarator: Declarator,
+    ) -> Option<Vec<AST>> {
+        let mut declarations = Vec::new();
+
+        loop {
+            let name = declarator.name?;
+            let kind = match declarator.ty {
+                Type::Function(func_type) => {
+                    ASTKind::FunctionDeclaration(name, *func_type, storage)
+                }
+                ty => {
+                    let init = if self.tokens.peek() == Some(&Token::Assign) {
+                        self.tokens.next();
+                        Some(Box::new(self.parse_initializer()?))
+                    } else {
+                        None
+                    };
+
+                    ASTKind::Declaration(name, ty, storage, init)
+                }
+            };
+            declarations.push(AST::new(kind, declarator.span));
+
+            match self.tokens.peek() {
+                Some(&Token::Comma) => self.tokens.next(),
+                Some(&Token::Semicolon) => {
+                    self.tokens.next();
+                    return Some(declarations);
+                }
+                _ => return self.expected(";", " at end of declaration"),
+            };
+
+            declarator = self.parse_declarator(base_type.clone())?;
+        }
+    }
+
+    // Parse an initializer, which is either an expression or a list of
+    // initializers in braces, e.g. `{ 1, [4] = 2, .x = { 3 } }`.
+    fn parse_initializer(&mut self) -> Option<AST> {
+        if self.tokens.peek() != Some(&Token::OpenBrace) {
+            return self.parse_expression();
+        }
+        let start = self.tokens.peek_span();
+        self.tokens.next();
+
+        let mut items = Vec::new();
+        loop {
+            if self.tokens.peek() == Some(&Token::CloseBrace) {
+                self.tokens.next();
+                return Some(self.node(ASTKind::InitializerList(items), start));
+            }
+
+            let mut designators = Vec::new();
             loop {
                 match self.tokens.peek() {
-                    Some(&&Token::Or) => {
-                        let next = self.tokens.next().unwrap();
-                        let op = BinaryOperator::from_token(next).unwrap();
-
-                        if let Some(next_term) = self.parse_logical_and_exp() {
-                            term = AST::BinaryOp(op, Box::new(term), Box::new(next_term));
-                        } else {
+                    Some(&Token::Dot) => {
+                        self.tokens.next();
+                        match self.tokens.next() {
+                            Some(Token::Identifier(name)) => {
+                                designators.push(Designator::Member(name.clone()));
+                            }
+                            _ => return None,
+                        }
+                    }
+                    Some(&Token::OpenBracket) => {
+                        self.tokens.next();
+                        let index = self.parse_expression()?;
+                        if !self.consume(Token::CloseBracket) {
                             return None;
                         }
+                        designators.push(Designator::Index(index));
                     }
-                    _ => {
-                        break;
+                    _ => break,
+                }
+            }
+
+            if !designators.is_empty() && !self.consume(Token::Assign) {
+                return None;
+            }
+
+            items.push((designators, self.parse_initializer()?));
+
+            // The last item may be followed by a comma.
+            match self.tokens.next() {
+                Some(Token::Comma) => {}
+                Some(Token::CloseBrace) => {
+                    return Some(self.node(ASTKind::InitializerList(items), start))
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Option<AST> {
+        let start = self.tokens.peek_span();
+        match self.tokens.peek().cloned() {
+            Some(Token::OpenBrace) => self.parse_block(),
+            Some(Token::Semicolon) => {
+                self.tokens.next();
+                Some(self.node(ASTKind::Block(Vec::new()), start))
+            }
+            Some(Token::Return) => {
+                self.tokens.next();
+                if self.tokens.peek() == Some(&Token::Semicolon) {
+                    return self.consume_semicolon(ASTKind::Return(None), start);
+                }
+
+                let expr = self.parse_expression()?;
+                self.consume_semicolon(ASTKind::Return(Some(Box::new(expr))), start)
+            }
+            Some(Token::Switch) => {
+                self.tokens.next();
+                if !self.consume(Token::OpenParens) {
+                    return None;
+                }
+
+                let expr = self.parse_expression()?;
+                if !self.consume(Token::CloseParens) {
+                    return None;
+                }
+
+                let body = self.parse_statement()?;
+                Some(self.node(ASTKind::Switch(Box::new(expr), Box::new(body)), start))
+            }
+            Some(Token::Case) => {
+                self.tokens.next();
+                let value = self.parse_expression()?;
+                if !self.consume(Token::Colon) {
+                    return None;
+                }
+
+                let stmt = self.parse_statement()?;
+                Some(self.node(ASTKind::Case(Box::new(value), Box::new(stmt)), start))
+            }
+            Some(Token::Default) => {
+                self.tokens.next();
+                if !self.consume(Token::Colon) {
+                    return None;
+                }
+
+                let stmt = self.parse_statement()?;
+                Some(self.node(ASTKind::Default(Box::new(stmt)), start))
+            }
+            Some(Token::Break) => {
+                self.tokens.next();
+                self.consume_semicolon(ASTKind::Break, start)
+            }
+            Some(Token::Goto) => {
+                self.tokens.next();
+                match self.tokens.next() {
+                    Some(Token::Identifier(label)) => {
+                        self.consume_semicolon(ASTKind::Goto(label.clone()), start)
                     }
+                    _ => None,
                 }
             }
+            Some(Token::Identifier(_)) if self.tokens.peek_second() == Some(&Token::Colon) => {
+                self.parse_labeled_statement()
+            }
+            Some(_) => {
+                let expr = self.parse_expression()?;
+                self.consume_semicolon(ASTKind::Expression(Box::new(expr)), start)
+            }
+            None => None,
+        }
+    }
 
-            Some(term)
-        })
+    fn parse_labeled_statement(&mut self) -> Option<AST> {
+        let start = self.tokens.peek_span();
+        match self.tokens.next() {
+            Some(Token::Identifier(label)) => {
+                if !self.consume(Token::Colon) {
+                    return None;
+                }
+
+                let stmt = self.parse_statement()?;
+                Some(self.node(ASTKind::Label(label.clone(), Box::new(stmt)), start))
+            }
+            _ => None,
+        }
     }
 
-    fn parse_logical_and_exp(&mut self) -> Option<AST> {
+    fn parse_expression(&mut self) -> Option<AST> {
+        let expr = self.parse_logical_or_exp()?;
+
+        // Assignment is right-associative, so parse its right-hand side as a
+        // whole expression.
+        if self.tokens.peek() == Some(&Token::Assign) {
+            self.tokens.next();
+            return self.parse_expression().map(|value| {
+                let span = expr.span.to(value.span);
+                AST::new(ASTKind::Assign(Box::new(expr), Box::new(value)), span)
+            });
+        }
+
+        Some(expr)
+    }
+
+    fn parse_logical_or_exp(&mut self) -> Option<AST> {
+        let (mut term, and_operator) = self.parse_logical_and_exp()?;
+        let mut and_operands = Vec::new();
+        if let Some(operator) = and_operator {
+            and_operands.push((operator, term.span));
+        }
+        let mut has_or = false;
+
+        while let Some(&Token::Or) = self.tokens.peek() {
+            let next = self.tokens.next().unwrap();
+            let op = BinaryOperator::from_token(&next).unwrap();
+
+            let (next_term, and_operator) = self.parse_logical_and_exp()?;
+            if let Some(operator) = and_operator {
+                and_operands.push((operator, next_term.span));
+            }
+            term = binary_op(op, term, next_term);
+            has_or = true;
+        }
+
+        if has_or {
+            for (operator, operand) in and_operands {
+                let note = (
+                    operand,
+                    "place parentheses around the '&&' expression to silence this warning"
+                        .to_owned(),
+                );
+                self.diagnostics.warn_with_notes(
+                    Warning::Parentheses,
+                    operator,
+                    "'&&' within '||'".to_owned(),
+                    vec![note],
+                );
+            }
+        }
+
+        Some(term)
+    }
+
+    // Parse a && expression, also returning where its last && is if it has
+    // one outside of any parentheses, as that's easily misread as an operand
+    // of ||.
+    fn parse_logical_and_exp(&mut self) -> Option<(AST, Option<Span>)> {
         self.parse_equality_exp().and_then(|mut term| {
-            loop {
-                match self.tokens.peek() {
-                    Some(&&Token::And) => {
-                        let next = self.tokens.next().unwrap();
-                        let op = BinaryOperator::from_token(next).unwrap();
+            let mut and_operator = None;
+            while let Some(&Token::And) = self.tokens.peek() {
+                let next = self.tokens.next().unwrap();
+                let op = BinaryOperator::from_token(&next).unwrap();
+                and_operator = Some(self.tokens.previous);
 
-                        if let Some(next_term) = self.parse_equality_exp() {
-                            term = AST::BinaryOp(op, Box::new(term), Box::new(next_term));
-                        } else {
-                            return None;
-                        }
-                    }
-                    _ => {
-                        break;
-                    }
+                if let Some(next_term) = self.parse_equality_exp() {
+                    term = binary_op(op, term, next_term);
+                } else {
+                    return None;
                 }
             }
 
-            Some(term)
+            Some((term, and_operator))
         })
     }
 
     fn parse_equality_exp(&mut self) -> Option<AST> {
         self.parse_relational_exp().and_then(|mut term| {
-            loop {
-                match self.tokens.peek() {
-                    Some(&&Token::Equal) | Some(&&Token::NotEqual) => {
-                        let next = self.tokens.next().unwrap();
-                        let op = BinaryOperator::from_token(next).unwrap();
+            while let Some(&Token::Equal) | Some(&Token::NotEqual) = self.tokens.peek() {
+                let next = self.tokens.next().unwrap();
+                let op = BinaryOperator::from_token(&next).unwrap();
 
-                        if let Some(next_term) = self.parse_relational_exp() {
-                            term = AST::BinaryOp(op, Box::new(term), Box::new(next_term));
-                        } else {
-                            return None;
-                        }
-                    }
-                    _ => {
-                        break;
-                    }
+                if let Some(next_term) = self.parse_relational_exp() {
+                    term = binary_op(op, term, next_term);
+                } else {
+                    return None;
                 }
             }
 
@@ -208,25 +743,47 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_relational_exp(&mut self) -> Option<AST> {
+        self.parse_shift_exp().and_then(|mut term| {
+            let mut chained = false;
+            while let Some(&Token::LessThan)
+            | Some(&Token::LessThanOrEqual)
+            | Some(&Token::GreaterThan)
+            | Some(&Token::GreaterThanOrEqual) = self.tokens.peek()
+            {
+                let next = self.tokens.next().unwrap();
+                let op = BinaryOperator::from_token(&next).unwrap();
+
+                if chained {
+                    self.diagnostics.warn(
+                        Warning::Parentheses,
+                        self.tokens.previous,
+                        "comparisons like 'X<=Y<=Z' don't have their mathematical meaning"
+                            .to_owned(),
+                    );
+                }
+                chained = true;
+
+                if let Some(next_term) = self.parse_shift_exp() {
+                    term = binary_op(op, term, next_term);
+                } else {
+                    return None;
+                }
+            }
+
+            Some(term)
+        })
+    }
+
+    fn parse_shift_exp(&mut self) -> Option<AST> {
         self.parse_additive_exp().and_then(|mut term| {
-            loop {
-                match self.tokens.peek() {
-                    Some(&&Token::LessThan)
-                    | Some(&&Token::LessThanOrEqual)
-                    | Some(&&Token::GreaterThan)
-                    | Some(&&Token::GreaterThanOrEqual) => {
-                        let next = self.tokens.next().unwrap();
-                        let op = BinaryOperator::from_token(next).unwrap();
-
-                        if let Some(next_term) = self.parse_additive_exp() {
-                            term = AST::BinaryOp(op, Box::new(term), Box::new(next_term));
-                        } else {
-                            return None;
-                        }
-                    }
-                    _ => {
-                        break;
-                    }
+            while let Some(&Token::ShiftLeft) | Some(&Token::ShiftRight) = self.tokens.peek() {
+                let next = self.tokens.next().unwrap();
+                let op = BinaryOperator::from_token(&next).unwrap();
+
+                if let Some(next_term) = self.parse_additive_exp() {
+                    term = binary_op(op, term, next_term);
+                } else {
+                    return None;
                 }
             }
 
@@ -236,21 +793,14 @@ impl<'a> Parser<'a> {
 
     fn parse_additive_exp(&mut self) -> Option<AST> {
         self.parse_term().and_then(|mut term| {
-            loop {
-                match self.tokens.peek() {
-                    Some(&&Token::Plus) | Some(&&Token::Minus) => {
-                        let next = self.tokens.next().unwrap();
-                        let op = BinaryOperator::from_token(next).unwrap();
+            while let Some(&Token::Plus) | Some(&Token::Minus) = self.tokens.peek() {
+                let next = self.tokens.next().unwrap();
+                let op = BinaryOperator::from_token(&next).unwrap();
 
-                        if let Some(next_term) = self.parse_term() {
-                            term = AST::BinaryOp(op, Box::new(term), Box::new(next_term));
-                        } else {
-                            return None;
-                        }
-                    }
-                    _ => {
-                        break;
-                    }
+                if let Some(next_term) = self.parse_term() {
+                    term = binary_op(op, term, next_term);
+                } else {
+                    return None;
                 }
             }
 
@@ -260,21 +810,14 @@ impl<'a> Parser<'a> {
 
     fn parse_term(&mut self) -> Option<AST> {
         self.parse_factor().and_then(|mut factor| {
-            loop {
-                match self.tokens.peek() {
-                    Some(&&Token::Times) | Some(&&Token::Divide) => {
-                        let next = self.tokens.next().unwrap();
-                        let op = BinaryOperator::from_token(next).unwrap();
+            while let Some(&Token::Times) | Some(&Token::Divide) = self.tokens.peek() {
+                let next = self.tokens.next().unwrap();
+                let op = BinaryOperator::from_token(&next).unwrap();
 
-                        if let Some(next_factor) = self.parse_factor() {
-                            factor = AST::BinaryOp(op, Box::new(factor), Box::new(next_factor));
-                        } else {
-                            return None;
-                        }
-                    }
-                    _ => {
-                        break;
-                    }
+                if let Some(next_factor) = self.parse_factor() {
+                    factor = binary_op(op, factor, next_factor);
+                } else {
+                    return None;
                 }
             }
 
@@ -283,32 +826,763 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_factor(&mut self) -> Option<AST> {
-        match self.tokens.next() {
-            Some(&Token::NumLiteral(num)) => Some(AST::IntConstant(num)),
-            Some(&Token::OpenParens) => {
+        let start = self.tokens.peek_span();
+        let kind = match self.tokens.peek().cloned() {
+            Some(Token::OpenParens)
+                if self.tokens.peek_second().is_some_and(is_type_name_start) =>
+            {
+                self.tokens.next();
+                let ty = self.parse_parenthesized_type_name()?;
+                ASTKind::Cast(ty, Box::new(self.parse_factor()?))
+            }
+            Some(Token::Sizeof) => {
+                self.tokens.next();
+                // A parenthesized type name can only follow sizeof directly,
+                // whereas a parenthesized expression is just another factor.
+                if self.tokens.peek() == Some(&Token::OpenParens)
+                    && self.tokens.peek_second().is_some_and(is_type_name_start)
+                {
+                    self.tokens.next();
+                    ASTKind::SizeOfType(self.parse_parenthesized_type_name()?)
+                } else {
+                    ASTKind::SizeOfExpr(Box::new(self.parse_factor()?))
+                }
+            }
+            Some(Token::Alignof) => {
+                self.tokens.next();
+                if !self.consume(Token::OpenParens) {
+                    return None;
+                }
+
+                ASTKind::AlignOfType(self.parse_parenthesized_type_name()?)
+            }
+            Some(Token::Times) => {
+                self.tokens.next();
+                ASTKind::Dereference(Box::new(self.parse_factor()?))
+            }
+            Some(Token::Ampersand) => {
+                self.tokens.next();
+                ASTKind::AddressOf(Box::new(self.parse_factor()?))
+            }
+            Some(token) => match UnaryOperator::from_token(&token) {
+                Some(op) => {
+                    self.tokens.next();
+                    ASTKind::UnaryOp(op, Box::new(self.parse_factor()?))
+                }
+                None => return self.parse_postfix_exp(),
+            },
+            None => return None,
+        };
+
+        Some(self.node(kind, start))
+    }
+
+    // Parse a primary expression followed by any number of calls and
+    // subscripts, e.g. `callbacks[i](x)`.
+    fn parse_postfix_exp(&mut self) -> Option<AST> {
+        let mut expr = self.parse_primary()?;
+        let start = expr.span;
+
+        loop {
+            match self.tokens.peek() {
+                Some(&Token::OpenParens) => {
+                    self.tokens.next();
+                    let args = self.parse_arguments()?;
+                    expr = self.node(ASTKind::Call(Box::new(expr), args), start);
+                }
+                Some(&Token::OpenBracket) => {
+                    self.tokens.next();
+                    let index = self.parse_expression()?;
+                    if !self.consume(Token::CloseBracket) {
+                        return None;
+                    }
+
+                    // a[i] is the same as *(a + i).
+                    let address =
+                        ASTKind::BinaryOp(BinaryOperator::Plus, Box::new(expr), Box::new(index));
+                    let address = self.node(address, start);
+                    expr = self.node(ASTKind::Dereference(Box::new(address)), start);
+                }
+                Some(&Token::Dot) | Some(&Token::Arrow) => {
+                    // p->x is the same as (*p).x.
+                    if self.tokens.next() == Some(Token::Arrow) {
+                        expr = AST::new(ASTKind::Dereference(Box::new(expr)), start);
+                    }
+
+                    match self.tokens.next() {
+                        Some(Token::Identifier(name)) => {
+                            expr = self.node(ASTKind::Member(Box::new(expr), name.clone()), start);
+                        }
+                        _ => return None,
+                    }
+                }
+                _ => return Some(expr),
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Option<AST> {
+        let start = self.tokens.peek_span();
+        let kind = match self.tokens.next() {
+            Some(Token::NumLiteral(num, suffix)) => ASTKind::IntConstant(num, suffix),
+            Some(Token::FloatLiteral(num)) => ASTKind::FloatConstant(num),
+            Some(Token::DoubleLiteral(num)) => ASTKind::DoubleConstant(num),
+            Some(Token::StringLiteral(bytes)) => {
+                // Adjacent string literals are concatenated into one.
+                let mut bytes = bytes.to_vec();
+                while let Some(Token::StringLiteral(next)) = self.tokens.peek() {
+                    bytes.extend_from_slice(next);
+                    self.tokens.next();
+                }
+
+                ASTKind::StringLiteral(bytes.into_boxed_slice())
+            }
+            Some(Token::Identifier(name)) => ASTKind::Variable(name),
+            Some(Token::VaStart) => {
+                let mut args = self.parse_builtin_arguments(2)?.into_iter();
+                let (ap, last) = (args.next()?, args.next()?);
+                ASTKind::VaStart(Box::new(ap), Box::new(last))
+            }
+            Some(Token::VaArg) => {
+                if !self.consume(Token::OpenParens) {
+                    return None;
+                }
+
+                let ap = self.parse_expression()?;
+                if !self.consume(Token::Comma) {
+                    return None;
+                }
+
+                ASTKind::VaArg(Box::new(ap), self.parse_parenthesized_type_name()?)
+            }
+            Some(Token::VaEnd) => {
+                let ap = self.parse_builtin_arguments(1)?.pop()?;
+                ASTKind::VaEnd(Box::new(ap))
+            }
+            Some(Token::VaCopy) => {
+                let mut args = self.parse_builtin_arguments(2)?.into_iter();
+                let (dest, src) = (args.next()?, args.next()?);
+                ASTKind::VaCopy(Box::new(dest), Box::new(src))
+            }
+            Some(Token::Offsetof) => {
+                if !self.consume(Token::OpenParens) {
+                    return None;
+                }
+
+                let ty = self.parse_type_name()?;
+                if !self.consume(Token::Comma) {
+                    return None;
+                }
+
+                let member = match self.tokens.next() {
+                    Some(Token::Identifier(name)) => name,
+                    _ => return None,
+                };
+                if !self.consume(Token::CloseParens) {
+                    return None;
+                }
+                ASTKind::OffsetOf(ty, member)
+            }
+            Some(Token::OpenParens) => {
                 let expr = self.parse_expression();
-                if expr.is_some() && self.consume(Token::CloseParens) {
+                return if expr.is_some() && self.consume(Token::CloseParens) {
                     expr
                 } else {
                     None
+                };
+            }
+            _ => return None,
+        };
+
+        Some(self.node(kind, start))
+    }
+
+    // Parse the arguments of a function call, whose opening parenthesis has
+    // already been consumed.
+    fn parse_arguments(&mut self) -> Option<Vec<AST>> {
+        let mut args = Vec::new();
+        if self.tokens.peek() == Some(&Token::CloseParens) {
+            self.tokens.next();
+            return Some(args);
+        }
+
+        loop {
+            args.push(self.parse_expression()?);
+
+            match self.tokens.peek() {
+                Some(&Token::Comma) => self.tokens.next(),
+                Some(&Token::CloseParens) => {
+                    self.tokens.next();
+                    return Some(args);
+                }
+                _ => return self.expected(")", ""),
+            };
+        }
+    }
+
+    // Parse the parenthesized arguments of a builtin that takes exactly the
+    // given number of them.
+    fn parse_builtin_arguments(&mut self, count: usize) -> Option<Vec<AST>> {
+        if !self.consume(Token::OpenParens) {
+            return None;
+        }
+
+        self.parse_arguments().filter(|args| args.len() == count)
+    }
+
+    // Parse the rest of a type name whose opening parenthesis has already
+    // been consumed, along with its closing parenthesis.
+    fn parse_parenthesized_type_name(&mut self) -> Option<Type> {
+        let ty = self.parse_type_name()?;
+        if self.consume(Token::CloseParens) {
+            Some(ty)
+        } else {
+            None
+        }
+    }
+
+    // Parse a type name, which is a declaration without a name, as in a
+    // cast or in sizeof.
+    fn parse_type_name(&mut self) -> Option<Type> {
+        let base_type = self.parse_type_specifiers()?;
+        match self.parse_declarator(base_type)? {
+            Declarator { name: None, ty, .. } => Some(ty),
+            _ => None,
+        }
+    }
+
+    // Parse the storage-class specifiers, type specifiers and type
+    // qualifiers at the start of a declaration, which may appear in any
+    // order, e.g. `int static const`.
+    fn parse_declaration_specifiers(&mut self) -> Option<(Option<StorageClass>, Type)> {
+        let mut storage = None;
+        let mut specifiers = Vec::new();
+        let mut struct_type = None;
+        let mut qualifiers = Qualifiers::default();
+        while let Some(token) = self.tokens.peek().cloned() {
+            if token == Token::StructType {
+                if struct_type.is_some() {
+                    return None;
                 }
+                struct_type = Some(self.parse_struct_specifier()?);
+                continue;
+            } else if let Some(class) = StorageClass::from_token(&token) {
+                // A declaration can have at most one storage-class specifier.
+                if storage.is_some() {
+                    return None;
+                }
+                storage = Some(class);
+            } else if is_type_specifier(&token) {
+                specifiers.push(token);
+            } else if !add_type_qualifier(&mut qualifiers, &token) {
+                break;
             }
-            Some(ref token) => {
-                if let Some(op) = UnaryOperator::from_token(token) {
-                    self.parse_factor()
-                        .map(|factor| AST::UnaryOp(op, Box::new(factor)))
-                } else {
-                    None
+
+            self.tokens.next();
+        }
+
+        // A structure type can't be combined with any other type specifier.
+        let ty = match struct_type {
+            Some(ty) if specifiers.is_empty() => ty,
+            Some(_) => return None,
+            None => match type_from_specifiers(&specifiers) {
+                Some(ty) => ty,
+                None => {
+                    // A declaration without a type may have a misspelled one.
+                    if let Some(Token::Identifier(name)) = self.tokens.peek().cloned() {
+                        if specifiers.is_empty() {
+                            let span = self.tokens.peek_span();
+                            self.suggest_keyword(&name, span);
+                        }
+                    }
+                    return None;
                 }
+            },
+        };
+
+        Some((storage, ty.qualified(qualifiers)))
+    }
+
+    // Parse the type specifiers and qualifiers of a type name, which can't
+    // have a storage class.
+    fn parse_type_specifiers(&mut self) -> Option<Type> {
+        match self.parse_declaration_specifiers()? {
+            (None, ty) => Some(ty),
+            _ => None,
+        }
+    }
+
+    // Parse a structure specifier, which either defines a structure, e.g.
+    // `struct point { int x, y; }`, or refers to one by its tag.
+    fn parse_struct_specifier(&mut self) -> Option<Type> {
+        self.tokens.next();
+
+        let tag = match self.tokens.peek() {
+            Some(Token::Identifier(tag)) => {
+                let tag = tag.clone();
+                self.tokens.next();
+                Some(tag)
             }
             _ => None,
+        };
+
+        if self.tokens.peek() != Some(&Token::OpenBrace) {
+            let tag = tag?;
+
+            // A tag that isn't in scope yet declares a new, incomplete
+            // structure, and so does `struct tag;` on its own, even if an
+            // outer scope declares the same tag.
+            let declares = self.tokens.peek() == Some(&Token::Semicolon);
+            let existing = if declares {
+                self.struct_scopes.last().and_then(|scope| scope.get(&tag))
+            } else {
+                self.struct_scopes
+                    .iter()
+                    .rev()
+                    .filter_map(|scope| scope.get(&tag))
+                    .next()
+            };
+
+            let struct_type = match existing {
+                Some(struct_type) => struct_type.clone(),
+                None => self.declare_struct(tag),
+            };
+            return Some(Type::Struct(struct_type));
+        }
+        self.tokens.next();
+
+        // Defining a structure completes the one declared with the same tag
+        // in the current scope, unless that one is already defined.
+        let struct_type = match tag {
+            Some(tag) => match self.struct_scopes.last().and_then(|scope| scope.get(&tag)) {
+                Some(struct_type) if struct_type.is_complete() => return None,
+                Some(struct_type) => struct_type.clone(),
+                None => self.declare_struct(tag),
+            },
+            None => StructType::new(None),
+        };
+
+        let mut members: Vec<(Box<str>, Type)> = Vec::new();
+        while self.tokens.peek() != Some(&Token::CloseBrace) {
+            if self.tokens.peek().is_none() {
+                return self.expected("}", "");
+            }
+
+            let base_type = self.parse_type_specifiers()?;
+            loop {
+                let declarator = self.parse_declarator(base_type.clone())?;
+                let name = declarator.name?;
+
+                // Members must be complete objects with distinct names.
+                if declarator.ty.size().is_none() || members.iter().any(|(other, _)| *other == name)
+                {
+                    return None;
+                }
+                members.push((name, declarator.ty));
+
+                match self.tokens.peek() {
+                    Some(&Token::Comma) => self.tokens.next(),
+                    Some(&Token::Semicolon) => {
+                        self.tokens.next();
+                        break;
+                    }
+                    _ => return self.expected(";", " at end of declaration list"),
+                };
+            }
+        }
+        self.tokens.next();
+
+        if members.is_empty() {
+            return None;
+        }
+
+        struct_type.define(members);
+        Some(Type::Struct(struct_type))
+    }
+
+    fn declare_struct(&mut self, tag: Box<str>) -> StructType {
+        let struct_type = StructType::new(Some(tag.clone()));
+        if let Some(scope) = self.struct_scopes.last_mut() {
+            scope.insert(tag, struct_type.clone());
+        }
+        struct_type
+    }
+
+    // Parse a declarator, which may be abstract, and derive its type from
+    // the type named by the declaration specifiers.
+    fn parse_declarator(&mut self, base_type: Type) -> Option<Declarator> {
+        let start = self.tokens.peek_span();
+        let (name, derivations) = self.parse_derivations()?;
+        let (name, span) = match name {
+            Some((name, span)) => (Some(name), span),
+            None => (
+                None,
+                Span {
+                    end: start.start,
+                    ..start
+                },
+            ),
+        };
+
+        let mut ty = base_type;
+        let mut param_names = Vec::new();
+        for derivation in derivations {
+            param_names = Vec::new();
+            ty = match derivation {
+                Derivation::Pointer(qualifiers) => {
+                    Type::Pointer(Box::new(ty)).qualified(qualifiers)
+                }
+                Derivation::Array(length) => {
+                    // Array elements must be complete object types.
+                    ty.size()?;
+                    let array = Type::Array(Box::new(ty), length);
+                    if array.is_too_large() {
+                        let length = length.unwrap_or_default();
+                        return self
+                            .fail(format!("array is too large ({} elements)", length), span);
+                    }
+                    array
+                }
+                Derivation::Function(params, variadic) => {
+                    // Functions can't return arrays or functions.
+                    if ty.is_array() || ty.is_function() {
+                        return None;
+                    }
+
+                    let params = params.map(|params| {
+                        let (types, names) = params.into_iter().unzip();
+                        param_names = names;
+                        types
+                    });
+                    Type::Function(Box::new(FunctionType {
+                        return_type: ty,
+                        params,
+                        variadic,
+                    }))
+                }
+            };
+        }
+
+        Some(Declarator {
+            name,
+            span,
+            ty,
+            param_names,
+        })
+    }
+
+    // Parse the parts of a declarator, returning its name and where it is,
+    // and the steps that
+    // derive its type from the base type in the order in which they apply.
+    // Pointers bind less tightly than the array and function suffixes, and
+    // a parenthesized declarator applies last, so in `int *(*x)[4]` the
+    // steps are pointer, array of 4, pointer.
+    fn parse_derivations(&mut self) -> Option<(Option<Name>, Vec<Derivation>)> {
+        let mut derivations = Vec::new();
+        while self.tokens.peek() == Some(&Token::Times) {
+            self.tokens.next();
+            derivations.push(Derivation::Pointer(self.parse_type_qualifiers()));
+        }
+
+        let (name, nested) = match self.tokens.peek().cloned() {
+            Some(Token::Identifier(name)) => {
+                self.tokens.next();
+                (Some((name.clone(), self.tokens.previous)), Vec::new())
+            }
+            // An opening parenthesis followed by a type, or by nothing at
+            // all, starts a parameter list instead.
+            Some(Token::OpenParens)
+                if self.tokens.peek_second().is_some_and(|token| {
+                    !is_type_name_start(token) && *token != Token::CloseParens
+                }) =>
+            {
+                self.tokens.next();
+                let nested = self.parse_derivations()?;
+                if !self.consume(Token::CloseParens) {
+                    return None;
+                }
+                nested
+            }
+            _ => (None, Vec::new()),
+        };
+
+        let mut suffixes = Vec::new();
+        loop {
+            match self.tokens.peek() {
+                Some(&Token::OpenBracket) => {
+                    self.tokens.next();
+                    let length = if self.tokens.peek() == Some(&Token::CloseBracket) {
+                        None
+                    } else {
+                        Some(self.parse_array_length()?)
+                    };
+                    if !self.consume(Token::CloseBracket) {
+                        return None;
+                    }
+                    suffixes.push(Derivation::Array(length));
+                }
+                Some(&Token::OpenParens) => {
+                    self.tokens.next();
+                    let (params, variadic) = self.parse_parameter_list()?;
+                    suffixes.push(Derivation::Function(params, variadic));
+                }
+                _ => break,
+            }
         }
+
+        derivations.extend(suffixes.into_iter().rev());
+        derivations.extend(nested);
+        Some((name, derivations))
     }
 
+    // Parse the length of an array declarator, which must be a positive
+    // integer constant expression.
+    fn parse_array_length(&mut self) -> Option<u64> {
+        let expr = self.parse_logical_or_exp()?;
+
+        // The types of variables aren't known while parsing, so the size of
+        // an expression can't be taken here.
+        let mut type_of = |operand: &AST| {
+            Err(constant::Error::NotConstant(
+                operand.span,
+                "the operand of 'sizeof' in an array size must be a type name".to_owned(),
+            ))
+        };
+        let length = match constant::evaluate(&expr, &mut type_of) {
+            Ok(length) => length,
+            Err(error) => {
+                let context = "array size is not an integer constant expression";
+                self.error = Some(error.into_error(context, expr.span));
+                return None;
+            }
+        };
+
+        match length.value {
+            0 => self.fail("zero-length arrays are not supported".to_owned(), expr.span),
+            value if value < 0 && length.ty.is_signed() => {
+                self.fail("array size is negative".to_owned(), expr.span)
+            }
+            value => Some(value as u64),
+        }
+    }
+
+    fn parse_type_qualifiers(&mut self) -> Qualifiers {
+        let mut qualifiers = Qualifiers::default();
+        while let Some(token) = self.tokens.peek() {
+            if !add_type_qualifier(&mut qualifiers, token) {
+                break;
+            }
+            self.tokens.next();
+        }
+
+        qualifiers
+    }
+
+    // Consume the terminating semicolon of a statement that starts at the
+    // given span, and return the statement.
+    fn consume_semicolon(&mut self, statement: ASTKind, start: Span) -> Option<AST> {
+        if self.tokens.peek() == Some(&Token::Semicolon) {
+            self.tokens.next();
+            return Some(self.node(statement, start));
+        }
+
+        // An expression statement that's just a name or a call, followed by
+        // something else, is likely to start with a misspelled keyword, as in
+        // `itn x;` or `swtich (x) {`.
+        if let ASTKind::Expression(ref expr) = statement {
+            let name = match expr.kind {
+                ASTKind::Call(ref callee, _) => callee,
+                _ => expr,
+            };
+            if let ASTKind::Variable(ref keyword) = name.kind {
+                if self.suggest_keyword(keyword, name.span) {
+                    return None;
+                }
+            }
+        }
+
+        let context = match statement {
+            ASTKind::Expression(_) => " after expression",
+            ASTKind::Return(_) => " after return statement",
+            ASTKind::Break => " after break statement",
+            ASTKind::Goto(_) => " after goto statement",
+            ASTKind::StaticAssert(..) => " after static assertion",
+            _ => "",
+        };
+        self.expected(";", context)
+    }
+
+    // A node that spans from the start of a given span to the end of the
+    // last token taken.
+    fn node(&self, kind: ASTKind, start: Span) -> AST {
+        AST::new(kind, start.to(self.tokens.previous))
+    }
+
+    // Stop parsing at an identifier that's close enough to a keyword to be
+    // a misspelling of it, suggesting the keyword. Returns false if there's
+    // no such keyword.
+    fn suggest_keyword(&mut self, name: &str, span: Span) -> bool {
+        let keyword = match diagnostics::closest_match(name, lexer::keywords()) {
+            Some(keyword) => keyword,
+            None => return false,
+        };
+
+        let description = if lexer::keyword(keyword).is_some_and(is_declaration_specifier) {
+            "unknown type name"
+        } else {
+            "use of undeclared identifier"
+        };
+        self.diagnostics.suggest_replacement(span, name, keyword);
+        let message = format!("{} '{}'; did you mean '{}'?", description, name, keyword);
+        self.fail::<()>(message, span);
+        true
+    }
+
+    // Stop parsing because a token is missing, suggesting to insert it just
+    // after the last token.
+    fn expected<T>(&mut self, token: &str, context: &str) -> Option<T> {
+        let span = self.tokens.end();
+        self.diagnostics.suggest_insertion(span, token);
+        self.fail(format!("expected '{}'{}", token, context), span)
+    }
+
+    // Stop parsing because of an error at a span that has more to say about
+    // what's wrong than that the program doesn't parse.
+    fn fail<T>(&mut self, message: String, span: Span) -> Option<T> {
+        self.error = Some(Error::at(message, span));
+        None
+    }
+
+    // Consume the next token, which should be the given one. A missing
+    // closing token is reported as such, as it's a common mistake.
     fn consume(&mut self, token: Token) -> bool {
-        self.tokens
-            .next()
-            .map(|next| *next == token)
-            .unwrap_or(false)
+        if self.tokens.peek() == Some(&token) {
+            self.tokens.next();
+            return true;
+        }
+
+        let spelling = match token {
+            Token::Semicolon => ";",
+            Token::CloseParens => ")",
+            Token::CloseBrace => "}",
+            _ => return false,
+        };
+        self.expected::<()>(spelling, "");
+        false
+    }
+}
+
+fn binary_op(op: BinaryOperator, lhs: AST, rhs: AST) -> AST {
+    let span = lhs.span.to(rhs.span);
+    AST::new(ASTKind::BinaryOp(op, Box::new(lhs), Box::new(rhs)), span)
+}
+
+fn is_declaration_specifier(token: &Token) -> bool {
+    is_type_name_start(token) || StorageClass::from_token(token).is_some()
+}
+
+// Whether a token can start a type name, as in a cast or sizeof.
+fn is_type_name_start(token: &Token) -> bool {
+    is_type_specifier(token) || matches!(*token, Token::StructType | Token::Const | Token::Volatile)
+}
+
+// Add the qualifier named by a token to a set of qualifiers, returning false
+// if the token isn't a type qualifier. Repeating a qualifier is allowed.
+fn add_type_qualifier(qualifiers: &mut Qualifiers, token: &Token) -> bool {
+    match *token {
+        Token::Const => qualifiers.is_const = true,
+        Token::Volatile => qualifiers.is_volatile = true,
+        _ => return false,
     }
+
+    true
+}
+
+fn is_type_specifier(token: &Token) -> bool {
+    matches!(
+        *token,
+        Token::VoidType
+            | Token::BoolType
+            | Token::CharType
+            | Token::ShortType
+            | Token::IntType
+            | Token::LongType
+            | Token::FloatType
+            | Token::DoubleType
+            | Token::Signed
+            | Token::Unsigned
+            | Token::VaListType
+    )
+}
+
+// A parameter declared as an array or a function, including a va_list,
+// really has the type of the pointer that it decays to.
+fn adjust_parameter_type(ty: Type) -> Type {
+    if ty.decays() {
+        ty.decay()
+    } else {
+        ty
+    }
+}
+
+// Work out the type named by a list of type specifiers, which may appear in
+// any order, e.g. `unsigned long int` or `long unsigned`.
+fn type_from_specifiers(specifiers: &[Token]) -> Option<Type> {
+    let count = |want: Token| specifiers.iter().filter(|&token| *token == want).count();
+
+    let void = count(Token::VoidType);
+    let bools = count(Token::BoolType);
+    let va_lists = count(Token::VaListType);
+    let floats = count(Token::FloatType);
+    let doubles = count(Token::DoubleType);
+    let chars = count(Token::CharType);
+    let shorts = count(Token::ShortType);
+    let ints = count(Token::IntType);
+    let longs = count(Token::LongType);
+    let signed = count(Token::Signed);
+    let unsigned = count(Token::Unsigned);
+
+    if specifiers.is_empty()
+        || signed + unsigned > 1
+        || ints > 1
+        || longs > 2
+        || void + chars + shorts > 1
+        || (void + chars + shorts > 0 && longs > 0)
+        || (chars > 0 && ints > 0)
+    {
+        return None;
+    }
+
+    // void, _Bool, float, double and va_list can't be combined with any
+    // other specifier, which also rules out `long double` since we don't
+    // support it.
+    let standalone = [
+        (void, Type::Void),
+        (bools, Type::Bool),
+        (floats, Type::Float),
+        (doubles, Type::Double),
+        (va_lists, Type::VaList),
+    ];
+    for &(count, ref ty) in &standalone {
+        if count > 0 {
+            return if specifiers.len() == 1 {
+                Some(ty.clone())
+            } else {
+                None
+            };
+        }
+    }
+
+    let ty = match (chars > 0, shorts > 0, longs > 0, unsigned > 0) {
+        (true, _, _, false) => Type::Char,
+        (true, _, _, true) => Type::UnsignedChar,
+        (_, true, _, false) => Type::Short,
+        (_, true, _, true) => Type::UnsignedShort,
+        (_, _, true, false) => Type::Long,
+        (_, _, true, true) => Type::UnsignedLong,
+        (_, _, _, false) => Type::Int,
+        (_, _, _, true) => Type::UnsignedInt,
+    };
+
+    Some(ty)
 }
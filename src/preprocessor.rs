@@ -0,0 +1,1572 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::slice;
+
+use diagnostics::Location;
+use headers;
+use source_map::{self, Origin, OriginMap, SourceMap};
+
+// Includes nested deeper than this are almost certainly recursive.
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+#[derive(Debug)]
+pub struct Error {
+    pub location: Location,
+    pub message: String,
+}
+
+// The text that results from preprocessing a file, along with where each of
+// its tokens came from.
+pub struct Output {
+    pub text: String,
+    pub origins: OriginMap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Identifier,
+    Number,
+    StringLiteral,
+    CharLiteral,
+    Punctuator,
+    // A character that isn't part of any other token, such as `@`.
+    Other,
+    // Stands in for an empty macro argument next to `##`, and is removed
+    // once the replacement list has been built.
+    Placemarker,
+}
+
+// A preprocessing token, which is kept as it was spelled in the source.
+#[derive(Debug, Clone)]
+struct PpToken {
+    kind: Kind,
+    text: String,
+    line: usize,
+    // The column, counting bytes from 1, that the token starts at on its
+    // line, or 0 if it doesn't come from the source. A token that a macro
+    // expands to has the position of the macro's invocation.
+    column: usize,
+    // The offset of the token in the text of its file after translation
+    // phases 1 and 2.
+    offset: usize,
+    expanded: bool,
+    // Whether the token is the first on its line, which makes a `#` start
+    // a directive.
+    first_on_line: bool,
+    has_space: bool,
+    // The macros that must not be expanded again within this token.
+    hide_set: HashSet<String>,
+}
+
+impl PpToken {
+    fn new(kind: Kind, text: &str, line: usize) -> Self {
+        PpToken {
+            kind,
+            text: text.to_owned(),
+            line,
+            column: 0,
+            offset: 0,
+            expanded: false,
+            first_on_line: false,
+            has_space: false,
+            hide_set: HashSet::new(),
+        }
+    }
+
+    fn is(&self, punctuator: &str) -> bool {
+        self.kind == Kind::Punctuator && self.text == punctuator
+    }
+
+    fn is_identifier(&self) -> bool {
+        self.kind == Kind::Identifier
+    }
+}
+
+struct Macro {
+    // The parameters of a function-like macro, or None for an object-like
+    // one. A variadic macro takes its variable arguments as __VA_ARGS__.
+    params: Option<Vec<String>>,
+    variadic: bool,
+    body: Vec<PpToken>,
+}
+
+// A file being preprocessed, with the name and line numbering it presents
+// through __FILE__ and __LINE__, which #line can change.
+struct Source {
+    path: PathBuf,
+    name: String,
+    // The text of the file, for showing the lines that errors are on.
+    text: Rc<str>,
+    map: Rc<SourceMap>,
+    line_offset: i64,
+    // The file that the origins of its tokens refer to, which a #line
+    // directive replaces.
+    file: usize,
+    // The line of the file that the end of the output corresponds to.
+    output_line: usize,
+}
+
+impl Source {
+    fn presumed_line(&self, line: usize) -> usize {
+        (line as i64 + self.line_offset).max(0) as usize
+    }
+}
+
+// The state of an #if, #ifdef or #ifndef group and its #elif and #else
+// branches.
+struct Conditional {
+    // Whether the branch being read is included in the output.
+    active: bool,
+    // Whether any branch has been included yet, so later ones are skipped.
+    taken: bool,
+    seen_else: bool,
+    // Whether the whole group is inside an included branch.
+    parent_active: bool,
+    // The line of the #if, for reporting a missing #endif.
+    line: usize,
+}
+
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    // Files guarded by #pragma once, which are only ever included once.
+    once: HashSet<PathBuf>,
+    sources: Vec<Source>,
+    output: String,
+    origins: OriginMap,
+}
+
+impl Preprocessor {
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        let mut predefined = vec!["__STDC__", "__x86_64__"];
+        if cfg!(target_os = "macos") {
+            predefined.push("__APPLE__");
+        } else if cfg!(target_os = "linux") {
+            predefined.push("__linux__");
+        }
+
+        let mut macros = HashMap::new();
+        for name in predefined {
+            let body = vec![PpToken::new(Kind::Number, "1", 0)];
+            macros.insert(
+                name.to_owned(),
+                Macro {
+                    params: None,
+                    variadic: false,
+                    body,
+                },
+            );
+        }
+
+        Preprocessor {
+            include_paths,
+            macros,
+            once: HashSet::new(),
+            sources: Vec::new(),
+            output: String::new(),
+            origins: OriginMap::new(),
+        }
+    }
+
+    // Preprocess a source file, returning the text that results once its
+    // directives have been carried out and its macros expanded.
+    pub fn preprocess(mut self, path: &Path, source: &str) -> Result<Output, Error> {
+        self.process(path, source)?;
+        if !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+        Ok(Output {
+            text: self.output,
+            origins: self.origins,
+        })
+    }
+
+    fn process(&mut self, path: &Path, source: &str) -> Result<(), Error> {
+        let name = path.to_string_lossy().into_owned();
+
+        let map = Rc::new(SourceMap::new(source));
+        let tokens = tokenize(&map).map_err(|(line, column, message)| Error {
+            location: Location {
+                file: name.clone(),
+                line,
+                columns: Some(column..column + 1),
+                source_line: source_map::physical_line(source, line).map(str::to_owned),
+            },
+            message,
+        })?;
+
+        if !self.output.is_empty() && !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+        let text: Rc<str> = Rc::from(source);
+        let file = self
+            .origins
+            .add_file(name.clone(), text.clone(), map.clone(), 0);
+        self.sources.push(Source {
+            path: path.to_owned(),
+            name,
+            text,
+            map,
+            line_offset: 0,
+            file,
+            output_line: 1,
+        });
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut text = VecDeque::new();
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let active = conditionals
+                .last()
+                .is_none_or(|conditional| conditional.active);
+
+            if !(tokens[i].first_on_line && tokens[i].is("#")) {
+                if active {
+                    text.push_back(tokens[i].clone());
+                }
+                i += 1;
+                continue;
+            }
+
+            // A directive runs until the end of its line.
+            let line = tokens[i].line;
+            let end = tokens[i + 1..]
+                .iter()
+                .position(|token| token.first_on_line)
+                .map_or(tokens.len(), |position| i + 1 + position);
+            let directive = &tokens[i + 1..end];
+            i = end;
+
+            if !text.is_empty() {
+                let expanded = self.expand(text)?;
+                self.emit(&expanded);
+                text = VecDeque::new();
+            }
+
+            self.directive(line, directive, &mut conditionals)?;
+        }
+
+        if !text.is_empty() {
+            let expanded = self.expand(text)?;
+            self.emit(&expanded);
+        }
+
+        if let Some(conditional) = conditionals.first() {
+            return Err(self.error(conditional.line, "unterminated conditional directive"));
+        }
+
+        self.sources.pop();
+        Ok(())
+    }
+
+    fn directive(
+        &mut self,
+        line: usize,
+        tokens: &[PpToken],
+        conditionals: &mut Vec<Conditional>,
+    ) -> Result<(), Error> {
+        // The null directive does nothing.
+        let name = match tokens.first() {
+            Some(token) => token.text.as_str(),
+            None => return Ok(()),
+        };
+        let name_token = &tokens[..1];
+        let rest = &tokens[1..];
+        let active = conditionals
+            .last()
+            .is_none_or(|conditional| conditional.active);
+
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let condition = if !active {
+                    false
+                } else if name == "if" {
+                    self.evaluate_condition(line, rest)?
+                } else {
+                    let defined = self.macro_name(line, rest)?;
+                    self.macros.contains_key(&defined) == (name == "ifdef")
+                };
+
+                conditionals.push(Conditional {
+                    active: condition,
+                    taken: condition,
+                    seen_else: false,
+                    parent_active: active,
+                    line,
+                });
+            }
+            "elif" => {
+                let (parent_active, taken) = match conditionals.last() {
+                    Some(conditional) if conditional.seen_else => {
+                        return Err(self.error_at(name_token, "#elif after #else"));
+                    }
+                    Some(conditional) => (conditional.parent_active, conditional.taken),
+                    None => return Err(self.error_at(name_token, "#elif without #if")),
+                };
+
+                // The condition isn't even evaluated once a branch has been
+                // taken.
+                let condition = parent_active && !taken && self.evaluate_condition(line, rest)?;
+                let conditional = conditionals.last_mut().unwrap();
+                conditional.active = condition;
+                conditional.taken |= condition;
+            }
+            "else" => {
+                let conditional = match conditionals.last_mut() {
+                    Some(conditional) if conditional.seen_else => {
+                        return Err(self.error_at(name_token, "#else after #else"));
+                    }
+                    Some(conditional) => conditional,
+                    None => return Err(self.error_at(name_token, "#else without #if")),
+                };
+
+                conditional.active = conditional.parent_active && !conditional.taken;
+                conditional.taken = true;
+                conditional.seen_else = true;
+            }
+            "endif" => {
+                if conditionals.pop().is_none() {
+                    return Err(self.error_at(name_token, "#endif without #if"));
+                }
+            }
+            // Other directives are ignored in skipped groups, even if they
+            // aren't valid.
+            _ if !active => {}
+            "define" => self.define(line, rest)?,
+            "undef" => {
+                let name = self.macro_name(line, rest)?;
+                self.macros.remove(&name);
+            }
+            "include" => self.include(line, rest)?,
+            "line" => self.line(line, rest)?,
+            "error" => {
+                let message = spell(rest);
+                return Err(self.error_at(name_token, format!("#error {}", message).trim_end()));
+            }
+            "pragma" => {
+                if rest.len() == 1 && rest[0].text == "once" {
+                    let path = self.current().path.clone();
+                    self.once.insert(fs::canonicalize(&path).unwrap_or(path));
+                }
+                // Any other pragma is ignored.
+            }
+            _ => return Err(self.error_at(name_token, "invalid preprocessing directive")),
+        }
+
+        Ok(())
+    }
+
+    // The name of the macro that a directive is about.
+    fn macro_name(&self, line: usize, tokens: &[PpToken]) -> Result<String, Error> {
+        match tokens.first() {
+            Some(token) if token.is_identifier() => {
+                if token.text == "defined" {
+                    let message = "'defined' cannot be used as a macro name";
+                    return Err(self.error_at(&tokens[..1], message));
+                }
+                Ok(token.text.clone())
+            }
+            Some(_) => Err(self.error_at(&tokens[..1], "macro name must be an identifier")),
+            None => Err(self.error(line, "macro name missing")),
+        }
+    }
+
+    fn define(&mut self, line: usize, tokens: &[PpToken]) -> Result<(), Error> {
+        let name = self.macro_name(line, tokens)?;
+
+        // A macro is function-like if its name is immediately followed by an
+        // opening parenthesis, without any whitespace in between.
+        let mut position = 1;
+        let mut params = None;
+        let mut variadic = false;
+        if tokens
+            .get(1)
+            .is_some_and(|token| token.is("(") && !token.has_space)
+        {
+            let mut names = Vec::new();
+            position = 2;
+            loop {
+                match tokens.get(position) {
+                    Some(token) if token.is(")") && names.is_empty() => break,
+                    Some(token) if token.is("...") => {
+                        variadic = true;
+                        position += 1;
+                        if !tokens.get(position).is_some_and(|token| token.is(")")) {
+                            return Err(self.error(line, "missing ')' in macro parameter list"));
+                        }
+                        break;
+                    }
+                    Some(token) if token.is_identifier() => {
+                        if names.contains(&token.text) {
+                            return Err(self.error(
+                                line,
+                                &format!("duplicate macro parameter name '{}'", token.text),
+                            ));
+                        }
+                        names.push(token.text.clone());
+                        position += 1;
+                        match tokens.get(position) {
+                            Some(token) if token.is(")") => break,
+                            Some(token) if token.is(",") => position += 1,
+                            _ => {
+                                return Err(
+                                    self.error(line, "expected comma in macro parameter list")
+                                );
+                            }
+                        }
+                    }
+                    _ => return Err(self.error(line, "invalid token in macro parameter list")),
+                }
+            }
+            position += 1;
+            params = Some(names);
+        }
+
+        let mut body = tokens[position..].to_vec();
+        if let Some(first) = body.first_mut() {
+            first.has_space = false;
+        }
+
+        if body.first().is_some_and(|token| token.is("##"))
+            || body.last().is_some_and(|token| token.is("##"))
+        {
+            return Err(self.error(
+                line,
+                "'##' cannot appear at either end of a macro expansion",
+            ));
+        }
+
+        // In a function-like macro, # must stringize a parameter.
+        if let Some(ref names) = params {
+            for (i, token) in body.iter().enumerate() {
+                if token.is("#") {
+                    let is_param = body.get(i + 1).is_some_and(|next| {
+                        names.contains(&next.text) || (variadic && next.text == "__VA_ARGS__")
+                    });
+                    if !is_param {
+                        return Err(self.error(line, "'#' is not followed by a macro parameter"));
+                    }
+                }
+            }
+        }
+
+        self.macros.insert(
+            name,
+            Macro {
+                params,
+                variadic,
+                body,
+            },
+        );
+        Ok(())
+    }
+
+    fn include(&mut self, line: usize, tokens: &[PpToken]) -> Result<(), Error> {
+        // If the directive doesn't have either form of file name, it's macro
+        // expanded and then has to.
+        let is_file_name = |tokens: &[PpToken]| {
+            tokens
+                .first()
+                .is_some_and(|token| token.kind == Kind::StringLiteral || token.is("<"))
+        };
+        let tokens = if is_file_name(tokens) {
+            tokens.to_vec()
+        } else {
+            self.expand(tokens.iter().cloned().collect())?
+        };
+
+        let (name, quoted) = match tokens.first() {
+            Some(token) if token.kind == Kind::StringLiteral && tokens.len() == 1 => {
+                (token.text[1..token.text.len() - 1].to_owned(), true)
+            }
+            Some(token) if token.is("<") => match tokens.iter().position(|token| token.is(">")) {
+                Some(end) if end == tokens.len() - 1 => (spell(&tokens[1..end]), false),
+                _ => return Err(self.error(line, "expected '>'")),
+            },
+            _ => return Err(self.error(line, "expected \"FILENAME\" or <FILENAME>")),
+        };
+
+        if name.is_empty() {
+            return Err(self.error(line, "empty filename"));
+        }
+
+        // A quoted name is looked for next to the file that includes it
+        // before the include paths.
+        let mut directories = Vec::new();
+        if quoted {
+            let current = &self.current().path;
+            directories.push(current.parent().map_or(PathBuf::new(), Path::to_path_buf));
+        }
+        directories.extend(self.include_paths.iter().cloned());
+
+        let path = directories
+            .iter()
+            .map(|directory| directory.join(&name))
+            .find(|path| path.is_file());
+
+        if self.sources.len() >= MAX_INCLUDE_DEPTH {
+            return Err(self.error(line, "#include nested too deeply"));
+        }
+
+        // The bundled headers come last, so that they can be overridden.
+        let (path, source) = match path {
+            Some(path) => {
+                let source = fs::read_to_string(&path)
+                    .map_err(|_| self.error_at(&tokens, &format!("could not read '{}'", name)))?;
+                (path, source)
+            }
+            None => match headers::find(&name) {
+                Some(source) => (Path::new("<built-in>").join(&name), source.to_owned()),
+                None => return Err(self.error_at(&tokens, &format!("'{}' file not found", name))),
+            },
+        };
+
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.once.contains(&canonical) {
+            return Ok(());
+        }
+
+        self.process(&path, &source)
+    }
+
+    fn line(&mut self, line: usize, tokens: &[PpToken]) -> Result<(), Error> {
+        let tokens = self.expand(tokens.iter().cloned().collect())?;
+
+        let number = match tokens.first() {
+            Some(token) if token.kind == Kind::Number => token.text.parse::<usize>().ok(),
+            _ => None,
+        };
+        let number = match number {
+            Some(number) if token_is_digits(&tokens[0]) => number,
+            _ => return Err(self.error(line, "#line directive requires a simple digit sequence")),
+        };
+
+        let name = match tokens.get(1) {
+            Some(token) if token.kind == Kind::StringLiteral && tokens.len() == 2 => {
+                Some(token.text[1..token.text.len() - 1].to_owned())
+            }
+            None => None,
+            Some(_) => return Err(self.error(line, "invalid filename for #line directive")),
+        };
+
+        // The line after the directive has the given number.
+        let source = self.sources.last_mut().unwrap();
+        source.line_offset = number as i64 - (line as i64 + 1);
+        if let Some(name) = name {
+            source.name = name;
+        }
+        source.file = self.origins.add_file(
+            source.name.clone(),
+            source.text.clone(),
+            source.map.clone(),
+            source.line_offset,
+        );
+        Ok(())
+    }
+
+    fn evaluate_condition(&mut self, line: usize, tokens: &[PpToken]) -> Result<bool, Error> {
+        // The operands of `defined` are replaced before macros are expanded.
+        let mut replaced = VecDeque::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            if tokens[i].text != "defined" {
+                replaced.push_back(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+
+            let parenthesized = tokens.get(i + 1).is_some_and(|token| token.is("("));
+            let name_index = if parenthesized { i + 2 } else { i + 1 };
+            let name = match tokens.get(name_index) {
+                Some(token) if token.is_identifier() => &token.text,
+                _ => return Err(self.error(line, "macro name must be an identifier")),
+            };
+            if parenthesized
+                && !tokens
+                    .get(name_index + 1)
+                    .is_some_and(|token| token.is(")"))
+            {
+                return Err(self.error(line, "missing ')' after 'defined'"));
+            }
+
+            let value = if self.macros.contains_key(name) {
+                "1"
+            } else {
+                "0"
+            };
+            replaced.push_back(PpToken::new(Kind::Number, value, line));
+            i = name_index + if parenthesized { 2 } else { 1 };
+        }
+
+        let expanded = self.expand(replaced)?;
+        if expanded.is_empty() {
+            return Err(self.error(line, "expected value in expression"));
+        }
+
+        let mut evaluator = Evaluator {
+            tokens: &expanded,
+            position: 0,
+        };
+        let value = evaluator.conditional(true);
+        match value {
+            Ok(_) if evaluator.position < expanded.len() => Err(self.error(
+                line,
+                "token is not a valid binary operator in a preprocessor subexpression",
+            )),
+            Ok(value) => Ok(value.value != 0),
+            Err(message) => Err(self.error(line, &message)),
+        }
+    }
+
+    // Expand the macros in a sequence of tokens, rescanning the result of
+    // each expansion along with the rest of the tokens. Each token carries
+    // the set of macros that it came from, which aren't expanded again.
+    fn expand(&mut self, mut input: VecDeque<PpToken>) -> Result<Vec<PpToken>, Error> {
+        let mut output = Vec::new();
+
+        while let Some(token) = input.pop_front() {
+            if !token.is_identifier() || token.hide_set.contains(&token.text) {
+                output.push(token);
+                continue;
+            }
+
+            if let Some(predefined) = self.predefined(&token) {
+                output.push(predefined);
+                continue;
+            }
+
+            let (is_function, variadic) = match self.macros.get(&token.text) {
+                Some(definition) => (definition.params.is_some(), definition.variadic),
+                None => {
+                    output.push(token);
+                    continue;
+                }
+            };
+
+            let replacement = if !is_function {
+                let mut hide_set = token.hide_set.clone();
+                hide_set.insert(token.text.clone());
+                self.substitute(&token.text, &[], hide_set)?
+            } else if input.front().is_some_and(|next| next.is("(")) {
+                // A function-like macro name is only an invocation if it's
+                // followed by its arguments.
+                input.pop_front();
+                let (args, close) = self.collect_arguments(&token, &mut input, variadic)?;
+                let mut hide_set: HashSet<String> = token
+                    .hide_set
+                    .intersection(&close.hide_set)
+                    .cloned()
+                    .collect();
+                hide_set.insert(token.text.clone());
+                self.substitute(&token.text, &args, hide_set)?
+            } else {
+                output.push(token);
+                continue;
+            };
+
+            // The expansion takes the place of the macro invocation, on its
+            // line and with its leading whitespace.
+            let mut replacement: Vec<PpToken> = replacement
+                .into_iter()
+                .map(|mut replaced| {
+                    replaced.line = token.line;
+                    replaced.column = token.column;
+                    replaced.offset = token.offset;
+                    replaced.expanded = true;
+                    replaced.first_on_line = false;
+                    replaced
+                })
+                .collect();
+            if let Some(first) = replacement.first_mut() {
+                first.has_space = token.has_space;
+                first.first_on_line = token.first_on_line;
+            }
+            for replaced in replacement.into_iter().rev() {
+                input.push_front(replaced);
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn predefined(&self, token: &PpToken) -> Option<PpToken> {
+        let (kind, text) = match token.text.as_str() {
+            "__FILE__" => (
+                Kind::StringLiteral,
+                format!("\"{}\"", escape(&self.current().name)),
+            ),
+            "__LINE__" => (
+                Kind::Number,
+                self.current().presumed_line(token.line).to_string(),
+            ),
+            _ => return None,
+        };
+
+        let mut predefined = token.clone();
+        predefined.kind = kind;
+        predefined.text = text;
+        predefined.expanded = true;
+        Some(predefined)
+    }
+
+    // Collect the arguments of a function-like macro invocation, whose
+    // opening parenthesis has been consumed, up to the matching closing one.
+    // The variable arguments of a variadic macro are collected as one, with
+    // the commas between them.
+    fn collect_arguments(
+        &self,
+        name: &PpToken,
+        input: &mut VecDeque<PpToken>,
+        variadic: bool,
+    ) -> Result<(Vec<Vec<PpToken>>, PpToken), Error> {
+        let param_count = self.macros[&name.text].params.as_ref().map_or(0, Vec::len);
+        let mut args = vec![Vec::new()];
+        let mut depth = 0;
+
+        loop {
+            let token = match input.pop_front() {
+                Some(token) => token,
+                None => {
+                    let message = "unterminated function-like macro invocation";
+                    return Err(self.error_at(slice::from_ref(name), message));
+                }
+            };
+
+            if token.is(")") && depth == 0 {
+                // An invocation of a macro without parameters has a single
+                // empty argument, and the variable arguments may be left out
+                // altogether.
+                if param_count == 0 && !variadic && args.len() == 1 && args[0].is_empty() {
+                    args.clear();
+                }
+                if variadic && args.len() == param_count {
+                    args.push(Vec::new());
+                }
+
+                let expected = param_count + variadic as usize;
+                if args.len() != expected {
+                    return Err(self.error_at(
+                        slice::from_ref(&token),
+                        &format!(
+                            "too {} arguments provided to function-like macro invocation",
+                            if args.len() < expected { "few" } else { "many" }
+                        ),
+                    ));
+                }
+                return Ok((args, token));
+            }
+
+            if token.is("(") {
+                depth += 1;
+            } else if token.is(")") {
+                depth -= 1;
+            } else if token.is(",") && depth == 0 && !(variadic && args.len() > param_count) {
+                args.push(Vec::new());
+                continue;
+            }
+            args.last_mut().unwrap().push(token);
+        }
+    }
+
+    // Build the replacement list of a macro invocation, with its parameters
+    // replaced by the given arguments and the # and ## operators applied.
+    fn substitute(
+        &mut self,
+        name: &str,
+        args: &[Vec<PpToken>],
+        hide_set: HashSet<String>,
+    ) -> Result<Vec<PpToken>, Error> {
+        let (body, params) = {
+            let definition = &self.macros[name];
+            let mut params = definition.params.clone().unwrap_or_default();
+            if definition.variadic {
+                params.push("__VA_ARGS__".to_owned());
+            }
+            (definition.body.clone(), params)
+        };
+        let param_index = |token: &PpToken| {
+            if token.is_identifier() {
+                params.iter().position(|param| *param == token.text)
+            } else {
+                None
+            }
+        };
+
+        let mut output: Vec<PpToken> = Vec::new();
+        let mut expanded_args: HashMap<usize, Vec<PpToken>> = HashMap::new();
+        let mut i = 0;
+
+        while i < body.len() {
+            let token = &body[i];
+
+            if token.is("#") && !params.is_empty() {
+                if let Some(index) = body.get(i + 1).and_then(param_index) {
+                    let mut string =
+                        PpToken::new(Kind::StringLiteral, &stringize(&args[index]), token.line);
+                    string.has_space = token.has_space;
+                    output.push(string);
+                    i += 2;
+                    continue;
+                }
+            }
+
+            if token.is("##") {
+                let rhs = match body.get(i + 1).and_then(param_index) {
+                    // An argument next to ## isn't expanded.
+                    Some(index) => {
+                        let arg = &args[index];
+                        // As an extension, `, ## __VA_ARGS__` drops the comma
+                        // if there are no variable arguments, and otherwise
+                        // pastes nothing.
+                        if params[index] == "__VA_ARGS__"
+                            && output.last().is_some_and(|last| last.is(","))
+                        {
+                            if arg.is_empty() {
+                                output.pop();
+                            } else {
+                                push_argument(&mut output, arg.clone(), body[i + 1].has_space);
+                            }
+                            i += 2;
+                            continue;
+                        }
+                        arg.clone()
+                    }
+                    None => vec![body[i + 1].clone()],
+                };
+                i += 2;
+
+                let lhs = output.pop().unwrap_or_else(|| placemarker(token.line));
+                let mut rhs = rhs.into_iter();
+                let first = rhs.next().unwrap_or_else(|| placemarker(token.line));
+                output.push(self.paste(&lhs, &first)?);
+                output.extend(rhs);
+                continue;
+            }
+
+            match param_index(token) {
+                Some(index) if body.get(i + 1).is_some_and(|next| next.is("##")) => {
+                    let arg = &args[index];
+                    if arg.is_empty() {
+                        output.push(placemarker(token.line));
+                    } else {
+                        push_argument(&mut output, arg.clone(), token.has_space);
+                    }
+                }
+                Some(index) => {
+                    if let Entry::Vacant(entry) = expanded_args.entry(index) {
+                        entry.insert(self.expand(args[index].iter().cloned().collect())?);
+                    }
+                    push_argument(&mut output, expanded_args[&index].clone(), token.has_space);
+                }
+                None => output.push(token.clone()),
+            }
+            i += 1;
+        }
+
+        Ok(output
+            .into_iter()
+            .filter(|token| token.kind != Kind::Placemarker)
+            .map(|mut token| {
+                token.hide_set.extend(hide_set.iter().cloned());
+                token
+            })
+            .collect())
+    }
+
+    // Join two tokens with ##, which must form a single valid token.
+    fn paste(&self, lhs: &PpToken, rhs: &PpToken) -> Result<PpToken, Error> {
+        if rhs.kind == Kind::Placemarker {
+            return Ok(lhs.clone());
+        }
+        if lhs.kind == Kind::Placemarker {
+            let mut pasted = rhs.clone();
+            pasted.has_space = lhs.has_space;
+            return Ok(pasted);
+        }
+
+        let text = format!("{}{}", lhs.text, rhs.text);
+        match tokenize(&SourceMap::new(&text)) {
+            Ok(ref tokens) if tokens.len() == 1 && tokens[0].text == text => {
+                let mut pasted = lhs.clone();
+                pasted.kind = tokens[0].kind;
+                pasted.text = text;
+                Ok(pasted)
+            }
+            _ => Err(self.error(
+                lhs.line,
+                &format!("pasting formed '{}', an invalid preprocessing token", text),
+            )),
+        }
+    }
+
+    // Append tokens to the output, starting each line on a new line and
+    // keeping the line numbers of the source where possible.
+    fn emit(&mut self, tokens: &[PpToken]) {
+        let file = self.current().file;
+        let mut previous: Option<&PpToken> = None;
+
+        for token in tokens {
+            if token.first_on_line {
+                let source = self.sources.last_mut().unwrap();
+                let mut newlines = token.line.saturating_sub(source.output_line);
+                if !self.output.is_empty() && !self.output.ends_with('\n') {
+                    newlines = newlines.max(1);
+                }
+                for _ in 0..newlines {
+                    self.output.push('\n');
+                }
+                source.output_line += newlines;
+            } else if token.has_space
+                || previous.is_some_and(|previous| would_paste(previous, token))
+            {
+                self.output.push(' ');
+            }
+
+            self.origins.add_token(Origin {
+                offset: self.output.len(),
+                length: token.text.len(),
+                file,
+                source_offset: token.offset,
+                expanded: token.expanded,
+            });
+            self.output.push_str(&token.text);
+            previous = Some(token);
+        }
+    }
+
+    fn current(&self) -> &Source {
+        self.sources.last().unwrap()
+    }
+
+    fn error(&self, line: usize, message: &str) -> Error {
+        self.error_at_columns(line, None, message)
+    }
+
+    // An error about some tokens on a line, which are underlined when it's
+    // shown if they come straight from the source.
+    fn error_at(&self, tokens: &[PpToken], message: &str) -> Error {
+        let (first, last) = (&tokens[0], &tokens[tokens.len() - 1]);
+        let columns = if first.column == 0 || first.expanded {
+            None
+        } else if last.line == first.line && last.column >= first.column {
+            Some(first.column..last.column + last.text.len())
+        } else {
+            Some(first.column..first.column + first.text.len())
+        };
+        self.error_at_columns(first.line, columns, message)
+    }
+
+    fn error_at_columns(&self, line: usize, columns: Option<Range<usize>>, message: &str) -> Error {
+        let location = match self.sources.last() {
+            Some(source) => Location {
+                file: source.name.clone(),
+                line: source.presumed_line(line),
+                columns,
+                source_line: source_map::physical_line(&source.text, line).map(str::to_owned),
+            },
+            None => Location {
+                file: String::new(),
+                line,
+                columns: None,
+                source_line: None,
+            },
+        };
+        Error {
+            location,
+            message: message.to_owned(),
+        }
+    }
+}
+
+fn placemarker(line: usize) -> PpToken {
+    PpToken::new(Kind::Placemarker, "", line)
+}
+
+fn push_argument(output: &mut Vec<PpToken>, mut arg: Vec<PpToken>, has_space: bool) {
+    if let Some(first) = arg.first_mut() {
+        first.has_space = has_space;
+    }
+    output.append(&mut arg);
+}
+
+// Whether two adjacent tokens would lex as something else if they were
+// written out without a space between them.
+fn would_paste(lhs: &PpToken, rhs: &PpToken) -> bool {
+    let text = format!("{}{}", lhs.text, rhs.text);
+    match tokenize(&SourceMap::new(&text)) {
+        Ok(tokens) => tokens.first().is_none_or(|first| first.text != lhs.text),
+        Err(_) => true,
+    }
+}
+
+// Spell out a sequence of tokens, with a single space wherever there was
+// whitespace between them.
+fn spell(tokens: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && token.has_space {
+            text.push(' ');
+        }
+        text.push_str(&token.text);
+    }
+    text
+}
+
+// The string literal that # makes from a macro argument.
+fn stringize(tokens: &[PpToken]) -> String {
+    let mut text = String::from("\"");
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && token.has_space {
+            text.push(' ');
+        }
+        match token.kind {
+            Kind::StringLiteral | Kind::CharLiteral => text.push_str(&escape(&token.text)),
+            _ => text.push_str(&token.text),
+        }
+    }
+    text.push('"');
+    text
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn token_is_digits(token: &PpToken) -> bool {
+    token.text.chars().all(|c| c.is_ascii_digit())
+}
+
+// Punctuators, longest first so that the longest match wins.
+const PUNCTUATORS: [&str; 48] = [
+    "...", "<<=", ">>=", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=",
+    "/=", "%=", "+=", "-=", "&=", "^=", "|=", "##", "[", "]", "(", ")", "{", "}", ".", "&", "*",
+    "+", "-", "~", "!", "/", "%", "<", ">", "^", "|", "?", ":", ";", "=", ",", "#",
+];
+
+// Split source text into preprocessing tokens, replacing each comment with
+// a space. Tokens are given the physical lines and columns that they start at.
+fn tokenize(source: &SourceMap) -> Result<Vec<PpToken>, (usize, usize, String)> {
+    let (offsets, chars): (Vec<usize>, Vec<char>) = source.text().char_indices().unzip();
+    let offset = |i: usize| offsets.get(i).cloned().unwrap_or(source.text().len());
+    let line = |i: usize| source.line(offset(i));
+    let column = |i: usize| source.column(offset(i));
+    let mut tokens = Vec::new();
+    let mut first_on_line = true;
+    let mut has_space = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+
+        if c == '\n' {
+            first_on_line = true;
+            has_space = false;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            has_space = true;
+            i += 1;
+            continue;
+        }
+        if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            has_space = true;
+            continue;
+        }
+        if c == '/' && next == Some('*') {
+            let start = i;
+            i += 2;
+            loop {
+                match chars.get(i) {
+                    Some(&'*') if chars.get(i + 1) == Some(&'/') => break,
+                    Some(_) => {}
+                    None => {
+                        let message = "unterminated /* comment".to_owned();
+                        return Err((line(start), column(start), message));
+                    }
+                }
+                i += 1;
+            }
+            i += 2;
+            has_space = true;
+            continue;
+        }
+
+        let start = i;
+        let kind = if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Kind::Identifier
+        } else if c.is_ascii_digit() || (c == '.' && next.is_some_and(|next| next.is_ascii_digit()))
+        {
+            // A preprocessing number is anything that could start a number,
+            // including exponents with their signs.
+            i += 1;
+            while i < chars.len() {
+                let current = chars[i];
+                let is_exponent_sign =
+                    matches!(current, '+' | '-') && matches!(chars[i - 1], 'e' | 'E' | 'p' | 'P');
+                if is_exponent_sign || current.is_alphanumeric() || current == '_' || current == '.'
+                {
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            Kind::Number
+        } else if c == '"' || c == '\'' {
+            // An unterminated literal is left as a lone character for the
+            // lexer to reject, since it may be in a skipped group.
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c && chars[end] != '\n' {
+                if chars[end] == '\\' {
+                    end += 1;
+                }
+                end += 1;
+            }
+            if end < chars.len() && chars[end] == c {
+                i = end + 1;
+                if c == '"' {
+                    Kind::StringLiteral
+                } else {
+                    Kind::CharLiteral
+                }
+            } else {
+                i += 1;
+                Kind::Other
+            }
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+            match PUNCTUATORS
+                .iter()
+                .find(|punctuator| rest.starts_with(*punctuator))
+            {
+                Some(punctuator) => {
+                    i += punctuator.len();
+                    Kind::Punctuator
+                }
+                None => {
+                    i += 1;
+                    Kind::Other
+                }
+            }
+        };
+
+        let text: String = chars[start..i].iter().collect();
+        let mut token = PpToken::new(kind, &text, line(start));
+        token.column = column(start);
+        token.offset = offset(start);
+        token.first_on_line = first_on_line;
+        token.has_space = has_space;
+        tokens.push(token);
+        first_on_line = false;
+        has_space = false;
+    }
+
+    Ok(tokens)
+}
+
+// The value of a preprocessor expression, which is computed in the widest
+// signed or unsigned integer type.
+#[derive(Clone, Copy)]
+struct Value {
+    value: i64,
+    unsigned: bool,
+}
+
+impl Value {
+    fn signed(value: i64) -> Self {
+        Value {
+            value,
+            unsigned: false,
+        }
+    }
+}
+
+// Evaluates the controlling expression of an #if or #elif, once macros have
+// been expanded. Identifiers that are left are replaced by 0. Operations are
+// only checked when they're evaluated, so that `0 && 1 / 0` is fine.
+struct Evaluator<'a> {
+    tokens: &'a [PpToken],
+    position: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    fn conditional(&mut self, evaluated: bool) -> Result<Value, String> {
+        let condition = self.binary(0, evaluated)?;
+        if !self.consume("?") {
+            return Ok(condition);
+        }
+
+        let truthy = condition.value != 0;
+        let lhs = self.conditional(evaluated && truthy)?;
+        if !self.consume(":") {
+            return Err("expected ':' in preprocessor expression".to_owned());
+        }
+        let rhs = self.conditional(evaluated && !truthy)?;
+
+        let unsigned = lhs.unsigned || rhs.unsigned;
+        let value = if truthy { lhs.value } else { rhs.value };
+        Ok(Value { value, unsigned })
+    }
+
+    // Parse binary operators binding at least as tightly as the given
+    // precedence level.
+    fn binary(&mut self, level: usize, evaluated: bool) -> Result<Value, String> {
+        const LEVELS: [&[&str]; 10] = [
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", ">", "<=", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary(evaluated);
+        }
+
+        let mut lhs = self.binary(level + 1, evaluated)?;
+        loop {
+            let operator = match self.tokens.get(self.position) {
+                Some(token)
+                    if token.kind == Kind::Punctuator && LEVELS[level].contains(&&*token.text) =>
+                {
+                    token.text.clone()
+                }
+                _ => return Ok(lhs),
+            };
+            self.position += 1;
+
+            // The rhs of a logical operator is only evaluated if it decides
+            // the result.
+            let rhs_evaluated = match &*operator {
+                "||" => evaluated && lhs.value == 0,
+                "&&" => evaluated && lhs.value != 0,
+                _ => evaluated,
+            };
+            let rhs = self.binary(level + 1, rhs_evaluated)?;
+            lhs = apply(&operator, lhs, rhs, rhs_evaluated)?;
+        }
+    }
+
+    fn unary(&mut self, evaluated: bool) -> Result<Value, String> {
+        let token = match self.tokens.get(self.position) {
+            Some(token) => token,
+            None => return Err("expected value in expression".to_owned()),
+        };
+        self.position += 1;
+
+        match token.kind {
+            Kind::Number => parse_number(&token.text),
+            Kind::CharLiteral => parse_char(&token.text),
+            Kind::Identifier => Ok(Value::signed(0)),
+            Kind::Punctuator => match &*token.text {
+                "(" => {
+                    let value = self.conditional(evaluated)?;
+                    if !self.consume(")") {
+                        return Err("expected ')' in preprocessor expression".to_owned());
+                    }
+                    Ok(value)
+                }
+                "+" => self.unary(evaluated),
+                "-" => {
+                    let operand = self.unary(evaluated)?;
+                    Ok(Value {
+                        value: operand.value.wrapping_neg(),
+                        ..operand
+                    })
+                }
+                "~" => {
+                    let operand = self.unary(evaluated)?;
+                    Ok(Value {
+                        value: !operand.value,
+                        ..operand
+                    })
+                }
+                "!" => {
+                    let operand = self.unary(evaluated)?;
+                    Ok(Value::signed((operand.value == 0) as i64))
+                }
+                _ => Err("invalid token at start of a preprocessor expression".to_owned()),
+            },
+            _ => Err("invalid token at start of a preprocessor expression".to_owned()),
+        }
+    }
+
+    fn consume(&mut self, punctuator: &str) -> bool {
+        if self
+            .tokens
+            .get(self.position)
+            .is_some_and(|token| token.is(punctuator))
+        {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn apply(operator: &str, lhs: Value, rhs: Value, evaluated: bool) -> Result<Value, String> {
+    // Logical, equality and relational operators yield an int, and shifts
+    // have the type of their lhs.
+    let unsigned = lhs.unsigned || rhs.unsigned;
+    let (l, r) = (lhs.value, rhs.value);
+    let compare = |ordering: std::cmp::Ordering| -> bool {
+        match operator {
+            "<" => ordering.is_lt(),
+            ">" => ordering.is_gt(),
+            "<=" => ordering.is_le(),
+            _ => ordering.is_ge(),
+        }
+    };
+
+    let value = match operator {
+        "||" => return Ok(Value::signed((l != 0 || r != 0) as i64)),
+        "&&" => return Ok(Value::signed((l != 0 && r != 0) as i64)),
+        "==" => return Ok(Value::signed((l == r) as i64)),
+        "!=" => return Ok(Value::signed((l != r) as i64)),
+        "<" | ">" | "<=" | ">=" => {
+            let ordering = if unsigned {
+                (l as u64).cmp(&(r as u64))
+            } else {
+                l.cmp(&r)
+            };
+            return Ok(Value::signed(compare(ordering) as i64));
+        }
+        "<<" | ">>" => {
+            let shift = (r as u32) & 63;
+            let value = match (operator, lhs.unsigned) {
+                ("<<", _) => l.wrapping_shl(shift),
+                (_, true) => ((l as u64) >> shift) as i64,
+                (_, false) => l >> shift,
+            };
+            return Ok(Value {
+                value,
+                unsigned: lhs.unsigned,
+            });
+        }
+        "|" => l | r,
+        "^" => l ^ r,
+        "&" => l & r,
+        "+" => l.wrapping_add(r),
+        "-" => l.wrapping_sub(r),
+        "*" => l.wrapping_mul(r),
+        "/" | "%" if r == 0 => {
+            if evaluated {
+                return Err(format!(
+                    "{} by zero in preprocessor expression",
+                    if operator == "/" {
+                        "division"
+                    } else {
+                        "remainder"
+                    }
+                ));
+            }
+            0
+        }
+        "/" if unsigned => ((l as u64) / (r as u64)) as i64,
+        "/" => l.wrapping_div(r),
+        "%" if unsigned => ((l as u64) % (r as u64)) as i64,
+        _ => l.wrapping_rem(r),
+    };
+
+    Ok(Value { value, unsigned })
+}
+
+fn parse_number(text: &str) -> Result<Value, String> {
+    let digits = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    let suffix = &text[digits.len()..];
+    let invalid = || {
+        format!(
+            "invalid integer constant '{}' in preprocessor expression",
+            text
+        )
+    };
+
+    let (radix, digits) = if digits.starts_with("0x") || digits.starts_with("0X") {
+        (16, &digits[2..])
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (8, &digits[1..])
+    } else {
+        (10, digits)
+    };
+
+    let value = u64::from_str_radix(digits, radix).map_err(|_| invalid())?;
+    let lowercase = suffix.to_lowercase();
+    if !["", "u", "l", "ul", "lu", "ll", "ull", "llu"].contains(&lowercase.as_str()) {
+        return Err(invalid());
+    }
+
+    // A value too big for the signed type is unsigned.
+    let unsigned = lowercase.contains('u') || value > i64::MAX as u64;
+    Ok(Value {
+        value: value as i64,
+        unsigned,
+    })
+}
+
+fn parse_char(text: &str) -> Result<Value, String> {
+    let body: Vec<char> = text[1..text.len() - 1].chars().collect();
+    let invalid = || {
+        format!(
+            "invalid character constant {} in preprocessor expression",
+            text
+        )
+    };
+
+    let value = match body.as_slice() {
+        [c] => *c as i64,
+        ['\\', 'x', hex @ ..] => {
+            let hex: String = hex.iter().collect();
+            u8::from_str_radix(&hex, 16).map_err(|_| invalid())? as i64
+        }
+        ['\\', octal @ ..] if octal.iter().all(|c| c.is_digit(8)) && !octal.is_empty() => {
+            let octal: String = octal.iter().collect();
+            u8::from_str_radix(&octal, 8).map_err(|_| invalid())? as i64
+        }
+        ['\\', c] => match *c {
+            'n' => b'\n' as i64,
+            't' => b'\t' as i64,
+            'r' => b'\r' as i64,
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '\\' | '\'' | '"' | '?' => *c as i64,
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+
+    // A character constant has type int, from a (signed) char.
+    Ok(Value::signed(value as u8 as i8 as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The lines of preprocessed text that aren't blank.
+    fn preprocess(source: &str) -> Vec<String> {
+        let output = Preprocessor::new(Vec::new())
+            .preprocess(Path::new("test.c"), source)
+            .expect("preprocessing failed");
+        output
+            .text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.trim().to_owned())
+            .collect()
+    }
+
+    fn preprocess_error(source: &str) -> Error {
+        match Preprocessor::new(Vec::new()).preprocess(Path::new("test.c"), source) {
+            Ok(output) => panic!("expected an error, got {:?}", output.text),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn object_like_macro() {
+        assert_eq!(preprocess("#define N 42\nint x = N;\n"), ["int x = 42;"]);
+    }
+
+    #[test]
+    fn stringizing() {
+        let source = "#define STR(x) #x\nSTR(a  \"b\\n\"  c)\nSTR( )\n";
+        assert_eq!(preprocess(source), [r#""a \"b\\n\" c""#, r#""""#]);
+    }
+
+    #[test]
+    fn stringizing_doesnt_expand_the_argument() {
+        let source = "#define STR(x) #x\n#define XSTR(x) STR(x)\n#define N 4\nSTR(N) XSTR(N)\n";
+        assert_eq!(preprocess(source), [r#""N" "4""#]);
+    }
+
+    #[test]
+    fn token_pasting() {
+        let source = "#define CAT(a, b) a ## b\nCAT(x, y) CAT(, z) CAT(1, 2)\n";
+        assert_eq!(preprocess(source), ["xy z 12"]);
+    }
+
+    #[test]
+    fn pasted_token_is_rescanned() {
+        let source = "#define CAT(a, b) a ## b\n#define xy 5\nCAT(x, y)\n";
+        assert_eq!(preprocess(source), ["5"]);
+    }
+
+    #[test]
+    fn recursive_macro_isnt_expanded_again() {
+        let source = "#define f(x) x + f(x)\n#define g f\nf(1) g(2)\n";
+        assert_eq!(preprocess(source), ["1 + f(1) 2 + f(2)"]);
+    }
+
+    #[test]
+    fn mutually_recursive_macros_stop() {
+        let source = "#define a b\n#define b a\na b\n";
+        assert_eq!(preprocess(source), ["a b"]);
+    }
+
+    #[test]
+    fn hide_set_only_covers_the_expansion() {
+        // The example from C11 6.10.3.4: f is invoked again with tokens from
+        // after the expansion of g, so it's expanded too.
+        let source = "#define f(a) a*g\n#define g(a) f(a)\nf(2)(9)\n";
+        assert_eq!(preprocess(source), ["2*9*g"]);
+    }
+
+    #[test]
+    fn comma_paste_with_empty_variadic_arguments() {
+        let source =
+            "#define LOG(fmt, ...) printf(fmt, ## __VA_ARGS__)\nLOG(\"a\")\nLOG(\"a\", 1, 2)\n";
+        assert_eq!(
+            preprocess(source),
+            [r#"printf("a")"#, r#"printf("a", 1, 2)"#]
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = "#if 010 == 8 && defined(X) || !defined Y\nyes\n#else\nno\n#endif\n";
+        assert_eq!(preprocess(source), ["yes"]);
+
+        let source = "#define X\n#ifdef X\na\n#elif 1\nb\n#endif\n#ifndef X\nc\n#endif\n";
+        assert_eq!(preprocess(source), ["a"]);
+    }
+
+    #[test]
+    fn unsigned_arithmetic_in_conditionals() {
+        assert_eq!(preprocess("#if -1 > 0u\nyes\n#endif\n"), ["yes"]);
+        assert_eq!(preprocess("#if -1 > 0\nno\n#endif\n"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unterminated_conditional() {
+        let error = preprocess_error("#if 1\nint x;\n");
+        assert_eq!(error.message, "unterminated conditional directive");
+        assert_eq!(error.location.line, 1);
+    }
+
+    #[test]
+    fn error_directive() {
+        let error = preprocess_error("\n#error no good\n");
+        assert_eq!(error.message, "#error no good");
+        assert_eq!(error.location.line, 2);
+    }
+}
@@ -0,0 +1,2337 @@
+use cfg::{self, Cfg};
+use constant::{self, Constant};
+use dataflow;
+use diagnostics::{self, Diagnostics, Error, Warning};
+use fold;
+use lexer::Span;
+use parser::{ASTKind, BinaryOperator, Designator, StorageClass, UnaryOperator, AST};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use types::{FunctionType, Type};
+
+// The most stack that the automatic variables of a function may take up,
+// which leaves room for the rest of its frame within the 32-bit displacements
+// that address it.
+const MAX_FRAME_SIZE: u64 = 1 << 30;
+
+// A program after semantic analysis, which is what code is generated from:
+// every identifier is resolved to the object or function that it refers to,
+// every expression has a type, and every implicit conversion is explicit.
+#[derive(Debug)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    // The objects with static storage duration defined in this translation
+    // unit: the variables at file scope in the order in which they were first
+    // declared, followed by the static local variables.
+    pub objects: Vec<Object>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: Box<str>,
+    pub ty: FunctionType,
+    // Whether the function is visible outside this translation unit.
+    pub global: bool,
+    // The parameters are the function's first automatic variables, in order.
+    pub body: Vec<Stmt>,
+    // The closing brace of the body.
+    pub end: Span,
+    // The names of the automatic variables, by index, with where they're
+    // declared.
+    pub locals: Vec<(Box<str>, Span)>,
+}
+
+#[derive(Debug)]
+pub struct Object {
+    // The name of a variable at file scope, or a name made unique by
+    // numbering it for a static local variable.
+    pub name: String,
+    pub ty: Type,
+    pub global: bool,
+    // The parts of the object's initializer, or None if it's zero.
+    pub data: Option<Vec<StaticInitializer>>,
+}
+
+#[derive(Debug)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum StmtKind {
+    Block(Vec<Stmt>),
+    // The definition of an automatic variable, by its index among those of
+    // the function, with its type, which an initializer may have completed.
+    Declaration(usize, Type, Option<Vec<Initializer>>),
+    Expression(Expr),
+    Return(Option<Expr>),
+    // The controlling expression, already promoted, the values of the case
+    // labels that belong to the switch in the order in which they appear,
+    // with None for `default`, and the body.
+    Switch(Expr, Vec<Option<i64>>, Box<Stmt>),
+    // A `case` or `default` label, by its index in the enclosing switch's.
+    Case(usize, Box<Stmt>),
+    Break,
+    Label(Box<str>, Box<Stmt>),
+    Goto(Box<str>),
+}
+
+#[derive(Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    // The type of the object or function that an lvalue designates,
+    // including its qualifiers, and otherwise the unqualified type of the
+    // expression's value.
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
+    IntConstant(i64),
+    FloatConstant(f64),
+    // A string literal, which designates an array of characters.
+    StringLiteral(Box<[u8]>),
+    Object(Storage),
+    Function(Box<str>),
+    Dereference(Box<Expr>),
+    // A member of a structure, at an offset from its start.
+    Member(Box<Expr>, u64),
+    // The value stored in the object that an lvalue designates.
+    Load(Box<Expr>),
+    // The address of the array or function that an lvalue designates.
+    Decay(Box<Expr>),
+    // The value of the operand converted to the type of the expression.
+    Conversion(Box<Expr>),
+    AddressOf(Box<Expr>),
+    // The operand has already been promoted.
+    UnaryOp(UnaryOperator, Box<Expr>),
+    // The operands of an arithmetic operator or a comparison have already
+    // been converted to their common type, while those of && and || keep
+    // their own.
+    BinaryOp(BinaryOperator, Box<Expr>, Box<Expr>),
+    // Adding a long number of elements to a pointer, or subtracting it.
+    PointerArithmetic(BinaryOperator, Box<Expr>, Box<Expr>),
+    // The number of elements between two pointers.
+    PointerDifference(Box<Expr>, Box<Expr>),
+    // The value has already been converted to the type of the object.
+    Assign(Box<Expr>, Box<Expr>),
+    // The callee is a pointer to a function, and the arguments have already
+    // been converted to the types they're passed as.
+    Call(Box<Expr>, Vec<Expr>),
+    VaStart(Box<Expr>),
+    VaArg(Box<Expr>),
+    VaEnd(Box<Expr>),
+    VaCopy(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Storage {
+    // An automatic variable, by its index among those of the function.
+    Local(usize),
+    // An object with static storage duration, by its name.
+    Static(String),
+}
+
+// Part of an initializer that sets a scalar, a structure or a character
+// array to a single value, at an offset from the start of the object. The
+// value has already been converted to the part's type, unless it's a string
+// literal initializing a character array.
+#[derive(Debug)]
+pub struct Initializer {
+    pub offset: u64,
+    pub ty: Type,
+    pub value: Expr,
+}
+
+// Part of the initializer of an object with static storage duration, whose
+// value is a constant.
+#[derive(Debug)]
+pub struct StaticInitializer {
+    pub offset: u64,
+    pub ty: Type,
+    pub value: Data,
+}
+
+#[derive(Debug)]
+pub enum Data {
+    // The bits of a scalar.
+    Integer(i64),
+    // The address of a function or an object with static storage duration,
+    // by its name, plus an offset.
+    Address(String, i64),
+    // The address of a string literal, plus an offset.
+    StringAddress(Box<[u8]>, i64),
+    // The characters of a character array.
+    String(Box<[u8]>),
+}
+
+impl Stmt {
+    fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+}
+
+impl Expr {
+    fn new(kind: ExprKind, ty: Type, span: Span) -> Self {
+        Expr { kind, ty, span }
+    }
+
+    fn is_lvalue(&self) -> bool {
+        match self.kind {
+            ExprKind::Object(_)
+            | ExprKind::Function(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Dereference(_) => true,
+            ExprKind::Member(ref object, _) => object.is_lvalue(),
+            _ => false,
+        }
+    }
+}
+
+pub fn analyze(ast: &AST, diagnostics: &mut Diagnostics) -> Result<Program, Error> {
+    Analyzer::new(diagnostics).analyze_program(ast)
+}
+
+struct Analyzer<'a> {
+    // The signatures of all the functions declared so far, where each was
+    // first declared, and where the defined ones were defined.
+    functions: HashMap<String, FunctionType>,
+    declarations: HashMap<String, Span>,
+    defined_functions: HashMap<String, Span>,
+    // The functions and variables with internal linkage, which are only
+    // visible in this translation unit.
+    internal_symbols: HashSet<String>,
+    // The variables declared at file scope, and the order in which they were
+    // first declared.
+    globals: HashMap<String, Global>,
+    global_order: Vec<String>,
+    static_locals: Vec<Object>,
+    static_count: usize,
+    // The variables in scope, innermost block last.
+    scopes: Vec<HashMap<String, Variable>>,
+    // The automatic variables in the function so far, and the stack they
+    // take up.
+    locals: Vec<(Box<str>, Span)>,
+    frame_size: u64,
+    function_name: String,
+    function_type: FunctionType,
+    // The name of the last parameter of a variadic function, which va_start
+    // must be given.
+    last_param: Option<Box<str>>,
+    // The labels declared anywhere in the function, and where.
+    function_labels: HashMap<String, Span>,
+    // The enclosing switch statements, innermost last.
+    switches: Vec<Switch>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+#[derive(Clone)]
+struct Variable {
+    storage: Storage,
+    ty: Type,
+    // Whether the variable has been referred to. Parameters and variables
+    // defined elsewhere are never reported as unused, so they start out used.
+    used: bool,
+    // Where the variable is declared.
+    span: Span,
+}
+
+struct Global {
+    ty: Type,
+    // Where the variable is first declared.
+    span: Span,
+    state: GlobalState,
+}
+
+enum GlobalState {
+    // Only declared with `extern`, so defined elsewhere.
+    Declared,
+    // Declared without an initializer, which defines it as zero unless
+    // another declaration initializes it.
+    Tentative,
+    // Defined with an initializer at the given span.
+    Defined(Vec<StaticInitializer>, Span),
+}
+
+struct Switch {
+    // The promoted type of the controlling expression, which the case values
+    // are converted to.
+    ty: Type,
+    // The values of the case labels, or None for the default label, and
+    // where each label is.
+    cases: Vec<Option<i64>>,
+    labels: Vec<Span>,
+}
+
+// The ways that a value is converted to a type as if by assignment, which
+// are told apart in diagnostics about the conversion.
+#[derive(Clone, Copy)]
+enum Assignment {
+    Assign,
+    Initialize,
+    Pass,
+    Return,
+}
+
+impl Assignment {
+    // The conversion of a value of one type to another, described like
+    // "assigning to 'int *' from 'const int *'".
+    fn describe(self, to: &Type, from: &Type) -> String {
+        match self {
+            Assignment::Assign => format!("assigning to '{}' from '{}'", to, from),
+            Assignment::Initialize => {
+                format!(
+                    "initializing '{}' with an expression of type '{}'",
+                    to, from
+                )
+            }
+            Assignment::Pass => format!("passing '{}' to parameter of type '{}'", from, to),
+            Assignment::Return => {
+                format!(
+                    "returning '{}' from a function with result type '{}'",
+                    from, to
+                )
+            }
+        }
+    }
+}
+
+impl<'a> Analyzer<'a> {
+    fn new(diagnostics: &'a mut Diagnostics) -> Self {
+        Analyzer {
+            functions: HashMap::new(),
+            declarations: HashMap::new(),
+            defined_functions: HashMap::new(),
+            internal_symbols: HashSet::new(),
+            globals: HashMap::new(),
+            global_order: Vec::new(),
+            static_locals: Vec::new(),
+            static_count: 0,
+            scopes: Vec::new(),
+            locals: Vec::new(),
+            frame_size: 0,
+            function_name: String::new(),
+            function_type: FunctionType {
+                return_type: Type::Int,
+                params: None,
+                variadic: false,
+            },
+            last_param: None,
+            function_labels: HashMap::new(),
+            switches: Vec::new(),
+            diagnostics,
+        }
+    }
+
+    fn analyze_program(mut self, ast: &AST) -> Result<Program, Error> {
+        let items = match ast.kind {
+            ASTKind::Program(ref items) => items,
+            _ => {
+                return Err(Error::at(
+                    "expected a translation unit".to_owned(),
+                    ast.span,
+                ))
+            }
+        };
+
+        let mut functions = Vec::new();
+        for item in items {
+            let span = item.span;
+            match item.kind {
+                ASTKind::Function(ref name, ref func_type, storage, ref param_names, ref body) => {
+                    functions.push(self.analyze_function(
+                        name,
+                        span,
+                        func_type,
+                        storage,
+                        param_names,
+                        body,
+                    )?);
+                }
+                ASTKind::FunctionDeclaration(ref name, ref func_type, storage) => {
+                    self.declare_function(name, span, func_type, storage)?;
+                }
+                ASTKind::Declaration(ref name, ref ty, storage, ref init) => {
+                    self.declare_global(name, span, ty, storage, init.as_deref())?;
+                }
+                ASTKind::StaticAssert(ref expr, ref message) => self
+                    .check_static_assert(expr, message)
+                    .map_err(|error| error.or_at(span))?,
+                _ => {
+                    let message = "expected a function definition or a declaration".to_owned();
+                    return Err(Error::at(message, span));
+                }
+            }
+        }
+
+        let mut objects = Vec::new();
+        for name in &self.global_order {
+            let global = self
+                .globals
+                .remove(name)
+                .expect("global without a declaration");
+            let data = match global.state {
+                GlobalState::Declared => continue,
+                GlobalState::Tentative => None,
+                GlobalState::Defined(data, _) => Some(data),
+            };
+
+            objects.push(Object {
+                name: name.clone(),
+                ty: global.ty,
+                global: !self.internal_symbols.contains(name),
+                data,
+            });
+        }
+        objects.append(&mut self.static_locals);
+
+        Ok(Program { functions, objects })
+    }
+
+    fn declare_function(
+        &mut self,
+        name: &str,
+        span: Span,
+        func_type: &FunctionType,
+        storage: Option<StorageClass>,
+    ) -> Result<(), Error> {
+        if let Some(global) = self.globals.get(name) {
+            let message = format!("redefinition of '{}' as different kind of symbol", name);
+            return Err(previous_declaration(message, span, global.span));
+        }
+
+        let params = func_type.params.as_deref().unwrap_or_default();
+        if func_type.return_type.is_struct() || params.iter().any(Type::is_struct) {
+            let message = "passing or returning structures by value is not supported".to_owned();
+            return Err(Error::at(message, span));
+        }
+
+        // A function declared without a storage class is treated as if it
+        // were declared extern.
+        let previous_span = self.declarations.get(name).cloned();
+        let storage = storage.or(Some(StorageClass::Extern));
+        self.declare_linkage(name, span, storage, previous_span)?;
+
+        if let (Some(previous), Some(previous_span)) = (self.functions.get(name), previous_span) {
+            if !previous.is_compatible_with(func_type) {
+                let message = format!("conflicting types for '{}'", name);
+                return Err(previous_declaration(message, span, previous_span));
+            }
+
+            // Keep the prototype if this declaration doesn't have one.
+            if func_type.params.is_none() {
+                return Ok(());
+            }
+        }
+
+        self.functions.insert(name.to_owned(), func_type.clone());
+        self.declarations.entry(name.to_owned()).or_insert(span);
+        Ok(())
+    }
+
+    // Work out the linkage of a function or file-scope variable from its
+    // storage class and where it has been declared before, if it has, since
+    // an `extern` declaration inherits the linkage of a previous one.
+    fn declare_linkage(
+        &mut self,
+        name: &str,
+        span: Span,
+        storage: Option<StorageClass>,
+        previous: Option<Span>,
+    ) -> Result<(), Error> {
+        let internal = self.internal_symbols.contains(name);
+
+        match (storage, previous) {
+            (Some(StorageClass::Static), Some(previous)) if !internal => {
+                let message = format!(
+                    "static declaration of '{}' follows non-static declaration",
+                    name
+                );
+                Err(previous_declaration(message, span, previous))
+            }
+            (Some(StorageClass::Static), _) => {
+                self.internal_symbols.insert(name.to_owned());
+                Ok(())
+            }
+            (None, Some(previous)) if internal => {
+                let message = format!(
+                    "non-static declaration of '{}' follows static declaration",
+                    name
+                );
+                Err(previous_declaration(message, span, previous))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn declare_global(
+        &mut self,
+        name: &str,
+        span: Span,
+        ty: &Type,
+        storage: Option<StorageClass>,
+        init: Option<&AST>,
+    ) -> Result<(), Error> {
+        if let Some(&previous) = self.declarations.get(name) {
+            let message = format!("redefinition of '{}' as different kind of symbol", name);
+            return Err(previous_declaration(message, span, previous));
+        }
+
+        let previous = self
+            .globals
+            .get(name)
+            .map(|global| (global.ty.clone(), global.span));
+        self.declare_linkage(
+            name,
+            span,
+            storage,
+            previous.as_ref().map(|&(_, span)| span),
+        )?;
+
+        // An initializer can complete the type of an array of unknown
+        // length, before it's compared with any previous declaration.
+        let mut initializers = Vec::new();
+        let ty = match init {
+            Some(init) => self.flatten_initializer(ty, init, 0, &mut initializers)?,
+            None => ty.clone(),
+        };
+
+        if let Some((ref previous_type, previous_span)) = previous {
+            if *previous_type != ty {
+                let message = format!(
+                    "redefinition of '{}' with a different type: '{}' vs '{}'",
+                    name, ty, previous_type
+                );
+                return Err(previous_declaration(message, span, previous_span));
+            }
+        }
+
+        let state = match (init, storage) {
+            (Some(init), _) => {
+                if ty.size().is_none() {
+                    let message = format!("variable has incomplete type '{}'", ty);
+                    return Err(Error::at(message, span));
+                }
+                let data = static_data(initializers, self.diagnostics)
+                    .map_err(|error| Error::at(error, init.span))?;
+                GlobalState::Defined(data, span)
+            }
+            (None, Some(StorageClass::Extern)) => GlobalState::Declared,
+            (None, _) => GlobalState::Tentative,
+        };
+
+        let global = self
+            .globals
+            .entry(name.to_owned())
+            .or_insert_with(|| Global {
+                ty,
+                span,
+                state: GlobalState::Declared,
+            });
+
+        match (&global.state, state) {
+            (&GlobalState::Defined(_, previous), GlobalState::Defined(..)) => {
+                let message = format!("redefinition of '{}'", name);
+                return Err(previous_definition(message, span, previous));
+            }
+            (&GlobalState::Defined(..), _) => {}
+            (_, GlobalState::Declared) => {}
+            (_, state) => global.state = state,
+        }
+
+        if previous.is_none() {
+            self.global_order.push(name.to_owned());
+        }
+
+        Ok(())
+    }
+
+    fn analyze_function(
+        &mut self,
+        name: &str,
+        span: Span,
+        func_type: &FunctionType,
+        storage: Option<StorageClass>,
+        param_names: &[(Box<str>, Span)],
+        body: &AST,
+    ) -> Result<Function, Error> {
+        self.declare_function(name, span, func_type, storage)?;
+        if let Some(&previous) = self.defined_functions.get(name) {
+            let message = format!("redefinition of '{}'", name);
+            return Err(previous_definition(message, span, previous));
+        }
+        self.defined_functions.insert(name.to_owned(), span);
+
+        self.function_name = name.to_owned();
+        self.function_type = func_type.clone();
+        self.last_param = if func_type.variadic {
+            param_names.last().map(|(name, _)| name.clone())
+        } else {
+            None
+        };
+        self.function_labels = HashMap::new();
+        collect_labels(body, &mut self.function_labels)?;
+
+        // The parameters and the outermost block of the body share a single
+        // scope.
+        self.locals = Vec::new();
+        self.frame_size = 0;
+        self.scopes.push(HashMap::new());
+        let end = Span {
+            start: body.span.end - 1,
+            ..body.span
+        };
+        let body = self
+            .declare_parameters(func_type, param_names)
+            .and_then(|()| match body.kind {
+                ASTKind::Block(ref items) => self.analyze_block_items(items),
+                _ => self.analyze_statement(body).map(|stmt| vec![stmt]),
+            });
+        self.pop_scope();
+
+        let function = Function {
+            name: name.into(),
+            ty: func_type.clone(),
+            global: !self.internal_symbols.contains(name),
+            body: body?,
+            end,
+            locals: mem::take(&mut self.locals),
+        };
+
+        let graph = Cfg::new(&function);
+        cfg::check(&function, &graph, self.diagnostics);
+        dataflow::check(&function, &graph, self.diagnostics);
+        Ok(function)
+    }
+
+    fn declare_parameters(
+        &mut self,
+        func_type: &FunctionType,
+        param_names: &[(Box<str>, Span)],
+    ) -> Result<(), Error> {
+        let param_types = func_type.params.as_deref().unwrap_or_default();
+        for (&(ref name, span), ty) in param_names.iter().zip(param_types) {
+            let storage = Storage::Local(self.new_local(name, span));
+            self.allocate_local(ty, span)?;
+            self.check_shadowing(name, span);
+            let variable = Variable {
+                storage,
+                ty: ty.clone(),
+                used: true,
+                span,
+            };
+            self.declare_variable(name, variable)
+                .map_err(|error| Error {
+                    message: format!("redefinition of parameter '{}'", name),
+                    ..error
+                })?;
+        }
+        Ok(())
+    }
+
+    fn new_local(&mut self, name: &str, span: Span) -> usize {
+        self.locals.push((name.into(), span));
+        self.locals.len() - 1
+    }
+
+    fn allocate_local(&mut self, ty: &Type, span: Span) -> Result<(), Error> {
+        let size = ty
+            .size()
+            .unwrap_or(0)
+            .next_multiple_of(ty.align().unwrap_or(1));
+        self.frame_size = self.frame_size.saturating_add(size);
+        if self.frame_size > MAX_FRAME_SIZE {
+            let message = "total size of local objects is too large".to_owned();
+            return Err(Error::at(message, span));
+        }
+        Ok(())
+    }
+
+    fn declare_variable(&mut self, name: &str, variable: Variable) -> Result<(), Error> {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("no scope to declare variable in");
+        if let Some(previous) = scope.get(name) {
+            let message = format!("redefinition of '{}'", name);
+            return Err(previous_declaration(message, variable.span, previous.span));
+        }
+
+        scope.insert(name.to_owned(), variable);
+        Ok(())
+    }
+
+    // Warn about a declaration that hides a variable of the same name.
+    fn check_shadowing(&mut self, name: &str, span: Span) {
+        let outer_scopes = &self.scopes[..self.scopes.len() - 1];
+        let message = if outer_scopes.iter().any(|scope| scope.contains_key(name)) {
+            "declaration shadows a local variable"
+        } else if self.globals.contains_key(name) {
+            "declaration shadows a variable in the global scope"
+        } else {
+            return;
+        };
+        self.diagnostics
+            .warn(Warning::Shadow, span, message.to_owned());
+    }
+
+    // Leave the innermost scope, warning about the variables declared in it
+    // that were never used.
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("no scope to leave");
+        let mut unused = scope
+            .into_iter()
+            .filter(|(_, variable)| !variable.used)
+            .map(|(name, variable)| (name, variable.span))
+            .collect::<Vec<_>>();
+        unused.sort_by_key(|&(_, span)| span.start);
+
+        for (name, span) in unused {
+            self.diagnostics.warn(
+                Warning::UnusedVariable,
+                span,
+                format!("unused variable '{}'", name),
+            );
+        }
+    }
+
+    // Give a variable in the innermost scope the type that its initializer
+    // completed.
+    fn complete_variable(&mut self, name: &str, ty: &Type) {
+        if let Some(variable) = self.scopes.last_mut().and_then(|scope| scope.get_mut(name)) {
+            variable.ty = ty.clone();
+        }
+    }
+
+    fn lookup_variable(&self, name: &str) -> Option<Variable> {
+        self.scopes
+            .iter()
+            .rev()
+            .filter_map(|scope| scope.get(name))
+            .next()
+            .cloned()
+            .or_else(|| {
+                self.globals.get(name).map(|global| Variable {
+                    storage: Storage::Static(name.to_owned()),
+                    ty: global.ty.clone(),
+                    used: true,
+                    span: global.span,
+                })
+            })
+    }
+
+    // The error for a use of an identifier that isn't declared, suggesting
+    // the visible variable or function that it was probably meant to be.
+    fn undeclared_identifier(&mut self, description: &str, name: &str, span: Span) -> Error {
+        let names = self
+            .scopes
+            .iter()
+            .flat_map(HashMap::keys)
+            .chain(self.globals.keys())
+            .chain(self.functions.keys())
+            .map(String::as_str);
+        let suggestion = diagnostics::closest_match(name, names).map(str::to_owned);
+        self.undeclared(description, name, span, suggestion)
+    }
+
+    fn undeclared(
+        &mut self,
+        description: &str,
+        name: &str,
+        span: Span,
+        suggestion: Option<String>,
+    ) -> Error {
+        let message = match suggestion {
+            Some(suggestion) => {
+                let message = format!("{} '{}'; did you mean '{}'?", description, name, suggestion);
+                self.diagnostics
+                    .suggest_replacement(span, name, &suggestion);
+                message
+            }
+            None => format!("{} '{}'", description, name),
+        };
+        Error::at(message, span)
+    }
+
+    // Analyze the items of a block, where declarations that don't define an
+    // automatic variable need no statement.
+    fn analyze_block_items(&mut self, items: &[AST]) -> Result<Vec<Stmt>, Error> {
+        let mut statements = Vec::new();
+        for item in items {
+            let span = item.span;
+            match item.kind {
+                ASTKind::Declaration(ref name, ref ty, storage, ref init) => {
+                    let init = init.as_deref();
+                    match storage {
+                        Some(StorageClass::Static) => {
+                            self.declare_static_local(name, span, ty, init)?
+                        }
+                        Some(StorageClass::Extern) => {
+                            self.declare_extern_local(name, span, ty, init)?
+                        }
+                        None => {
+                            let kind = self.analyze_declaration(name, span, ty, init)?;
+                            statements.push(Stmt::new(kind, span));
+                        }
+                    }
+                }
+                ASTKind::FunctionDeclaration(ref name, ref func_type, storage) => {
+                    self.declare_function(name, span, func_type, storage)?;
+                }
+                ASTKind::StaticAssert(ref expr, ref message) => self
+                    .check_static_assert(expr, message)
+                    .map_err(|error| error.or_at(span))?,
+                _ => statements.push(self.analyze_statement(item)?),
+            }
+        }
+        Ok(statements)
+    }
+
+    fn check_static_assert(&mut self, expr: &AST, message: &[u8]) -> Result<(), Error> {
+        let value = self.evaluate_integer_constant(expr).map_err(|error| {
+            let context = "static assertion expression is not an integer constant expression";
+            error.into_error(context, expr.span)
+        })?;
+        if value.value == 0 {
+            return Err(format!(
+                "static assertion failed: {}",
+                String::from_utf8_lossy(message)
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // Analyze a statement. An error in it that isn't found anywhere more
+    // specific is reported at the statement.
+    fn analyze_statement(&mut self, stmt: &AST) -> Result<Stmt, Error> {
+        let kind = self
+            .analyze_statement_kind(stmt)
+            .map_err(|error| error.or_at(stmt.span))?;
+        Ok(Stmt::new(kind, stmt.span))
+    }
+
+    fn analyze_statement_kind(&mut self, stmt: &AST) -> Result<StmtKind, Error> {
+        Ok(match stmt.kind {
+            ASTKind::Block(ref items) => {
+                self.scopes.push(HashMap::new());
+                let statements = self.analyze_block_items(items);
+                self.pop_scope();
+                StmtKind::Block(statements?)
+            }
+            ASTKind::Expression(ref expr) => StmtKind::Expression(self.analyze_value(expr)?),
+            ASTKind::Return(ref expr) => self.analyze_return(expr.as_deref(), stmt.span)?,
+            ASTKind::Switch(ref expr, ref body) => self.analyze_switch(expr, body)?,
+            ASTKind::Case(ref value, ref stmt) => {
+                let index = self.add_case(Some(value), value.span)?;
+                StmtKind::Case(index, Box::new(self.analyze_statement(stmt)?))
+            }
+            ASTKind::Default(ref body) => {
+                let keyword = Span {
+                    end: stmt.span.start + "default".len(),
+                    ..stmt.span
+                };
+                let index = self.add_case(None, keyword)?;
+                StmtKind::Case(index, Box::new(self.analyze_statement(body)?))
+            }
+            ASTKind::Break => {
+                if self.switches.is_empty() {
+                    return Err("break statement not within a switch statement"
+                        .to_owned()
+                        .into());
+                }
+                StmtKind::Break
+            }
+            ASTKind::Label(ref name, ref stmt) => {
+                StmtKind::Label(name.clone(), Box::new(self.analyze_statement(stmt)?))
+            }
+            ASTKind::Goto(ref name) => {
+                if !self.function_labels.contains_key(&**name) {
+                    let labels = self.function_labels.keys().map(String::as_str);
+                    let suggestion = diagnostics::closest_match(name, labels).map(str::to_owned);
+                    let span = stmt.span;
+                    return Err(self.undeclared("use of undeclared label", name, span, suggestion));
+                }
+                StmtKind::Goto(name.clone())
+            }
+            _ => return Err("expected statement".to_owned().into()),
+        })
+    }
+
+    fn analyze_return(&mut self, expr: Option<&AST>, span: Span) -> Result<StmtKind, Error> {
+        let return_type = self.function_type.return_type.unqualified().clone();
+
+        match (expr, &return_type) {
+            (Some(expr), &Type::Void) => {
+                let message = format!(
+                    "void function '{}' should not return a value",
+                    self.function_name
+                );
+                Err(Error::at(message, expr.span))
+            }
+            (None, &Type::Void) => Ok(StmtKind::Return(None)),
+            (None, _) => {
+                self.diagnostics.warn(
+                    Warning::ReturnType,
+                    span,
+                    format!(
+                        "non-void function '{}' should return a value",
+                        self.function_name
+                    ),
+                );
+                Ok(StmtKind::Return(None))
+            }
+            (Some(expr), _) => {
+                let expr = self.analyze_value(expr)?;
+                if !is_assignable(&return_type, &expr.ty) {
+                    let message = format!(
+                        "returning '{}' from a function with incompatible result type '{}'",
+                        expr.ty, return_type
+                    );
+                    return Err(Error::at(message, expr.span));
+                }
+                let expr = self.convert_implicitly(expr, &return_type, Assignment::Return);
+                Ok(StmtKind::Return(Some(expr)))
+            }
+        }
+    }
+
+    fn analyze_switch(&mut self, expr: &AST, body: &AST) -> Result<StmtKind, Error> {
+        // The controlling expression and the case values are all converted
+        // to the promoted type of the controlling expression.
+        let expr = self.analyze_value(expr)?;
+        let switch_type = expr.ty.promote();
+        if !switch_type.is_integer() {
+            let message = format!(
+                "statement requires expression of integer type ('{}' invalid)",
+                expr.ty
+            );
+            return Err(Error::at(message, expr.span));
+        }
+
+        self.switches.push(Switch {
+            ty: switch_type.clone(),
+            cases: Vec::new(),
+            labels: Vec::new(),
+        });
+        let body = self.analyze_statement(body);
+        let switch = self.switches.pop().expect("switch without a context");
+
+        Ok(StmtKind::Switch(
+            convert(expr, &switch_type),
+            switch.cases,
+            Box::new(body?),
+        ))
+    }
+
+    // Add a `case` label with the given value, or a `default` label, at the
+    // given span to the innermost switch, and return its index there.
+    fn add_case(&mut self, value: Option<&AST>, span: Span) -> Result<usize, Error> {
+        if self.switches.is_empty() {
+            return Err(match value {
+                Some(_) => "case label not within a switch statement".to_owned(),
+                None => "'default' statement not in switch statement".to_owned(),
+            }
+            .into());
+        }
+
+        let case = match value {
+            Some(value) => {
+                let constant = self.evaluate_integer_constant(value).map_err(|error| {
+                    let context = "case label does not reduce to an integer constant";
+                    error.into_error(context, value.span)
+                })?;
+                Some(self.switches.last().unwrap().ty.wrap(constant.value))
+            }
+            None => None,
+        };
+
+        let switch = self.switches.last_mut().unwrap();
+        if let Some(index) = switch.cases.iter().position(|&previous| previous == case) {
+            let message = match case {
+                Some(value) => format!(
+                    "duplicate case value '{}'",
+                    constant::format_value(value, &switch.ty)
+                ),
+                None => "multiple default labels in one switch".to_owned(),
+            };
+            let note = "previous case defined here".to_owned();
+            return Err(Error::at(message, span).with_note(switch.labels[index], note));
+        }
+
+        switch.cases.push(case);
+        switch.labels.push(span);
+        Ok(switch.cases.len() - 1)
+    }
+
+    fn analyze_declaration(
+        &mut self,
+        name: &str,
+        span: Span,
+        ty: &Type,
+        init: Option<&AST>,
+    ) -> Result<StmtKind, Error> {
+        // The variable is in scope in its own initializer.
+        let index = self.new_local(name, span);
+        let storage = Storage::Local(index);
+        self.check_shadowing(name, span);
+        self.declare_variable(
+            name,
+            Variable {
+                storage,
+                ty: ty.clone(),
+                used: false,
+                span,
+            },
+        )?;
+
+        let (ty, initializers) = match init {
+            Some(init) => {
+                let mut initializers = Vec::new();
+                let ty = self.flatten_initializer(ty, init, 0, &mut initializers)?;
+                (ty, Some(initializers))
+            }
+            None => (ty.clone(), None),
+        };
+
+        if ty.size().is_none() {
+            return Err(Error::at(
+                format!("variable has incomplete type '{}'", ty),
+                span,
+            ));
+        }
+        self.allocate_local(&ty, span)?;
+        self.complete_variable(name, &ty);
+
+        Ok(StmtKind::Declaration(index, ty, initializers))
+    }
+
+    // A static local variable lives in the data section like a global, under
+    // a name made unique by numbering it, and is initialized only once.
+    fn declare_static_local(
+        &mut self,
+        name: &str,
+        span: Span,
+        ty: &Type,
+        init: Option<&AST>,
+    ) -> Result<(), Error> {
+        self.static_count += 1;
+        let label = format!("{}.{}", name, self.static_count);
+        let storage = Storage::Static(label.clone());
+        self.check_shadowing(name, span);
+        self.declare_variable(
+            name,
+            Variable {
+                storage,
+                ty: ty.clone(),
+                used: false,
+                span,
+            },
+        )?;
+
+        let mut initializers = Vec::new();
+        let ty = match init {
+            Some(init) => self.flatten_initializer(ty, init, 0, &mut initializers)?,
+            None => ty.clone(),
+        };
+
+        if ty.size().is_none() {
+            return Err(Error::at(
+                format!("variable has incomplete type '{}'", ty),
+                span,
+            ));
+        }
+        self.complete_variable(name, &ty);
+
+        let data = match init {
+            Some(init) => {
+                let data = static_data(initializers, self.diagnostics)
+                    .map_err(|error| Error::at(error, init.span))?;
+                Some(data)
+            }
+            None => None,
+        };
+        self.static_locals.push(Object {
+            name: label,
+            ty,
+            global: false,
+            data,
+        });
+        Ok(())
+    }
+
+    // An extern local variable refers to a variable defined at file scope,
+    // here or in another translation unit.
+    fn declare_extern_local(
+        &mut self,
+        name: &str,
+        span: Span,
+        ty: &Type,
+        init: Option<&AST>,
+    ) -> Result<(), Error> {
+        if init.is_some() {
+            let message = "'extern' variable cannot have an initializer".to_owned();
+            return Err(Error::at(message, span));
+        }
+
+        if let Some(global) = self.globals.get(name) {
+            if global.ty != *ty {
+                let message = format!(
+                    "redefinition of '{}' with a different type: '{}' vs '{}'",
+                    name, ty, global.ty
+                );
+                return Err(previous_declaration(message, span, global.span));
+            }
+        }
+
+        let storage = Storage::Static(name.to_owned());
+        self.declare_variable(
+            name,
+            Variable {
+                storage,
+                ty: ty.clone(),
+                used: true,
+                span,
+            },
+        )
+    }
+
+    // Break an initializer for an object of the given type down into the
+    // values of its scalars, structures and character arrays, adding them
+    // to a list in order. Returns the type of the object, which for an array
+    // of unknown length is completed by the initializer.
+    fn flatten_initializer(
+        &mut self,
+        ty: &Type,
+        init: &AST,
+        offset: u64,
+        initializers: &mut Vec<Initializer>,
+    ) -> Result<Type, Error> {
+        match init.kind {
+            ASTKind::InitializerList(ref items) if ty.is_array() || ty.is_struct() => {
+                let mut position = 0;
+                self.initialize_aggregate(ty, items, &mut position, offset, true, 0, initializers)
+                    .map_err(|error| error.or_at(init.span))
+            }
+            // A scalar can be initialized by a list of a single item.
+            ASTKind::InitializerList(ref items) => match items.len() {
+                0 => Err(Error::at(
+                    "scalar initializer cannot be empty".to_owned(),
+                    init.span,
+                )),
+                1 if items[0].0.is_empty() => {
+                    self.flatten_initializer(ty, &items[0].1, offset, initializers)
+                }
+                1 => {
+                    let message = format!("designator in initializer for scalar type '{}'", ty);
+                    Err(Error::at(message, items[0].1.span))
+                }
+                _ => {
+                    let message = "excess elements in scalar initializer".to_owned();
+                    Err(Error::at(message, items[1].1.span))
+                }
+            },
+            ASTKind::StringLiteral(ref bytes) if is_char_array(ty) => {
+                // The terminating null character is only left out if the
+                // array is exactly as long as the string.
+                let ty = match *ty.unqualified() {
+                    Type::Array(ref elem, None) => {
+                        Type::Array(elem.clone(), Some(bytes.len() as u64 + 1))
+                    }
+                    Type::Array(_, Some(length)) if bytes.len() as u64 > length => {
+                        let message = "initializer-string for char array is too long".to_owned();
+                        return Err(Error::at(message, init.span));
+                    }
+                    _ => ty.clone(),
+                };
+
+                let value = string_literal(bytes, init.span);
+                initializers.push(Initializer {
+                    offset,
+                    ty: ty.clone(),
+                    value,
+                });
+                Ok(ty)
+            }
+            _ if ty.is_array() || *ty.unqualified() == Type::VaList => {
+                let message = "array initializer must be an initializer list".to_owned();
+                Err(Error::at(message, init.span))
+            }
+            _ => {
+                let value = self.analyze_value(init)?;
+                if !is_assignable(ty, &value.ty) {
+                    let message = format!(
+                        "initializing '{}' with an expression of incompatible type '{}'",
+                        ty, value.ty
+                    );
+                    return Err(Error::at(message, value.span));
+                }
+
+                let value = self.convert_implicitly(value, ty, Assignment::Initialize);
+                initializers.push(Initializer {
+                    offset,
+                    ty: ty.clone(),
+                    value,
+                });
+                Ok(ty.clone())
+            }
+        }
+    }
+
+    // Initialize the members or elements of an aggregate from a list of
+    // items, starting at the given position in the list. A braced aggregate
+    // takes all of the items, whereas one whose braces were left out only
+    // takes as many as it has subobjects, and stops at a designator, which
+    // designates a subobject of the enclosing braced aggregate. The given
+    // number of designators of the first item have already been applied.
+    #[allow(clippy::too_many_arguments)]
+    fn initialize_aggregate(
+        &mut self,
+        ty: &Type,
+        items: &[(Vec<Designator>, AST)],
+        position: &mut usize,
+        offset: u64,
+        braced: bool,
+        applied: usize,
+        initializers: &mut Vec<Initializer>,
+    ) -> Result<Type, Error> {
+        let mut next = 0;
+        let mut length = 0;
+        let mut skip = applied;
+
+        while *position < items.len() {
+            let designators = &items[*position].0[skip..];
+            let consumed = skip;
+            skip = 0;
+
+            if designators.is_empty() {
+                let (sub_type, sub_offset) = match subobject(ty, next) {
+                    Some(subobject) => subobject,
+                    None if braced => {
+                        let message = format!(
+                            "excess elements in {} initializer",
+                            if ty.is_array() { "array" } else { "struct" }
+                        );
+                        return Err(Error::at(message, items[*position].1.span));
+                    }
+                    None => break,
+                };
+                self.initialize_subobject(
+                    &sub_type,
+                    items,
+                    position,
+                    offset + sub_offset,
+                    consumed,
+                    initializers,
+                )?;
+            } else {
+                if !braced && consumed == 0 {
+                    break;
+                }
+
+                // The rest of a chain of designators applies to the
+                // designated subobject, as if its braces were left out, and
+                // the next item without designators follows on from it.
+                let (index, sub_type, sub_offset) = self
+                    .designate(ty, &designators[0])
+                    .map_err(|error| error.or_at(items[*position].1.span))?;
+                next = index;
+                if designators.len() > 1 {
+                    self.initialize_aggregate(
+                        &sub_type,
+                        items,
+                        position,
+                        offset + sub_offset,
+                        false,
+                        consumed + 1,
+                        initializers,
+                    )?;
+                } else {
+                    self.initialize_subobject(
+                        &sub_type,
+                        items,
+                        position,
+                        offset + sub_offset,
+                        consumed + 1,
+                        initializers,
+                    )?;
+                }
+            }
+
+            next += 1;
+            length = length.max(next);
+            if !braced && subobject(ty, next).is_none() {
+                break;
+            }
+        }
+
+        match *ty.unqualified() {
+            Type::Array(_, None) if length == 0 => {
+                Err("zero-length arrays are not supported".to_owned().into())
+            }
+            Type::Array(ref elem, None) => Ok(Type::Array(elem.clone(), Some(length))),
+            _ => Ok(ty.clone()),
+        }
+    }
+
+    // Initialize a subobject from the item at the given position. Unless the
+    // item is in braces, or initializes the whole subobject at once, the
+    // subobject is an aggregate whose braces were left out, which takes its
+    // elements from the following items.
+    fn initialize_subobject(
+        &mut self,
+        ty: &Type,
+        items: &[(Vec<Designator>, AST)],
+        position: &mut usize,
+        offset: u64,
+        applied: usize,
+        initializers: &mut Vec<Initializer>,
+    ) -> Result<(), Error> {
+        let aggregate = ty.is_array() || ty.is_struct();
+        let value = &items[*position].1;
+        let whole = match value.kind {
+            ASTKind::InitializerList(_) => true,
+            ASTKind::StringLiteral(_) => is_char_array(ty),
+            _ => !aggregate || self.analyze_value(value)?.ty == *ty.unqualified(),
+        };
+
+        if aggregate && !whole {
+            self.initialize_aggregate(ty, items, position, offset, false, applied, initializers)?;
+        } else {
+            *position += 1;
+            self.flatten_initializer(ty, value, offset, initializers)?;
+        }
+
+        Ok(())
+    }
+
+    // The index, type and offset of the subobject of an aggregate that a
+    // designator picks.
+    fn designate(&mut self, ty: &Type, designator: &Designator) -> Result<(u64, Type, u64), Error> {
+        match *designator {
+            Designator::Index(ref index) => {
+                if !ty.is_array() {
+                    return Err(format!(
+                        "array designator cannot initialize non-array type '{}'",
+                        ty
+                    )
+                    .into());
+                }
+
+                let span = index.span;
+                let index = self.evaluate_integer_constant(index).map_err(|error| {
+                    error.into_error("expression is not an integer constant expression", span)
+                })?;
+                if index.value < 0 && index.ty.is_signed() {
+                    let message = format!("array designator value '{}' is negative", index.value);
+                    return Err(Error::at(message, span));
+                }
+                let value = index.value;
+
+                // An array of unknown length is as long as its largest index.
+                if let Type::Array(ref elem, None) = *ty.unqualified() {
+                    let length = (value as u64).saturating_add(1);
+                    if Type::Array(elem.clone(), Some(length)).is_too_large() {
+                        let message = format!("array is too large ({} elements)", length);
+                        return Err(Error::at(message, span));
+                    }
+                }
+
+                match subobject(ty, value as u64) {
+                    Some((elem, offset)) => Ok((value as u64, elem, offset)),
+                    None => {
+                        let message = format!(
+                            "array designator index ({}) exceeds array bounds ({})",
+                            value,
+                            array_length(ty).unwrap_or(0)
+                        );
+                        Err(Error::at(message, span))
+                    }
+                }
+            }
+            Designator::Member(ref name) => {
+                let members = match *ty.unqualified() {
+                    Type::Struct(ref struct_type) => struct_type.members().unwrap_or_default(),
+                    _ => {
+                        return Err(format!(
+                            "field designator cannot initialize a non-struct, non-union type '{}'",
+                            ty
+                        )
+                        .into());
+                    }
+                };
+
+                let index = members
+                    .iter()
+                    .position(|member| member.name == *name)
+                    .ok_or_else(|| {
+                        Error::from(format!(
+                            "field designator '{}' does not refer to any field in type '{}'",
+                            name, ty
+                        ))
+                    })?;
+                let (member_type, offset) = subobject(ty, index as u64).unwrap();
+                Ok((index as u64, member_type, offset))
+            }
+        }
+    }
+
+    // Evaluate an integer constant expression, where the operand of a sizeof
+    // expression has the type given by the declarations in scope.
+    fn evaluate_integer_constant(&mut self, expr: &AST) -> Result<Constant, constant::Error> {
+        let mut type_of = |operand: &AST| {
+            self.analyze_expr(operand)
+                .map(|operand| operand.ty)
+                .map_err(constant::Error::Invalid)
+        };
+        constant::evaluate(expr, &mut type_of)
+    }
+
+    // Analyze an expression whose value is used.
+    fn analyze_value(&mut self, expr: &AST) -> Result<Expr, Error> {
+        let span = expr.span;
+        let expr = self.analyze_expr(expr)?;
+        rvalue(expr).map_err(|error| Error::at(error, span))
+    }
+
+    // Convert a value as it's assigned, warning if it's an integer that
+    // doesn't fit in the narrower integer type, a pointer to an incompatible
+    // or less qualified type, or if it converts between an integer and a
+    // pointer without a cast. Only a null pointer constant converts to a
+    // pointer silently.
+    fn convert_implicitly(&mut self, expr: Expr, ty: &Type, assignment: Assignment) -> Expr {
+        let ty = ty.unqualified();
+        let description = || assignment.describe(ty, &expr.ty);
+        let discards =
+            |to: &Type, from: &Type| to.qualifiers().union(from.qualifiers()) != to.qualifiers();
+        let warning = match (ty.pointee(), expr.ty.pointee()) {
+            (Some(to), Some(from)) if !is_compatible_pointee(to, from) => Some((
+                Warning::IncompatiblePointerTypes,
+                format!("incompatible pointer types {}", description()),
+            )),
+            (Some(to), Some(from)) if discards(to, from) => Some((
+                Warning::IncompatiblePointerTypesDiscardsQualifiers,
+                format!("{} discards qualifiers", description()),
+            )),
+            (Some(_), None) if expr.ty.is_integer() && evaluate_constant(&expr) != Some(0) => {
+                Some((
+                    Warning::IntConversion,
+                    format!(
+                        "incompatible integer to pointer conversion {}",
+                        description()
+                    ),
+                ))
+            }
+            (None, Some(_)) if ty.is_integer() && *ty != Type::Bool => Some((
+                Warning::IntConversion,
+                format!(
+                    "incompatible pointer to integer conversion {}",
+                    description()
+                ),
+            )),
+            _ => None,
+        };
+        if let Some((warning, message)) = warning {
+            self.diagnostics.warn(warning, expr.span, message);
+        }
+
+        let narrowing = expr.ty.is_integer()
+            && ty.is_integer()
+            && *ty != Type::Bool
+            && ty.size() < expr.ty.size();
+
+        if narrowing {
+            match evaluate_constant(&expr) {
+                Some(value) => {
+                    let (from, to) = (
+                        constant::format_value(value, &expr.ty),
+                        constant::format_value(ty.wrap(value), ty),
+                    );
+                    if from != to {
+                        self.diagnostics.warn(
+                            Warning::ImplicitIntConversion,
+                            expr.span,
+                            format!(
+                                "implicit conversion from '{}' to '{}' changes value from {} to {}",
+                                expr.ty, ty, from, to
+                            ),
+                        );
+                    }
+                }
+                None => self.diagnostics.warn(
+                    Warning::ImplicitIntConversion,
+                    expr.span,
+                    format!(
+                        "implicit conversion loses integer precision: '{}' to '{}'",
+                        expr.ty, ty
+                    ),
+                ),
+            }
+        }
+
+        convert(expr, ty)
+    }
+
+    // Analyze an expression, leaving an lvalue as the object or function
+    // that it designates. An error in it that isn't found anywhere more
+    // specific is reported at the expression.
+    fn analyze_expr(&mut self, expr: &AST) -> Result<Expr, Error> {
+        self.analyze_expr_kind(expr)
+            .map_err(|error| error.or_at(expr.span))
+    }
+
+    fn analyze_expr_kind(&mut self, expr: &AST) -> Result<Expr, Error> {
+        let span = expr.span;
+        match expr.kind {
+            ASTKind::IntConstant(n, suffix) => {
+                // A constant has the first type that can represent it out
+                // of those that its suffix and base allow.
+                let ty = constant::integer_constant_type(n, suffix);
+                Ok(Expr::new(ExprKind::IntConstant(n as i64), ty, span))
+            }
+            ASTKind::FloatConstant(n) => Ok(Expr::new(
+                ExprKind::FloatConstant(n as f64),
+                Type::Float,
+                span,
+            )),
+            ASTKind::DoubleConstant(n) => {
+                Ok(Expr::new(ExprKind::FloatConstant(n), Type::Double, span))
+            }
+            ASTKind::StringLiteral(ref bytes) => Ok(string_literal(bytes, span)),
+            ASTKind::Variable(ref name) => self.analyze_identifier(name, span),
+            ASTKind::Dereference(ref pointer) => {
+                let pointer = self.analyze_value(pointer)?;
+                let ty = match pointer.ty.pointee() {
+                    Some(pointee) => pointee.clone(),
+                    None => {
+                        return Err(format!(
+                            "indirection requires pointer operand ('{}' invalid)",
+                            pointer.ty
+                        )
+                        .into());
+                    }
+                };
+                Ok(Expr::new(
+                    ExprKind::Dereference(Box::new(pointer)),
+                    ty,
+                    span,
+                ))
+            }
+            ASTKind::AddressOf(ref operand) => {
+                let operand = self.analyze_expr(operand)?;
+                if !operand.is_lvalue() {
+                    return Err(format!(
+                        "cannot take the address of an rvalue of type '{}'",
+                        operand.ty
+                    )
+                    .into());
+                }
+
+                let ty = Type::Pointer(Box::new(operand.ty.clone()));
+                Ok(Expr::new(ExprKind::AddressOf(Box::new(operand)), ty, span))
+            }
+            ASTKind::Member(ref object, ref name) => {
+                // A member of a qualified structure has the same qualifiers.
+                let object = self.analyze_expr(object)?;
+                let member = object.ty.member(name)?;
+                let ty = member.ty.qualified(object.ty.qualifiers());
+                Ok(Expr::new(
+                    ExprKind::Member(Box::new(object), member.offset),
+                    ty,
+                    span,
+                ))
+            }
+            ASTKind::Cast(ref ty, ref operand) => {
+                let operand = self.analyze_value(operand)?;
+                check_cast(ty, &operand.ty)?;
+                let ty = ty.unqualified().clone();
+                Ok(Expr::new(ExprKind::Conversion(Box::new(operand)), ty, span))
+            }
+            // The operand of sizeof is never evaluated.
+            ASTKind::SizeOfExpr(ref operand) => {
+                let operand = self.analyze_expr(operand)?;
+                Ok(type_query("sizeof", &operand.ty, operand.ty.size(), span)?)
+            }
+            ASTKind::SizeOfType(ref ty) => Ok(type_query("sizeof", ty, ty.size(), span)?),
+            ASTKind::AlignOfType(ref ty) => Ok(type_query("_Alignof", ty, ty.align(), span)?),
+            ASTKind::OffsetOf(ref ty, ref member) => {
+                let offset = ty.member(member)?.offset as i64;
+                Ok(Expr::new(
+                    ExprKind::IntConstant(offset),
+                    Type::UnsignedLong,
+                    span,
+                ))
+            }
+            ASTKind::UnaryOp(operator, ref operand) => {
+                let operand = self.analyze_value(operand)?;
+                let promoted_type = operand.ty.promote();
+
+                // Only ! accepts pointers; - needs an arithmetic operand and ~
+                // an integer one.
+                let valid = match operator {
+                    UnaryOperator::Bang => operand.ty.is_scalar(),
+                    UnaryOperator::Minus => operand.ty.is_integer() || operand.ty.is_floating(),
+                    UnaryOperator::Tilde => operand.ty.is_integer(),
+                };
+                if !valid {
+                    return Err(format!(
+                        "invalid argument type '{}' to unary expression",
+                        operand.ty
+                    )
+                    .into());
+                }
+
+                let ty = if operator == UnaryOperator::Bang {
+                    Type::Int
+                } else {
+                    promoted_type.clone()
+                };
+                let operand = convert(operand, &promoted_type);
+                Ok(Expr::new(
+                    ExprKind::UnaryOp(operator, Box::new(operand)),
+                    ty,
+                    span,
+                ))
+            }
+            ASTKind::BinaryOp(operator, ref expr1, ref expr2) => {
+                let expr1 = self.analyze_value(expr1)?;
+                let expr2 = self.analyze_value(expr2)?;
+                Ok(binary_op(operator, expr1, expr2)?)
+            }
+            ASTKind::Assign(ref lhs, ref rhs) => self.analyze_assignment(lhs, rhs, span),
+            ASTKind::Call(ref callee, ref args) => self.analyze_call(callee, args, span),
+            ASTKind::VaStart(ref ap, ref last) => {
+                let ap = self.analyze_va_list(ap, "va_start")?;
+                let last_param = match self.last_param {
+                    Some(ref last_param) => last_param,
+                    None => {
+                        let message = "'va_start' used in function with fixed args".to_owned();
+                        return Err(Error::at(message, span));
+                    }
+                };
+
+                match last.kind {
+                    ASTKind::Variable(ref name) if name == last_param => {}
+                    _ => {
+                        let message =
+                            "second argument to 'va_start' is not the last named parameter";
+                        return Err(Error::at(message.to_owned(), last.span));
+                    }
+                }
+                Ok(Expr::new(ExprKind::VaStart(Box::new(ap)), Type::Void, span))
+            }
+            ASTKind::VaArg(ref ap, ref ty) => {
+                let ap = self.analyze_va_list(ap, "va_arg")?;
+                if !ty.is_integer() && !ty.is_floating() && !ty.is_pointer() {
+                    return Err(format!("second argument to 'va_arg' is of type '{}'", ty).into());
+                }
+                Ok(Expr::new(
+                    ExprKind::VaArg(Box::new(ap)),
+                    ty.unqualified().clone(),
+                    span,
+                ))
+            }
+            ASTKind::VaEnd(ref ap) => {
+                let ap = self.analyze_va_list(ap, "va_end")?;
+                Ok(Expr::new(ExprKind::VaEnd(Box::new(ap)), Type::Void, span))
+            }
+            ASTKind::VaCopy(ref dest, ref src) => {
+                let dest = self.analyze_va_list(dest, "va_copy")?;
+                let src = self.analyze_va_list(src, "va_copy")?;
+                Ok(Expr::new(
+                    ExprKind::VaCopy(Box::new(dest), Box::new(src)),
+                    Type::Void,
+                    span,
+                ))
+            }
+            _ => Err("expected expression".to_owned().into()),
+        }
+    }
+
+    fn analyze_identifier(&mut self, name: &str, span: Span) -> Result<Expr, Error> {
+        if let Some(variable) = self.lookup_variable(name) {
+            let scope = self
+                .scopes
+                .iter_mut()
+                .rev()
+                .find(|scope| scope.contains_key(name));
+            if let Some(variable) = scope.and_then(|scope| scope.get_mut(name)) {
+                variable.used = true;
+            }
+            return Ok(Expr::new(
+                ExprKind::Object(variable.storage),
+                variable.ty,
+                span,
+            ));
+        }
+
+        match self.functions.get(name) {
+            Some(func_type) => Ok(Expr::new(
+                ExprKind::Function(name.into()),
+                Type::Function(Box::new(func_type.clone())),
+                span,
+            )),
+            None => Err(self.undeclared_identifier("use of undeclared identifier", name, span)),
+        }
+    }
+
+    fn analyze_assignment(&mut self, lhs: &AST, rhs: &AST, span: Span) -> Result<Expr, Error> {
+        let target = self.analyze_expr(lhs)?;
+        let ty = target.ty.clone();
+
+        let target_span = target.span;
+
+        if ty.is_array() || *ty.unqualified() == Type::VaList {
+            let message = format!("array type '{}' is not assignable", ty);
+            return Err(Error::at(message, target_span));
+        }
+
+        if ty.is_function() {
+            let message = format!("non-object type '{}' is not assignable", ty);
+            return Err(Error::at(message, target_span));
+        }
+
+        if !target.is_lvalue() {
+            return Err(Error::at(
+                "expression is not assignable".to_owned(),
+                target_span,
+            ));
+        }
+
+        if ty.is_const() {
+            let message = match lhs.kind {
+                ASTKind::Variable(ref name) => format!(
+                    "cannot assign to variable '{}' with const-qualified type '{}'",
+                    name, ty
+                ),
+                _ => "read-only variable is not assignable".to_owned(),
+            };
+            return Err(Error::at(message, target_span));
+        }
+
+        let value = self.analyze_value(rhs)?;
+        if !is_assignable(&ty, &value.ty) {
+            let message = format!(
+                "assigning to '{}' from incompatible type '{}'",
+                ty, value.ty
+            );
+            return Err(Error::at(message, value.span));
+        }
+
+        // The value of an assignment is the new value of its lhs.
+        let ty = ty.unqualified().clone();
+        let value = self.convert_implicitly(value, &ty, Assignment::Assign);
+        Ok(Expr::new(
+            ExprKind::Assign(Box::new(target), Box::new(value)),
+            ty,
+            span,
+        ))
+    }
+
+    fn analyze_call(&mut self, callee: &AST, args: &[AST], span: Span) -> Result<Expr, Error> {
+        if let ASTKind::Variable(ref name) = callee.kind {
+            if self.lookup_variable(name).is_none() && !self.functions.contains_key(&**name) {
+                let span = callee.span;
+                return Err(self.undeclared_identifier("call to undeclared function", name, span));
+            }
+        }
+
+        // The function designator has decayed to a pointer to the function.
+        let callee = self.analyze_value(callee)?;
+        let func_type = match callee.ty.pointee().map(Type::unqualified) {
+            Some(Type::Function(func_type)) => (**func_type).clone(),
+            _ => {
+                let message = format!(
+                    "called object type '{}' is not a function or function pointer",
+                    callee.ty
+                );
+                return Err(Error::at(message, callee.span));
+            }
+        };
+
+        if let Some(ref params) = func_type.params {
+            if args.len() < params.len() || (args.len() > params.len() && !func_type.variadic) {
+                return Err(format!(
+                    "too {} arguments to function call, expected {}, have {}",
+                    if args.len() < params.len() {
+                        "few"
+                    } else {
+                        "many"
+                    },
+                    params.len(),
+                    args.len()
+                )
+                .into());
+            }
+        }
+
+        // Arguments are converted to the types of the corresponding
+        // parameters. Those without one, the variable arguments and all the
+        // arguments to a function without a prototype, undergo the default
+        // argument promotions instead.
+        let mut converted = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let arg = self.analyze_value(arg)?;
+            if arg.ty.is_struct() {
+                let message = "passing or returning structures by value is not supported";
+                return Err(Error::at(message.to_owned(), arg.span));
+            }
+
+            let ty = match func_type.params.as_ref().and_then(|params| params.get(i)) {
+                Some(param_type) if !is_assignable(param_type, &arg.ty) => {
+                    let message = format!(
+                        "passing '{}' to parameter of incompatible type '{}'",
+                        arg.ty, param_type
+                    );
+                    return Err(Error::at(message, arg.span));
+                }
+                Some(param_type) => param_type.unqualified().clone(),
+                None => arg.ty.promote_argument(),
+            };
+            converted.push(self.convert_implicitly(arg, &ty, Assignment::Pass));
+        }
+
+        let ty = func_type.return_type.unqualified().clone();
+        Ok(Expr::new(
+            ExprKind::Call(Box::new(callee), converted),
+            ty,
+            span,
+        ))
+    }
+
+    fn analyze_va_list(&mut self, ap: &AST, builtin: &str) -> Result<Expr, Error> {
+        let ap = self.analyze_value(ap)?;
+        match ap.ty.pointee().map(Type::unqualified) {
+            Some(&Type::VaList) => Ok(ap),
+            _ => Err(format!(
+                "'{}' expects a va_list, but the argument has type '{}'",
+                builtin, ap.ty
+            )
+            .into()),
+        }
+    }
+}
+
+// The value of an expression. An lvalue designating an object is read from
+// it, unless the object is an array, which decays to a pointer to its first
+// element, just as a function decays to a pointer to it.
+fn rvalue(expr: Expr) -> Result<Expr, String> {
+    let span = expr.span;
+    match expr.kind {
+        ExprKind::Object(_)
+        | ExprKind::Function(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::Dereference(_)
+        | ExprKind::Member(..) => {}
+        _ => return Ok(expr),
+    }
+
+    if expr.ty.decays() {
+        let ty = expr.ty.decay();
+        return Ok(Expr::new(ExprKind::Decay(Box::new(expr)), ty, span));
+    }
+
+    // Dereferencing a pointer to void yields nothing to read.
+    if *expr.ty.unqualified() == Type::Void {
+        return Ok(expr);
+    }
+
+    if expr.ty.size().is_none() {
+        let ty = match expr.kind {
+            ExprKind::Dereference(ref pointer) => &pointer.ty,
+            _ => &expr.ty,
+        };
+        return Err(format!(
+            "incomplete type '{}' where a complete type is required",
+            ty
+        ));
+    }
+
+    let ty = expr.ty.unqualified().clone();
+    Ok(Expr::new(ExprKind::Load(Box::new(expr)), ty, span))
+}
+
+// Convert the value of an expression to the given type, if it doesn't have
+// that type already.
+fn convert(expr: Expr, ty: &Type) -> Expr {
+    let (ty, span) = (ty.unqualified(), expr.span);
+    if expr.ty == *ty {
+        return expr;
+    }
+    Expr::new(ExprKind::Conversion(Box::new(expr)), ty.clone(), span)
+}
+
+// Whether a pointer to one type converts to a pointer to another without a
+// warning: if they point to the same type, ignoring qualifiers, to
+// compatible functions, or one of them points to void. Pointers to integer
+// types that only differ in signedness are let through too, as clang only
+// warns about those with -Wpointer-sign.
+fn is_compatible_pointee(to: &Type, from: &Type) -> bool {
+    match (to.unqualified(), from.unqualified()) {
+        (Type::Function(to), Type::Function(from)) => to.is_compatible_with(from),
+        (to, from) if to == from || *to == Type::Void || *from == Type::Void => true,
+        (to, from) => {
+            to.is_integer()
+                && from.is_integer()
+                && *to != Type::Bool
+                && *from != Type::Bool
+                && to.size() == from.size()
+        }
+    }
+}
+
+// Whether a value of one type converts implicitly to another, as it does
+// when it's assigned. Structures only convert to the same structure, and
+// there is no conversion between pointers and floating types.
+fn is_assignable(to: &Type, from: &Type) -> bool {
+    let (to, from) = (to.unqualified(), from.unqualified());
+    if to == from {
+        return true;
+    }
+
+    let pointer_and_floating =
+        (to.is_pointer() && from.is_floating()) || (to.is_floating() && from.is_pointer());
+    to.is_scalar() && from.is_scalar() && !pointer_and_floating
+}
+
+fn check_cast(ty: &Type, operand_type: &Type) -> Result<(), String> {
+    if *ty.unqualified() == Type::Void {
+        return Ok(());
+    }
+
+    if ty.is_struct() {
+        return Err(format!(
+            "used type '{}' where arithmetic or pointer type is required",
+            ty
+        ));
+    }
+
+    if !operand_type.is_scalar() {
+        return Err(format!(
+            "operand of type '{}' where arithmetic or pointer type is required",
+            operand_type
+        ));
+    }
+
+    if ty.is_pointer() && operand_type.is_floating() {
+        return Err(format!(
+            "operand of type '{}' cannot be cast to a pointer type",
+            operand_type
+        ));
+    }
+
+    if ty.is_floating() && operand_type.is_pointer() {
+        return Err(format!("pointer cannot be cast to type '{}'", ty));
+    }
+
+    Ok(())
+}
+
+fn binary_op(operator: BinaryOperator, expr1: Expr, expr2: Expr) -> Result<Expr, String> {
+    let span = expr1.span.to(expr2.span);
+    let pointer_operand = expr1.ty.is_pointer() || expr2.ty.is_pointer();
+    let invalid_operands = format!(
+        "invalid operands to binary expression ('{}' and '{}')",
+        expr1.ty, expr2.ty
+    );
+
+    if !expr1.ty.is_scalar() || !expr2.ty.is_scalar() {
+        return Err(invalid_operands);
+    }
+
+    match operator {
+        // The operands of && and || are only compared with zero.
+        BinaryOperator::And | BinaryOperator::Or => Ok(Expr::new(
+            ExprKind::BinaryOp(operator, Box::new(expr1), Box::new(expr2)),
+            Type::Int,
+            span,
+        )),
+        BinaryOperator::Plus | BinaryOperator::Minus if pointer_operand => {
+            pointer_arithmetic(operator, expr1, expr2)
+        }
+        BinaryOperator::Times | BinaryOperator::Divide if pointer_operand => Err(invalid_operands),
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+            if !expr1.ty.is_integer() || !expr2.ty.is_integer() {
+                return Err(invalid_operands);
+            }
+
+            // The operands are promoted separately, and the result has the
+            // type of the left one.
+            let ty = expr1.ty.promote();
+            let count_type = expr2.ty.promote();
+            let expr1 = convert(expr1, &ty);
+            let expr2 = convert(expr2, &count_type);
+            Ok(Expr::new(
+                ExprKind::BinaryOp(operator, Box::new(expr1), Box::new(expr2)),
+                ty,
+                span,
+            ))
+        }
+        _ => {
+            let operand_type = expr1.ty.common(&expr2.ty);
+
+            // Comparisons yield an int.
+            let ty = match operator {
+                BinaryOperator::Plus
+                | BinaryOperator::Minus
+                | BinaryOperator::Times
+                | BinaryOperator::Divide => operand_type.clone(),
+                _ => Type::Int,
+            };
+
+            let expr1 = convert(expr1, &operand_type);
+            let expr2 = convert(expr2, &operand_type);
+            Ok(Expr::new(
+                ExprKind::BinaryOp(operator, Box::new(expr1), Box::new(expr2)),
+                ty,
+                span,
+            ))
+        }
+    }
+}
+
+// Addition or subtraction involving pointers, where integer operands count
+// elements rather than bytes.
+fn pointer_arithmetic(operator: BinaryOperator, expr1: Expr, expr2: Expr) -> Result<Expr, String> {
+    let span = expr1.span.to(expr2.span);
+    // Work out the type of the element that the pointer operands point to.
+    let pointee = match (expr1.ty.pointee(), expr2.ty.pointee()) {
+        (Some(pointee1), Some(pointee2)) if operator == BinaryOperator::Minus => {
+            if pointee1.unqualified() != pointee2.unqualified() {
+                return Err(format!(
+                    "'{}' and '{}' are not pointers to compatible types",
+                    expr1.ty, expr2.ty
+                ));
+            }
+            pointee1.clone()
+        }
+        (Some(pointee), None) if expr2.ty.is_integer() => pointee.clone(),
+        (None, Some(pointee)) if expr1.ty.is_integer() && operator == BinaryOperator::Plus => {
+            pointee.clone()
+        }
+        _ => {
+            return Err(format!(
+                "invalid operands to binary expression ('{}' and '{}')",
+                expr1.ty, expr2.ty
+            ));
+        }
+    };
+
+    if pointee.size().is_none() || pointee.is_function() {
+        return Err(format!(
+            "arithmetic on a pointer to an incomplete type '{}'",
+            pointee
+        ));
+    }
+
+    // The difference between two pointers is in elements.
+    if expr2.ty.is_pointer() && expr1.ty.is_pointer() {
+        return Ok(Expr::new(
+            ExprKind::PointerDifference(Box::new(expr1), Box::new(expr2)),
+            Type::Long,
+            span,
+        ));
+    }
+
+    let (pointer, index) = if expr1.ty.is_pointer() {
+        (expr1, expr2)
+    } else {
+        (expr2, expr1)
+    };
+    let ty = pointer.ty.clone();
+    let index = convert(index, &Type::Long);
+    Ok(Expr::new(
+        ExprKind::PointerArithmetic(operator, Box::new(pointer), Box::new(index)),
+        ty,
+        span,
+    ))
+}
+
+// The value of a sizeof or _Alignof expression.
+fn type_query(operator: &str, ty: &Type, value: Option<u64>, span: Span) -> Result<Expr, String> {
+    match value {
+        Some(value) => Ok(Expr::new(
+            ExprKind::IntConstant(value as i64),
+            Type::UnsignedLong,
+            span,
+        )),
+        None => Err(format!(
+            "invalid application of '{}' to incomplete type '{}'",
+            operator, ty
+        )),
+    }
+}
+
+fn string_literal(bytes: &[u8], span: Span) -> Expr {
+    let ty = Type::Array(Box::new(Type::Char), Some(bytes.len() as u64 + 1));
+    Expr::new(ExprKind::StringLiteral(bytes.into()), ty, span)
+}
+
+// Turn the parts of the initializer of an object with static storage
+// duration into the constants that they hold. They are folded first, so that
+// overflow wraps around with the same warnings as in a function.
+fn static_data(
+    initializers: Vec<Initializer>,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<StaticInitializer>, String> {
+    initializers
+        .into_iter()
+        .map(|mut initializer| {
+            fold::fold_expression(&mut initializer.value, diagnostics);
+            Ok(StaticInitializer {
+                offset: initializer.offset,
+                value: static_value(&initializer.ty, &initializer.value)?,
+                ty: initializer.ty,
+            })
+        })
+        .collect()
+}
+
+// The constant that is the initial value of part of an object with static
+// storage duration.
+fn static_value(ty: &Type, value: &Expr) -> Result<Data, String> {
+    let not_constant = || "initializer element is not a compile-time constant".to_owned();
+
+    if let ExprKind::StringLiteral(ref bytes) = value.kind {
+        return Ok(Data::String(bytes.clone()));
+    }
+
+    let value = match *ty.unqualified() {
+        Type::Float => {
+            let value = evaluate_floating_constant(value).ok_or_else(not_constant)?;
+            (value as f32).to_bits() as i64
+        }
+        Type::Double => {
+            let value = evaluate_floating_constant(value).ok_or_else(not_constant)?;
+            value.to_bits() as i64
+        }
+        Type::Pointer(_) => match static_address(value) {
+            Some(address) => return Ok(address),
+            None => evaluate_constant(value).ok_or_else(not_constant)?,
+        },
+        _ => evaluate_constant(value).ok_or_else(not_constant)?,
+    };
+
+    Ok(Data::Integer(value))
+}
+
+// Evaluate the integer value of an arithmetic constant expression in an
+// initializer, which unlike an integer constant expression may involve
+// floating values, following the same rules as constant::evaluate.
+fn evaluate_constant(expr: &Expr) -> Option<i64> {
+    let value = match expr.kind {
+        ExprKind::IntConstant(n) => n,
+        ExprKind::Conversion(ref operand) if operand.ty.is_floating() => {
+            let value = evaluate_floating_constant(operand)?;
+            constant::floating_to_integer(value, &expr.ty).ok()?
+        }
+        // An address with a label is never null.
+        ExprKind::Conversion(ref operand)
+            if expr.ty == Type::Bool && static_address(operand).is_some() =>
+        {
+            1
+        }
+        ExprKind::Conversion(ref operand) => evaluate_constant(operand)?,
+        ExprKind::UnaryOp(operator, ref operand) => {
+            let value = evaluate_constant(operand)?;
+            match operator {
+                UnaryOperator::Minus => {
+                    constant::arithmetic(BinaryOperator::Minus, &operand.ty, 0, value).ok()?
+                }
+                UnaryOperator::Tilde => !value,
+                UnaryOperator::Bang => (value == 0) as i64,
+            }
+        }
+        ExprKind::BinaryOp(operator, ref expr1, ref expr2)
+            if expr1.ty.is_floating() || expr2.ty.is_floating() =>
+        {
+            let lhs = evaluate_floating_constant(expr1)?;
+            let rhs = evaluate_floating_constant(expr2)?;
+            match operator {
+                BinaryOperator::And => (lhs != 0.0 && rhs != 0.0) as i64,
+                BinaryOperator::Or => (lhs != 0.0 || rhs != 0.0) as i64,
+                BinaryOperator::Equal => (lhs == rhs) as i64,
+                BinaryOperator::NotEqual => (lhs != rhs) as i64,
+                BinaryOperator::LessThan => (lhs < rhs) as i64,
+                BinaryOperator::LessThanOrEqual => (lhs <= rhs) as i64,
+                BinaryOperator::GreaterThan => (lhs > rhs) as i64,
+                BinaryOperator::GreaterThanOrEqual => (lhs >= rhs) as i64,
+                _ => return None,
+            }
+        }
+        ExprKind::BinaryOp(operator @ BinaryOperator::ShiftLeft, ref expr1, ref expr2)
+        | ExprKind::BinaryOp(operator @ BinaryOperator::ShiftRight, ref expr1, ref expr2) => {
+            let lhs = evaluate_constant(expr1)?;
+            let count = constant::widen(evaluate_constant(expr2)?, &expr2.ty);
+            constant::shift(operator, &expr1.ty, lhs, count).ok()?
+        }
+        ExprKind::BinaryOp(operator, ref expr1, ref expr2) => {
+            let lhs = evaluate_constant(expr1)?;
+            let rhs = evaluate_constant(expr2)?;
+            constant::arithmetic(operator, &expr1.ty, lhs, rhs).ok()?
+        }
+        _ => return None,
+    };
+
+    Some(expr.ty.wrap(value))
+}
+
+// Evaluate an arithmetic constant expression as a floating value, which is
+// rounded to float precision wherever the expression has type float.
+fn evaluate_floating_constant(expr: &Expr) -> Option<f64> {
+    if expr.ty.is_integer() {
+        let value = evaluate_constant(expr)?;
+        return Some(if expr.ty.is_signed() {
+            value as f64
+        } else {
+            value as u64 as f64
+        });
+    }
+
+    let value = match expr.kind {
+        ExprKind::FloatConstant(n) => n,
+        ExprKind::Conversion(ref operand) => evaluate_floating_constant(operand)?,
+        ExprKind::UnaryOp(UnaryOperator::Minus, ref operand) => {
+            -evaluate_floating_constant(operand)?
+        }
+        ExprKind::BinaryOp(operator, ref expr1, ref expr2) => {
+            let lhs = evaluate_floating_constant(expr1)?;
+            let rhs = evaluate_floating_constant(expr2)?;
+            match operator {
+                BinaryOperator::Plus => lhs + rhs,
+                BinaryOperator::Minus => lhs - rhs,
+                BinaryOperator::Times => lhs * rhs,
+                BinaryOperator::Divide => lhs / rhs,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    Some(if expr.ty == Type::Float {
+        value as f32 as f64
+    } else {
+        value
+    })
+}
+
+// The address that is the value of an expression, if it's a constant.
+fn static_address(expr: &Expr) -> Option<Data> {
+    match expr.kind {
+        ExprKind::AddressOf(ref object) | ExprKind::Decay(ref object) => object_address(object),
+        ExprKind::Conversion(ref operand) if operand.ty.is_pointer() && expr.ty.is_pointer() => {
+            static_address(operand)
+        }
+        ExprKind::PointerArithmetic(operator, ref pointer, ref index) => {
+            let size = expr.ty.pointee()?.size()? as i64;
+            let offset = evaluate_constant(index)?.wrapping_mul(size);
+            let offset = if operator == BinaryOperator::Plus {
+                offset
+            } else {
+                offset.wrapping_neg()
+            };
+            offset_address(static_address(pointer)?, offset)
+        }
+        _ => None,
+    }
+}
+
+// The address of what an lvalue designates, if it's a constant because the
+// lvalue designates a function, a string literal or part of an object with
+// static storage duration.
+fn object_address(expr: &Expr) -> Option<Data> {
+    match expr.kind {
+        ExprKind::Object(Storage::Static(ref name)) => Some(Data::Address(name.clone(), 0)),
+        ExprKind::Function(ref name) => Some(Data::Address(name.to_string(), 0)),
+        ExprKind::StringLiteral(ref bytes) => Some(Data::StringAddress(bytes.clone(), 0)),
+        ExprKind::Dereference(ref pointer) => static_address(pointer),
+        ExprKind::Member(ref object, offset) => {
+            offset_address(object_address(object)?, offset as i64)
+        }
+        _ => None,
+    }
+}
+
+fn offset_address(address: Data, offset: i64) -> Option<Data> {
+    match address {
+        Data::Address(name, base) => Some(Data::Address(name, base.wrapping_add(offset))),
+        Data::StringAddress(bytes, base) => {
+            Some(Data::StringAddress(bytes, base.wrapping_add(offset)))
+        }
+        _ => None,
+    }
+}
+
+// Collect the labels declared in a function body, which form a single
+// namespace no matter how deeply the labeled statements are nested.
+fn collect_labels(stmt: &AST, labels: &mut HashMap<String, Span>) -> Result<(), Error> {
+    match stmt.kind {
+        ASTKind::Block(ref statements) => {
+            for statement in statements {
+                collect_labels(statement, labels)?;
+            }
+            Ok(())
+        }
+        ASTKind::Label(ref name, ref labeled) => {
+            // A labeled statement starts with the label's name.
+            let span = Span {
+                end: stmt.span.start + name.len(),
+                ..stmt.span
+            };
+            if let Some(&previous) = labels.get(&**name) {
+                let message = format!("redefinition of label '{}'", name);
+                return Err(previous_definition(message, span, previous));
+            }
+            labels.insert(name.to_string(), span);
+            collect_labels(labeled, labels)
+        }
+        ASTKind::Switch(_, ref stmt) | ASTKind::Case(_, ref stmt) | ASTKind::Default(ref stmt) => {
+            collect_labels(stmt, labels)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn previous_declaration(message: String, span: Span, previous: Span) -> Error {
+    Error::at(message, span).with_note(previous, "previous declaration is here".to_owned())
+}
+
+fn previous_definition(message: String, span: Span, previous: Span) -> Error {
+    Error::at(message, span).with_note(previous, "previous definition is here".to_owned())
+}
+
+fn is_char_array(ty: &Type) -> bool {
+    match *ty.unqualified() {
+        Type::Array(ref elem, _) => matches!(*elem.unqualified(), Type::Char | Type::UnsignedChar),
+        _ => false,
+    }
+}
+
+fn array_length(ty: &Type) -> Option<u64> {
+    match *ty.unqualified() {
+        Type::Array(_, length) => length,
+        _ => None,
+    }
+}
+
+// The type and offset of the subobject of an aggregate with the given index,
+// if it has one. The elements of an array of unknown length go on forever.
+fn subobject(ty: &Type, index: u64) -> Option<(Type, u64)> {
+    match *ty.unqualified() {
+        Type::Array(ref elem, length) => {
+            if length.is_some_and(|length| index >= length) {
+                return None;
+            }
+            let elem = elem.clone().qualified(ty.qualifiers());
+            let size = elem.size().unwrap_or(0);
+            Some((elem, index * size))
+        }
+        Type::Struct(ref struct_type) => {
+            let member = struct_type.members()?.into_iter().nth(index as usize)?;
+            Some((member.ty.qualified(ty.qualifiers()), member.offset))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,419 @@
+use std::rc::Rc;
+
+use diagnostics::{Location, SourceRange};
+use lexer::Span;
+
+// Source text after translation phases 1 and 2: a UTF-8 byte order mark is
+// skipped, CRLF and lone CR line endings become LF, and each backslash
+// followed by a newline is removed to splice the two lines together. The
+// byte offset in the original source of each byte of the text is kept, so
+// that anything found in the text can be reported at its physical line.
+pub struct SourceMap {
+    text: String,
+    offsets: Vec<usize>,
+    // The offset in the original source at which each physical line starts.
+    line_starts: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let bytes = source.as_bytes();
+        let mut text = Vec::with_capacity(bytes.len());
+        let mut offsets = Vec::with_capacity(bytes.len() + 1);
+        let mut i = if bytes.starts_with(b"\xef\xbb\xbf") {
+            3
+        } else {
+            0
+        };
+        let mut line_starts = vec![i];
+        while i < bytes.len() {
+            let newline = newline_length(&bytes[i..]);
+            if newline > 0 {
+                line_starts.push(i + newline);
+                text.push(b'\n');
+                offsets.push(i);
+                i += newline;
+                continue;
+            }
+
+            let spliced = newline_length(&bytes[i + 1..]);
+            if bytes[i] == b'\\' && spliced > 0 {
+                line_starts.push(i + 1 + spliced);
+                i += 1 + spliced;
+                continue;
+            }
+
+            text.push(bytes[i]);
+            offsets.push(i);
+            i += 1;
+        }
+        offsets.push(bytes.len());
+
+        // Only ASCII bytes were removed or replaced, so the rest of the text
+        // is still valid UTF-8.
+        SourceMap {
+            text: String::from_utf8(text).unwrap(),
+            offsets,
+            line_starts,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // The offset in the original source of a byte in the text.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        self.offsets[offset.min(self.offsets.len() - 1)]
+    }
+
+    // The physical line, counting from 1, that a byte in the text came from.
+    pub fn line(&self, offset: usize) -> usize {
+        let original = self.original_offset(offset);
+        match self.line_starts.binary_search(&original) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+
+    // The column, counting bytes from 1, that a byte in the text is at on
+    // its physical line.
+    pub fn column(&self, offset: usize) -> usize {
+        let line_start = self.line_starts[self.line(offset) - 1];
+        self.original_offset(offset) - line_start + 1
+    }
+}
+
+// The text of a physical line of a source, counting from 1, without its
+// line ending.
+pub fn physical_line(source: &str, line: usize) -> Option<&str> {
+    let bytes = source.as_bytes();
+    let mut start = if bytes.starts_with(b"\xef\xbb\xbf") {
+        3
+    } else {
+        0
+    };
+    let mut current = 1;
+    let mut i = start;
+    while i < bytes.len() {
+        let newline = newline_length(&bytes[i..]);
+        if newline == 0 {
+            i += 1;
+            continue;
+        }
+
+        if current == line {
+            return Some(&source[start..i]);
+        }
+        current += 1;
+        i += newline;
+        start = i;
+    }
+
+    if current == line && start < bytes.len() {
+        Some(&source[start..])
+    } else {
+        None
+    }
+}
+
+// The length of the line ending at the start of some bytes, if any.
+fn newline_length(bytes: &[u8]) -> usize {
+    match bytes {
+        [b'\r', b'\n', ..] => 2,
+        [b'\r', ..] | [b'\n', ..] => 1,
+        _ => 0,
+    }
+}
+
+// Where the tokens of preprocessed text came from, so that anything found in
+// the text can be reported at the file, line and column it was written at.
+// The tokens that a macro expands to are placed at the macro's invocation.
+pub struct OriginMap {
+    files: Vec<OriginFile>,
+    origins: Vec<Origin>,
+}
+
+// A file as it's named and numbered in diagnostics. A #line directive starts
+// another one for the rest of the file.
+struct OriginFile {
+    name: String,
+    // The file as it's written, for showing the lines that are reported.
+    source: Rc<str>,
+    map: Rc<SourceMap>,
+    line_offset: i64,
+}
+
+// Where a token of the preprocessed text came from.
+pub struct Origin {
+    // The offset in the preprocessed text at which the token starts, and
+    // its length there.
+    pub offset: usize,
+    pub length: usize,
+    // The file, as returned by add_file, and the offset in its text after
+    // translation phases 1 and 2 that the token starts at.
+    pub file: usize,
+    pub source_offset: usize,
+    // Whether the token comes from a macro expansion, in which case it's
+    // placed at the invocation but isn't spelled there.
+    pub expanded: bool,
+}
+
+impl OriginMap {
+    pub fn new() -> Self {
+        OriginMap {
+            files: Vec::new(),
+            origins: Vec::new(),
+        }
+    }
+
+    // Start a file, or the rest of one after a #line directive, returning
+    // the number that its tokens refer to it by.
+    pub fn add_file(
+        &mut self,
+        name: String,
+        source: Rc<str>,
+        map: Rc<SourceMap>,
+        line_offset: i64,
+    ) -> usize {
+        self.files.push(OriginFile {
+            name,
+            source,
+            map,
+            line_offset,
+        });
+        self.files.len() - 1
+    }
+
+    // Record where the next token of the text came from. Tokens are added
+    // in the order that they're written to the text.
+    pub fn add_token(&mut self, origin: Origin) {
+        self.origins.push(origin);
+    }
+
+    // Where a span of the preprocessed text was written. A span between
+    // tokens, or after the last one, is placed just after the token before
+    // it, and so is an empty span. A span that ends on another line than it
+    // starts, or in another file, is only marked where it starts.
+    pub fn locate(&self, span: Span) -> Location {
+        let origin = match self.origin(span.start, true) {
+            Some(origin) => origin,
+            None => {
+                return Location {
+                    file: self
+                        .files
+                        .first()
+                        .map_or(String::new(), |file| file.name.clone()),
+                    line: span.line,
+                    columns: None,
+                    source_line: None,
+                }
+            }
+        };
+
+        let file = &self.files[origin.file];
+        let start = source_offset(origin, span.start);
+        let line = file.map.line(start);
+        let column = file.map.column(start);
+
+        let end_column = match self.origin(span.end, false) {
+            Some(last) if last.file == origin.file && span.end > span.start => {
+                let end = if last.expanded {
+                    last.source_offset + 1
+                } else {
+                    source_offset(last, span.end)
+                };
+                if end > start && file.map.line(end - 1) == line {
+                    file.map.column(end - 1) + 1
+                } else {
+                    column + 1
+                }
+            }
+            _ => column + 1,
+        };
+
+        Location {
+            file: file.name.clone(),
+            line: (line as i64 + file.line_offset).max(0) as usize,
+            columns: Some(column..end_column),
+            source_line: physical_line(&file.source, line).map(str::to_owned),
+        }
+    }
+
+    // Where a span of the preprocessed text was written, as a range of its
+    // file that can be edited. An empty span is placed like in `locate`.
+    // There's no such range for a span in a macro expansion, which isn't
+    // spelled where it's placed, or for one that isn't on a single line.
+    pub fn source_range(&self, span: Span) -> Option<SourceRange> {
+        let origin = self.origin(span.start, true)?;
+        let last = if span.end > span.start {
+            self.origin(span.end, false)?
+        } else {
+            origin
+        };
+        if origin.expanded || last.expanded || last.file != origin.file {
+            return None;
+        }
+
+        let file = &self.files[origin.file];
+        let start = source_offset(origin, span.start);
+        let end = source_offset(last, span.end).max(start);
+        let line = file.map.line(start);
+        if end > start && file.map.line(end - 1) != line {
+            return None;
+        }
+
+        let original_start = file.map.original_offset(start);
+        let original_end = if end > start {
+            file.map.original_offset(end - 1) + 1
+        } else {
+            original_start
+        };
+        let column = file.map.column(start);
+        Some(SourceRange {
+            file: file.name.clone(),
+            line: (line as i64 + file.line_offset).max(0) as usize,
+            columns: column..column + (original_end - original_start),
+            offsets: original_start..original_end,
+        })
+    }
+
+    // The last token that starts at or before an offset, or strictly before
+    // it if `inclusive` isn't set.
+    fn origin(&self, offset: usize, inclusive: bool) -> Option<&Origin> {
+        let count = self.origins.partition_point(|origin| {
+            origin.offset < offset || (inclusive && origin.offset == offset)
+        });
+        count.checked_sub(1).map(|index| &self.origins[index])
+    }
+}
+
+// The offset in the text of a token's file that an offset in the
+// preprocessed text corresponds to. Offsets past the token are taken to be
+// just after it, and those in a token expanded from a macro at the macro's
+// invocation.
+fn source_offset(origin: &Origin, offset: usize) -> usize {
+    if origin.expanded {
+        return origin.source_offset;
+    }
+    origin.source_offset + offset.saturating_sub(origin.offset).min(origin.length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use preprocessor::{Output, Preprocessor};
+    use std::path::Path;
+
+    fn preprocess(source: &str) -> Output {
+        Preprocessor::new(Vec::new())
+            .preprocess(Path::new("test.c"), source)
+            .expect("preprocessing failed")
+    }
+
+    // The span of the first occurrence of some text in preprocessed text.
+    fn span_of(text: &str, needle: &str) -> Span {
+        let start = text.find(needle).expect("text not found");
+        Span {
+            start,
+            end: start + needle.len(),
+            line: 0,
+            column: 0,
+        }
+    }
+
+    #[test]
+    fn crlf_and_cr_line_endings_become_lf() {
+        let map = SourceMap::new("a\r\nb\rc\n");
+        assert_eq!(map.text(), "a\nb\nc\n");
+        assert_eq!(map.original_offset(2), 3);
+        assert_eq!((map.line(2), map.column(2)), (2, 1));
+        assert_eq!(map.original_offset(4), 5);
+        assert_eq!((map.line(4), map.column(4)), (3, 1));
+    }
+
+    #[test]
+    fn backslash_newline_splices_lines() {
+        let map = SourceMap::new("ab\\\ncd\\\r\nef");
+        assert_eq!(map.text(), "abcdef");
+        // c and e are at the start of the second and third physical lines.
+        assert_eq!(map.original_offset(2), 4);
+        assert_eq!((map.line(2), map.column(2)), (2, 1));
+        assert_eq!(map.original_offset(4), 9);
+        assert_eq!((map.line(5), map.column(5)), (3, 2));
+    }
+
+    #[test]
+    fn backslash_without_newline_is_kept() {
+        let map = SourceMap::new("a\\ b\n");
+        assert_eq!(map.text(), "a\\ b\n");
+        assert_eq!(map.column(3), 4);
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped() {
+        let map = SourceMap::new("\u{feff}int x;\n");
+        assert_eq!(map.text(), "int x;\n");
+        assert_eq!(map.original_offset(0), 3);
+        assert_eq!((map.line(0), map.column(0)), (1, 1));
+    }
+
+    #[test]
+    fn end_of_text_maps_to_end_of_source() {
+        let map = SourceMap::new("a\r\n");
+        assert_eq!(map.original_offset(2), 3);
+        assert_eq!(map.original_offset(100), 3);
+    }
+
+    #[test]
+    fn physical_lines() {
+        let source = "one\r\ntwo\\\nthree\rfour";
+        assert_eq!(physical_line(source, 1), Some("one"));
+        assert_eq!(physical_line(source, 2), Some("two\\"));
+        assert_eq!(physical_line(source, 3), Some("three"));
+        assert_eq!(physical_line(source, 4), Some("four"));
+        assert_eq!(physical_line(source, 5), None);
+    }
+
+    #[test]
+    fn locate_after_crlf_and_splice() {
+        let output = preprocess("int a;\r\nint\\\r\n bcd;\r\n");
+        let location = output.origins.locate(span_of(&output.text, "bcd"));
+        assert_eq!(location.file, "test.c");
+        assert_eq!(location.line, 3);
+        assert_eq!(location.columns, Some(2..5));
+        assert_eq!(location.source_line.as_deref(), Some(" bcd;"));
+    }
+
+    #[test]
+    fn macro_expansion_is_placed_at_the_invocation() {
+        let output = preprocess("#define N (1 + 2)\nint x = N;\n");
+        let location = output.origins.locate(span_of(&output.text, "2"));
+        assert_eq!(location.line, 2);
+        assert_eq!(location.columns, Some(9..10));
+        assert!(output
+            .origins
+            .source_range(span_of(&output.text, "2"))
+            .is_none());
+    }
+
+    #[test]
+    fn source_range_offsets_are_in_the_original_source() {
+        let output = preprocess("int a;\r\nint b;\r\n");
+        let range = output
+            .origins
+            .source_range(span_of(&output.text, "b"))
+            .expect("no range");
+        assert_eq!(range.line, 2);
+        assert_eq!(range.columns, 5..6);
+        assert_eq!(range.offsets, 12..13);
+    }
+
+    #[test]
+    fn line_directive_renumbers_lines() {
+        let output = preprocess("#line 100 \"other.c\"\nint x;\n");
+        let location = output.origins.locate(span_of(&output.text, "x"));
+        assert_eq!(location.file, "other.c");
+        assert_eq!(location.line, 100);
+    }
+}
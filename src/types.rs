@@ -0,0 +1,540 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+// The size of the largest object, which is that of the user half of the
+// x86-64 address space.
+pub const MAX_OBJECT_SIZE: u64 = 1 << 47;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Void,
+    Bool,
+    Char,
+    UnsignedChar,
+    Short,
+    UnsignedShort,
+    Int,
+    UnsignedInt,
+    Long,
+    UnsignedLong,
+    Float,
+    Double,
+    Pointer(Box<Type>),
+    // An array of elements of the given type, whose length may not be known.
+    Array(Box<Type>, Option<u64>),
+    Function(Box<FunctionType>),
+    Struct(StructType),
+    // The System V va_list, an array of one structure describing where the
+    // next variadic argument can be found.
+    VaList,
+    // A type with qualifiers, which never wraps another qualified type.
+    Qualified(Box<Type>, Qualifiers),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Qualifiers {
+    pub is_const: bool,
+    pub is_volatile: bool,
+}
+
+impl Qualifiers {
+    pub fn is_empty(&self) -> bool {
+        !self.is_const && !self.is_volatile
+    }
+
+    pub fn union(&self, other: Qualifiers) -> Qualifiers {
+        Qualifiers {
+            is_const: self.is_const || other.is_const,
+            is_volatile: self.is_volatile || other.is_volatile,
+        }
+    }
+}
+
+impl fmt::Display for Qualifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.is_const, self.is_volatile) {
+            (true, true) => write!(f, "const volatile"),
+            (true, false) => write!(f, "const"),
+            (false, true) => write!(f, "volatile"),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+impl Type {
+    // The size in bytes of an object of this type, or None if the type is
+    // incomplete.
+    pub fn size(&self) -> Option<u64> {
+        match *self {
+            Type::Void => None,
+            Type::Bool | Type::Char | Type::UnsignedChar => Some(1),
+            Type::Short | Type::UnsignedShort => Some(2),
+            Type::Int | Type::UnsignedInt | Type::Float => Some(4),
+            Type::Long | Type::UnsignedLong | Type::Double | Type::Pointer(_) => Some(8),
+            Type::Array(ref elem, length) => elem.size()?.checked_mul(length?),
+            Type::Function(_) => None,
+            Type::Struct(ref struct_type) => struct_type.layout().map(|(size, _)| size),
+            Type::VaList => Some(24),
+            Type::Qualified(ref ty, _) => ty.size(),
+        }
+    }
+
+    // Whether an object of this type would be too large to address, which
+    // can only happen with an array.
+    pub fn is_too_large(&self) -> bool {
+        match *self.unqualified() {
+            Type::Array(ref elem, Some(length)) => match elem.size() {
+                Some(size) => size
+                    .checked_mul(length)
+                    .is_none_or(|size| size > MAX_OBJECT_SIZE),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Scalar types on x86-64 are aligned to their size.
+    pub fn align(&self) -> Option<u64> {
+        match *self.unqualified() {
+            Type::VaList => Some(8),
+            Type::Array(ref elem, _) => elem.align(),
+            Type::Struct(ref struct_type) => struct_type.layout().map(|(_, align)| align),
+            _ => self.size(),
+        }
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            *self.unqualified(),
+            Type::Char | Type::Short | Type::Int | Type::Long
+        )
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !self.is_floating()
+            && !matches!(
+                *self.unqualified(),
+                Type::Void
+                    | Type::Pointer(_)
+                    | Type::Array(..)
+                    | Type::Function(_)
+                    | Type::Struct(_)
+                    | Type::VaList
+            )
+    }
+
+    pub fn is_floating(&self) -> bool {
+        matches!(*self.unqualified(), Type::Float | Type::Double)
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(*self.unqualified(), Type::Pointer(_))
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.is_integer() || self.is_floating() || self.is_pointer()
+    }
+
+    pub fn is_struct(&self) -> bool {
+        matches!(*self.unqualified(), Type::Struct(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(*self.unqualified(), Type::Array(..))
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(*self.unqualified(), Type::Function(_))
+    }
+
+    // The type that a pointer of this type points to.
+    pub fn pointee(&self) -> Option<&Type> {
+        match *self.unqualified() {
+            Type::Pointer(ref pointee) => Some(pointee),
+            _ => None,
+        }
+    }
+
+    // The member of a structure of this type with the given name, as named
+    // by a member access expression or offsetof.
+    pub fn member(&self, name: &str) -> Result<Member, String> {
+        let struct_type = match *self.unqualified() {
+            Type::Struct(ref struct_type) => struct_type,
+            _ => {
+                return Err(format!(
+                    "member reference base type '{}' is not a structure",
+                    self
+                ));
+            }
+        };
+
+        if !struct_type.is_complete() {
+            return Err(format!("incomplete definition of type '{}'", self));
+        }
+
+        struct_type
+            .member(name)
+            .ok_or_else(|| format!("no member named '{}' in '{}'", name, self))
+    }
+
+    // Whether an expression of this type decays into a pointer when its
+    // value is used, instead of the object being read.
+    pub fn decays(&self) -> bool {
+        matches!(
+            *self.unqualified(),
+            Type::Array(..) | Type::Function(_) | Type::VaList
+        )
+    }
+
+    // The type of the value of an expression of this type: arrays decay to
+    // pointers to their first element, functions and va_lists to pointers to
+    // themselves, and qualifiers are dropped.
+    pub fn decay(&self) -> Type {
+        match *self.unqualified() {
+            Type::Array(ref elem, _) => Type::Pointer(elem.clone()),
+            Type::VaList => Type::Pointer(Box::new(self.clone())),
+            Type::Function(_) => Type::Pointer(Box::new(self.unqualified().clone())),
+            ref ty => ty.clone(),
+        }
+    }
+
+    pub fn is_const(&self) -> bool {
+        self.qualifiers().is_const
+    }
+
+    pub fn qualifiers(&self) -> Qualifiers {
+        match *self {
+            Type::Qualified(_, qualifiers) => qualifiers,
+            _ => Qualifiers::default(),
+        }
+    }
+
+    // The type without its top-level qualifiers, which is the type of the
+    // value stored in an object of this type.
+    pub fn unqualified(&self) -> &Type {
+        match *self {
+            Type::Qualified(ref ty, _) => ty,
+            ref ty => ty,
+        }
+    }
+
+    // This type with the given qualifiers added to its own.
+    pub fn qualified(self, qualifiers: Qualifiers) -> Type {
+        if qualifiers.is_empty() {
+            return self;
+        }
+
+        match self {
+            Type::Qualified(ty, own) => Type::Qualified(ty, own.union(qualifiers)),
+            ty => Type::Qualified(Box::new(ty), qualifiers),
+        }
+    }
+
+    // The type that an operand of this type is converted to before taking
+    // part in arithmetic, following the integer promotions.
+    pub fn promote(&self) -> Type {
+        match *self.unqualified() {
+            Type::Bool | Type::Char | Type::UnsignedChar | Type::Short | Type::UnsignedShort => {
+                Type::Int
+            }
+            // Pointers only take part in arithmetic through casts for now,
+            // so they behave like the unsigned integer of the same size.
+            Type::Pointer(_) => Type::UnsignedLong,
+            ref other => other.clone(),
+        }
+    }
+
+    // The type that an argument of this type is passed as when there's no
+    // parameter to convert it to, following the default argument promotions.
+    pub fn promote_argument(&self) -> Type {
+        match *self.unqualified() {
+            Type::Float => Type::Double,
+            ref ty => ty.promote(),
+        }
+    }
+
+    // The common type of a binary operation's operands, following the usual
+    // arithmetic conversions.
+    pub fn common(&self, other: &Type) -> Type {
+        let (lhs, rhs) = (self.promote(), other.promote());
+        if lhs == rhs {
+            return lhs;
+        }
+
+        // Any floating operand makes the operation floating, in the widest
+        // of the floating types involved.
+        if lhs == Type::Double || rhs == Type::Double {
+            return Type::Double;
+        }
+        if lhs == Type::Float || rhs == Type::Float {
+            return Type::Float;
+        }
+
+        let (lhs_size, rhs_size) = (lhs.size(), rhs.size());
+        if lhs_size == rhs_size {
+            // With operands of the same size, the unsigned type wins.
+            if lhs.is_signed() {
+                rhs
+            } else {
+                lhs
+            }
+        } else if lhs_size > rhs_size {
+            lhs
+        } else {
+            rhs
+        }
+    }
+
+    // Convert a value to this type, truncating it to the type's size and then
+    // sign- or zero-extending it back to 64 bits.
+    // A _Bool is 1 for any nonzero value.
+    pub fn wrap(&self, value: i64) -> i64 {
+        if *self.unqualified() == Type::Bool {
+            return (value != 0) as i64;
+        }
+
+        match (self.size(), self.is_signed()) {
+            (Some(1), true) => value as i8 as i64,
+            (Some(1), false) => value as u8 as i64,
+            (Some(2), true) => value as i16 as i64,
+            (Some(2), false) => value as u16 as i64,
+            (Some(4), true) => value as i32 as i64,
+            (Some(4), false) => value as u32 as i64,
+            _ => value,
+        }
+    }
+}
+
+impl Type {
+    // Spell out a declaration of the given declarator with this type, which
+    // for an abstract declarator gives the name of the type, e.g. `int (*)[4]`
+    // for a pointer to an array.
+    fn declaration(&self, declarator: &str) -> String {
+        let name = match *self {
+            Type::Void => "void",
+            Type::Bool => "_Bool",
+            Type::Char => "char",
+            Type::UnsignedChar => "unsigned char",
+            Type::Short => "short",
+            Type::UnsignedShort => "unsigned short",
+            Type::Int => "int",
+            Type::UnsignedInt => "unsigned int",
+            Type::Long => "long",
+            Type::UnsignedLong => "unsigned long",
+            Type::Float => "float",
+            Type::Double => "double",
+            Type::VaList => "va_list",
+            Type::Struct(ref struct_type) => {
+                let name = struct_type.to_string();
+                return if declarator.is_empty() {
+                    name
+                } else {
+                    format!("{} {}", name, declarator)
+                };
+            }
+            Type::Pointer(ref pointee) => {
+                return pointee.declaration(&pointer_declarator(pointee, "", declarator));
+            }
+            // Qualifiers of a pointer follow the asterisk, e.g. `int *const`.
+            Type::Qualified(ref ty, qualifiers) if ty.is_pointer() => {
+                let pointee = ty.pointee().unwrap();
+                let qualifiers = qualifiers.to_string();
+                return pointee.declaration(&pointer_declarator(pointee, &qualifiers, declarator));
+            }
+            Type::Qualified(ref ty, qualifiers) => {
+                return format!("{} {}", qualifiers, ty.declaration(declarator));
+            }
+            Type::Array(ref elem, length) => {
+                let length = length.map_or(String::new(), |length| length.to_string());
+                return elem.declaration(&format!("{}[{}]", declarator, length));
+            }
+            Type::Function(ref func_type) => {
+                let params = match func_type.params {
+                    Some(ref params) if params.is_empty() && !func_type.variadic => {
+                        "void".to_owned()
+                    }
+                    Some(ref params) => {
+                        let mut names: Vec<String> = params.iter().map(Type::to_string).collect();
+                        if func_type.variadic {
+                            names.push("...".to_owned());
+                        }
+                        names.join(", ")
+                    }
+                    None => String::new(),
+                };
+                return func_type
+                    .return_type
+                    .declaration(&format!("{}({})", declarator, params));
+            }
+        };
+
+        if declarator.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{} {}", name, declarator)
+        }
+    }
+}
+
+// The declarator of a pointer to the given type, with the given qualifiers,
+// wrapping the declarator of the pointer itself. Pointers to arrays and
+// functions need parentheses, as in `int (*)(int)`.
+fn pointer_declarator(pointee: &Type, qualifiers: &str, declarator: &str) -> String {
+    let separator = if !qualifiers.is_empty() && !declarator.is_empty() {
+        " "
+    } else {
+        ""
+    };
+
+    let pointer = format!("*{}{}{}", qualifiers, separator, declarator);
+    if pointee.is_array() || pointee.is_function() {
+        format!("({})", pointer)
+    } else {
+        pointer
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.declaration(""))
+    }
+}
+
+// A structure type, which is shared between all the places it's named by its
+// tag, so that it can be completed after it has been declared. Two structure
+// types are only the same if they come from the same declaration.
+#[derive(Clone)]
+pub struct StructType(Rc<RefCell<StructDefinition>>);
+
+struct StructDefinition {
+    tag: Option<Box<str>>,
+    // The members, or None if the structure hasn't been defined yet.
+    members: Option<Vec<Member>>,
+    size: u64,
+    align: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub name: Box<str>,
+    pub ty: Type,
+    pub offset: u64,
+}
+
+impl StructType {
+    // Declare a structure type, which is incomplete until it is defined.
+    pub fn new(tag: Option<Box<str>>) -> Self {
+        StructType(Rc::new(RefCell::new(StructDefinition {
+            tag,
+            members: None,
+            size: 0,
+            align: 1,
+        })))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.0.borrow().members.is_some()
+    }
+
+    // Define the structure's members, laying each of them out at the next
+    // offset suitably aligned for its type, and padding the structure to a
+    // multiple of its strictest member alignment.
+    pub fn define(&self, members: Vec<(Box<str>, Type)>) {
+        let mut offset: u64 = 0;
+        let mut align = 1;
+        let mut laid_out = Vec::new();
+
+        for (name, ty) in members {
+            let member_align = ty.align().unwrap_or(1);
+            offset = offset.next_multiple_of(member_align);
+            align = align.max(member_align);
+
+            let size = ty.size().unwrap_or(0);
+            laid_out.push(Member { name, ty, offset });
+            offset += size;
+        }
+
+        let mut definition = self.0.borrow_mut();
+        definition.size = offset.next_multiple_of(align);
+        definition.align = align;
+        definition.members = Some(laid_out);
+    }
+
+    pub fn members(&self) -> Option<Vec<Member>> {
+        self.0.borrow().members.clone()
+    }
+
+    pub fn member(&self, name: &str) -> Option<Member> {
+        self.0
+            .borrow()
+            .members
+            .as_ref()?
+            .iter()
+            .find(|member| &*member.name == name)
+            .cloned()
+    }
+
+    // The size and alignment of the structure, if it is complete.
+    fn layout(&self) -> Option<(u64, u64)> {
+        let definition = self.0.borrow();
+        definition
+            .members
+            .as_ref()
+            .map(|_| (definition.size, definition.align))
+    }
+}
+
+impl PartialEq for StructType {
+    fn eq(&self, other: &StructType) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// Members can refer back to the structure through pointers, so only the tag
+// is shown.
+impl fmt::Debug for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StructType({})", self)
+    }
+}
+
+impl fmt::Display for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.borrow().tag {
+            Some(ref tag) => write!(f, "struct {}", tag),
+            None => write!(f, "struct (anonymous)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub return_type: Type,
+    // The types of the parameters, or None for a function declared without a
+    // prototype, e.g. `int f()`.
+    pub params: Option<Vec<Type>>,
+    pub variadic: bool,
+}
+
+impl FunctionType {
+    // Whether two declarations of the same function agree. A declaration
+    // without a prototype agrees with any prototype with the same return
+    // type, and the qualifiers of the parameters themselves don't matter.
+    pub fn is_compatible_with(&self, other: &FunctionType) -> bool {
+        if self.return_type != other.return_type {
+            return false;
+        }
+
+        match (&self.params, &other.params) {
+            (Some(params), Some(other_params)) => {
+                params.len() == other_params.len()
+                    && params.iter().zip(other_params).all(|(param, other_param)| {
+                        param.unqualified() == other_param.unqualified()
+                    })
+                    && self.variadic == other.variadic
+            }
+            _ => true,
+        }
+    }
+}